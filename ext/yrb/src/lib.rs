@@ -1,10 +1,12 @@
 extern crate core;
 
 use crate::yarray::YArray;
-use crate::yawareness::{YAwareness, YAwarenessEvent};
+use crate::yawareness::{YAwareness, YAwarenessEvent, YAwarenessSubscription};
 use crate::ydiff::YDiff;
 use crate::ydoc::YDoc;
+use crate::yevent::YEvent;
 use crate::ymap::YMap;
+use crate::yroom::YRoom;
 use crate::ytext::YText;
 use crate::ysnapshot::YSnapshot;
 use crate::ytransaction::YTransaction;
@@ -13,7 +15,7 @@ use crate::yxml_fragment::YXmlFragment;
 use crate::yundo_manager::YUndoManager;
 use crate::yxml_text::YXmlText;
 
-use magnus::{function, method, Error, Module, Object, Ruby};
+use magnus::{function, method, Class, Error, Module, Object, Ruby};
 
 mod utils;
 mod yany;
@@ -22,14 +24,23 @@ mod yattrs;
 mod yawareness;
 mod ydiff;
 mod ydoc;
+mod yevent;
 mod ysnapshot;
 mod ymap;
+mod yroom;
+mod ysync;
 mod ytext;
+mod ytext_diff;
 mod ytransaction;
 mod yvalue;
 mod yundo_manager;
+mod yupdate;
 mod yxml_element;
 mod yxml_fragment;
+mod yxml_markup;
+mod yxml_prosemirror;
+mod yxml_query;
+mod yxml_reconcile;
 mod yxml_text;
 
 #[magnus::init]
@@ -122,6 +133,12 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
         method!(YDoc::ydoc_get_or_insert_xml_text, 1),
     )
     .expect("cannot define private method: ydoc_get_or_insert_xml_text");
+    ydoc.define_private_method("ydoc_compact", method!(YDoc::ydoc_compact, 2))
+        .expect("cannot define private method: ydoc_compact");
+    ydoc.define_private_method("ydoc_fork", method!(YDoc::ydoc_fork, 2))
+        .expect("cannot define private method: ydoc_fork");
+    ydoc.define_private_method("ydoc_stats", method!(YDoc::ydoc_stats, 1))
+        .expect("cannot define private method: ydoc_stats");
     ydoc.define_private_method("ydoc_transact", method!(YDoc::ydoc_transact, 0))
         .expect("cannot define private method: ydoc_transact");
     ydoc.define_private_method("ydoc_transact_with", method!(YDoc::ydoc_transact_with, 1))
@@ -171,6 +188,18 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YTransaction::ytransaction_apply_update_v2, 1),
         )
         .expect("cannot define private method: ytransaction_apply_update_v2");
+    ytransaction
+        .define_private_method(
+            "ytransaction_update_violations",
+            method!(YTransaction::ytransaction_update_violations, 2),
+        )
+        .expect("cannot define private method: ytransaction_update_violations");
+    ytransaction
+        .define_private_method(
+            "ytransaction_update_violations_v2",
+            method!(YTransaction::ytransaction_update_violations_v2, 2),
+        )
+        .expect("cannot define private method: ytransaction_update_violations_v2");
     ytransaction
         .define_private_method(
             "ytransaction_commit",
@@ -216,6 +245,24 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YTransaction::ytransaction_get_xml_text, 1),
         )
         .expect("cannot define private method: ytransaction_get_xml_text");
+    ytransaction
+        .define_private_method(
+            "ytransaction_is_pending",
+            method!(YTransaction::ytransaction_is_pending, 0),
+        )
+        .expect("cannot define private method: ytransaction_is_pending");
+    ytransaction
+        .define_private_method(
+            "ytransaction_missing_state_vector",
+            method!(YTransaction::ytransaction_missing_state_vector, 0),
+        )
+        .expect("cannot define private method: ytransaction_missing_state_vector");
+    ytransaction
+        .define_private_method(
+            "ytransaction_pending_update",
+            method!(YTransaction::ytransaction_pending_update, 0),
+        )
+        .expect("cannot define private method: ytransaction_pending_update");
     ytransaction
         .define_private_method(
             "ytransaction_state_vector",
@@ -246,6 +293,9 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
         .define_class("Text", ruby.class_object())
         .expect("cannot define class Y::Text");
 
+    ytext
+        .define_private_method("ytext_apply_delta", method!(YText::ytext_apply_delta, 2))
+        .expect("cannot define private method: ytext_apply_delta");
     ytext
         .define_private_method("ytext_diff", method!(YText::ytext_diff, 1))
         .expect("cannot define private method: ytext_diff");
@@ -282,6 +332,12 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
     ytext
         .define_private_method("ytext_remove_range", method!(YText::ytext_remove_range, 3))
         .expect("cannot define private method: ytext_remove_range");
+    ytext
+        .define_private_method("ytext_replace_with", method!(YText::ytext_replace_with, 2))
+        .expect("cannot define private method: ytext_replace_with");
+    ytext
+        .define_private_method("ytext_to_delta", method!(YText::ytext_to_delta, 1))
+        .expect("cannot define private method: ytext_to_delta");
     ytext
         .define_private_method("ytext_to_s", method!(YText::ytext_to_s, 1))
         .expect("cannot define private method: ytext_to_s");
@@ -299,6 +355,18 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YXmlElement::yxml_element_attributes, 1),
         )
         .expect("cannot define private method: yxml_element_attributes");
+    yxml_element
+        .define_private_method(
+            "yxml_element_descendants",
+            method!(YXmlElement::yxml_element_descendants, 1),
+        )
+        .expect("cannot define private method: yxml_element_descendants");
+    yxml_element
+        .define_private_method(
+            "yxml_element_find_all",
+            method!(YXmlElement::yxml_element_find_all, 3),
+        )
+        .expect("cannot define private method: yxml_element_find_all");
     yxml_element
         .define_private_method(
             "yxml_element_first_child",
@@ -389,6 +457,12 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YXmlElement::yxml_element_push_text_front, 2),
         )
         .expect("cannot define private method: yxml_element_push_text_front");
+    yxml_element
+        .define_private_method(
+            "yxml_element_query",
+            method!(YXmlElement::yxml_element_query, 2),
+        )
+        .expect("cannot define private method: yxml_element_query");
     yxml_element
         .define_private_method(
             "yxml_element_remove_attribute",
@@ -401,6 +475,12 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YXmlElement::yxml_element_remove_range, 3),
         )
         .expect("cannot define private method: yxml_element_remove_range");
+    yxml_element
+        .define_private_method(
+            "yxml_element_replace_children_with_xml",
+            method!(YXmlElement::yxml_element_replace_children_with_xml, 2),
+        )
+        .expect("cannot define private method: yxml_element_replace_children_with_xml");
     yxml_element
         .define_private_method(
             "yxml_element_siblings",
@@ -436,6 +516,18 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
         .define_class("XMLFragment", ruby.class_object())
         .expect("cannot define class: Y::XMLFragment");
 
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_descendants",
+            method!(YXmlFragment::yxml_fragment_descendants, 1),
+        )
+        .expect("cannot define private method: yxml_fragment_descendants");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_find_all",
+            method!(YXmlFragment::yxml_fragment_find_all, 3),
+        )
+        .expect("cannot define private method: yxml_fragment_find_all");
     yxml_fragment
         .define_private_method(
             "yxml_fragment_first_child",
@@ -454,12 +546,36 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YXmlFragment::yxml_fragment_insert, 3),
         )
         .expect("cannot define private method: yxml_fragment_insert");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_insert_xml",
+            method!(YXmlFragment::yxml_fragment_insert_xml, 3),
+        )
+        .expect("cannot define private method: yxml_fragment_insert_xml");
     yxml_fragment
         .define_private_method(
             "yxml_fragment_len",
             method!(YXmlFragment::yxml_fragment_len, 1),
         )
         .expect("cannot define private method: yxml_fragment_len");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_load_prosemirror_json",
+            method!(YXmlFragment::yxml_fragment_load_prosemirror_json, 2),
+        )
+        .expect("cannot define private method: yxml_fragment_load_prosemirror_json");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_observe",
+            method!(YXmlFragment::yxml_fragment_observe, 1),
+        )
+        .expect("cannot define private method: yxml_fragment_observe");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_observe_deep",
+            method!(YXmlFragment::yxml_fragment_observe_deep, 1),
+        )
+        .expect("cannot define private method: yxml_fragment_observe_deep");
     yxml_fragment
         .define_private_method(
             "yxml_fragment_parent",
@@ -490,6 +606,18 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YXmlFragment::yxml_fragment_push_text_front, 2),
         )
         .expect("cannot define private method: yxml_fragment_push_text_front");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_query",
+            method!(YXmlFragment::yxml_fragment_query, 2),
+        )
+        .expect("cannot define private method: yxml_fragment_query");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_reconcile",
+            method!(YXmlFragment::yxml_fragment_reconcile, 2),
+        )
+        .expect("cannot define private method: yxml_fragment_reconcile");
     yxml_fragment
         .define_private_method(
             "yxml_fragment_insert_text",
@@ -508,12 +636,30 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YXmlFragment::yxml_fragment_successors, 1),
         )
         .expect("cannot define private method: yxml_fragment_successors");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_to_prosemirror_json",
+            method!(YXmlFragment::yxml_fragment_to_prosemirror_json, 1),
+        )
+        .expect("cannot define private method: yxml_fragment_to_prosemirror_json");
     yxml_fragment
         .define_private_method(
             "yxml_fragment_to_s",
             method!(YXmlFragment::yxml_fragment_to_s, 1),
         )
         .expect("cannot define private method: yxml_fragment_to_s");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_unobserve",
+            method!(YXmlFragment::yxml_fragment_unobserve, 1),
+        )
+        .expect("cannot define private method: yxml_fragment_unobserve");
+    yxml_fragment
+        .define_private_method(
+            "yxml_fragment_unobserve_deep",
+            method!(YXmlFragment::yxml_fragment_unobserve_deep, 1),
+        )
+        .expect("cannot define private method: yxml_fragment_unobserve_deep");
 
     let yxml_text = module
         .define_class("XMLText", ruby.class_object())
@@ -613,7 +759,7 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
     yawareness
         .define_private_method(
             "yawareness_apply_update",
-            method!(YAwareness::yawareness_apply_update, 1),
+            method!(YAwareness::yawareness_apply_update, 2),
         )
         .expect("cannot define private method: yawareness_apply_update");
     yawareness
@@ -628,6 +774,12 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YAwareness::yawareness_clients, 0),
         )
         .expect("cannot define private method: yawareness_clients");
+    yawareness
+        .define_private_method(
+            "yawareness_clients_value",
+            method!(YAwareness::yawareness_clients_value, 0),
+        )
+        .expect("cannot define private method: yawareness_clients_value");
     yawareness
         .define_private_method(
             "yawareness_client_id",
@@ -640,6 +792,18 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YAwareness::yawareness_local_state, 0),
         )
         .expect("cannot define private method: yawareness_local_state");
+    yawareness
+        .define_private_method(
+            "yawareness_local_state_value",
+            method!(YAwareness::yawareness_local_state_value, 0),
+        )
+        .expect("cannot define private method: yawareness_local_state_value");
+    yawareness
+        .define_private_method(
+            "yawareness_on_change",
+            method!(YAwareness::yawareness_on_change, 1),
+        )
+        .expect("cannot define private method: yawareness_on_change");
     yawareness
         .define_private_method(
             "yawareness_on_update",
@@ -658,6 +822,12 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
             method!(YAwareness::yawareness_set_local_state, 1),
         )
         .expect("cannot define private method: yawareness_set_local_state");
+    yawareness
+        .define_private_method(
+            "yawareness_set_local_state_value",
+            method!(YAwareness::yawareness_set_local_state_value, 1),
+        )
+        .expect("cannot define private method: yawareness_set_local_state_value");
     yawareness
         .define_private_method(
             "yawareness_update",
@@ -683,6 +853,31 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
     yawareness_event
         .define_method("removed", method!(YAwarenessEvent::removed, 0))
         .expect("cannot define private method: removed");
+    yawareness_event
+        .define_method("origin", method!(YAwarenessEvent::origin, 0))
+        .expect("cannot define private method: origin");
+
+    let yawareness_subscription = yawareness
+        .define_class("Subscription", ruby.class_object())
+        .expect("cannot define class Y::Awareness::Subscription");
+    yawareness_subscription
+        .define_private_method(
+            "yawareness_subscription_subscribed",
+            method!(
+                YAwarenessSubscription::yawareness_subscription_subscribed,
+                0
+            ),
+        )
+        .expect("cannot define private method: yawareness_subscription_subscribed");
+    yawareness_subscription
+        .define_private_method(
+            "yawareness_subscription_unsubscribe",
+            method!(
+                YAwarenessSubscription::yawareness_subscription_unsubscribe,
+                0
+            ),
+        )
+        .expect("cannot define private method: yawareness_subscription_unsubscribe");
 
     let ydiff = module
         .define_class("Diff", ruby.class_object())
@@ -694,6 +889,122 @@ fn init(ruby: &Ruby) -> Result<(), Error> {
         .define_private_method("ydiff_attrs", method!(YDiff::ydiff_attrs, 0))
         .expect("cannot define private method: attrs");
 
+    let yevent = module
+        .define_class("Event", ruby.class_object())
+        .expect("cannot define class Y::Event");
+    yevent.undef_default_alloc_func();
+    yevent
+        .define_private_method("yevent_delta", method!(YEvent::yevent_delta, 0))
+        .expect("cannot define private method: yevent_delta");
+    yevent
+        .define_private_method("yevent_keys", method!(YEvent::yevent_keys, 0))
+        .expect("cannot define private method: yevent_keys");
+    yevent
+        .define_private_method("yevent_origin", method!(YEvent::yevent_origin, 0))
+        .expect("cannot define private method: yevent_origin");
+    yevent
+        .define_private_method("yevent_path", method!(YEvent::yevent_path, 0))
+        .expect("cannot define private method: yevent_path");
+    yevent
+        .define_private_method("yevent_target", method!(YEvent::yevent_target, 0))
+        .expect("cannot define private method: yevent_target");
+    yevent
+        .define_private_method("yevent_transaction", method!(YEvent::yevent_transaction, 0))
+        .expect("cannot define private method: yevent_transaction");
+    module
+        .define_class("ArrayEvent", yevent)
+        .expect("cannot define class Y::ArrayEvent");
+    module
+        .define_class("MapEvent", yevent)
+        .expect("cannot define class Y::MapEvent");
+    module
+        .define_class("TextEvent", yevent)
+        .expect("cannot define class Y::TextEvent");
+    module
+        .define_class("XMLEvent", yevent)
+        .expect("cannot define class Y::XMLEvent");
+
+    let yroom = module
+        .define_class("Room", ruby.class_object())
+        .expect("cannot define class Y::Room");
+    yroom
+        .define_singleton_method("new", function!(YRoom::yroom_new, -1))
+        .expect("cannot define singleton method: yroom_new");
+    yroom
+        .define_private_method("yroom_connect", method!(YRoom::yroom_connect, 2))
+        .expect("cannot define private method: yroom_connect");
+    yroom
+        .define_private_method("yroom_connections", method!(YRoom::yroom_connections, 0))
+        .expect("cannot define private method: yroom_connections");
+    yroom
+        .define_private_method("yroom_disconnect", method!(YRoom::yroom_disconnect, 1))
+        .expect("cannot define private method: yroom_disconnect");
+    yroom
+        .define_private_method("yroom_doc", method!(YRoom::yroom_doc, 0))
+        .expect("cannot define private method: yroom_doc");
+    yroom
+        .define_private_method("yroom_is_read_only", method!(YRoom::yroom_is_read_only, 1))
+        .expect("cannot define private method: yroom_is_read_only");
+    yroom
+        .define_private_method("yroom_flush", method!(YRoom::yroom_flush, 0))
+        .expect("cannot define private method: yroom_flush");
+    yroom
+        .define_private_method("yroom_receive", method!(YRoom::yroom_receive, 2))
+        .expect("cannot define private method: yroom_receive");
+    yroom
+        .define_private_method(
+            "yroom_set_read_only",
+            method!(YRoom::yroom_set_read_only, 2),
+        )
+        .expect("cannot define private method: yroom_set_read_only");
+
+    let ysync = module
+        .define_module("Sync")
+        .expect("cannot define module Y::Sync");
+    ysync
+        .define_singleton_method("ysync_decode", function!(ysync::ysync_decode, 1))
+        .expect("cannot define singleton method: ysync_decode");
+    ysync
+        .define_singleton_method("ysync_encode_auth", function!(ysync::ysync_encode_auth, 1))
+        .expect("cannot define singleton method: ysync_encode_auth");
+    ysync
+        .define_singleton_method(
+            "ysync_encode_awareness",
+            function!(ysync::ysync_encode_awareness, 1),
+        )
+        .expect("cannot define singleton method: ysync_encode_awareness");
+    ysync
+        .define_singleton_method(
+            "ysync_encode_awareness_query",
+            function!(ysync::ysync_encode_awareness_query, 0),
+        )
+        .expect("cannot define singleton method: ysync_encode_awareness_query");
+    ysync
+        .define_singleton_method(
+            "ysync_encode_sync_step1",
+            function!(ysync::ysync_encode_sync_step1, 1),
+        )
+        .expect("cannot define singleton method: ysync_encode_sync_step1");
+    ysync
+        .define_singleton_method(
+            "ysync_encode_sync_step2",
+            function!(ysync::ysync_encode_sync_step2, 1),
+        )
+        .expect("cannot define singleton method: ysync_encode_sync_step2");
+    ysync
+        .define_singleton_method(
+            "ysync_encode_update",
+            function!(ysync::ysync_encode_update, 1),
+        )
+        .expect("cannot define singleton method: ysync_encode_update");
+
+    let yupdate = module
+        .define_module("Update")
+        .expect("cannot define module Y::Update");
+
+    yupdate
+        .define_singleton_method("yupdate_decode", function!(yupdate::yupdate_decode, 2))
+        .expect("cannot define singleton method: yupdate_decode");
 
     let ysnapshot = module
         .define_class("Snapshot", ruby.class_object())
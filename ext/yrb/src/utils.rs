@@ -1,9 +1,15 @@
-use crate::yvalue::YValue;
+use crate::yvalue::{ConversionOptions, YValue};
 use magnus::r_hash::ForEach::Continue;
-use magnus::{Error, RHash, RString, Ruby, Symbol, Value};
+use magnus::value::{BoxValue, ReprValue};
+use magnus::{
+    Error, Exception, ExceptionClass, Float, Integer, IntoValue, Module, RArray, RHash, RModule,
+    RString, Ruby, Symbol, Value,
+};
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+use std::cell::RefCell;
 use std::sync::Arc;
 use yrs::types::{Attrs, Value as YrsValue};
-use yrs::{Any, Array, Map, TransactionMut};
+use yrs::{Array, Map, TransactionMut};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -15,54 +21,190 @@ pub(crate) fn indifferent_hash_key(key: Value) -> Option<String> {
         .or_else(|| Symbol::from_value(key).map(|v| v.name().unwrap().to_string()))
 }
 
-pub(crate) fn map_rhash_to_attrs(hash: RHash) -> Result<Attrs, Error> {
+/// Returns the error class with the given name defined in the `Y` module
+/// (see `lib/y/errors.rb`), or `RuntimeError` if it does not exist.
+pub(crate) fn error_class(ruby: &Ruby, name: &str) -> ExceptionClass {
+    ruby.class_object()
+        .const_get::<_, RModule>("Y")
+        .and_then(|module| module.const_get::<_, ExceptionClass>(name))
+        .unwrap_or_else(|_error| ruby.exception_runtime_error())
+}
+
+/// An error raised by an observer callback.
+enum DeferredError {
+    Exception(BoxValue<Exception>),
+    Other(Error),
+}
+
+thread_local! {
+    /// Observers are called while a transaction commits, where errors cannot
+    /// be returned. Every commit that returns to Ruby runs in a scope (see
+    /// [catch_observer_errors]) that keeps the first error of its observers.
+    /// Scopes nest, as an observer may commit a transaction of another
+    /// document.
+    static DEFERRED_ERRORS: RefCell<Vec<Option<DeferredError>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps an error raised by an observer callback for the innermost commit
+/// scope, only its first error is kept. Observers that run outside of a scope,
+/// e.g. when a transaction is committed by the garbage collector, have no
+/// caller to raise to, and their errors are dropped.
+pub(crate) fn defer_error(error: Error) {
+    DEFERRED_ERRORS.with(|scopes| {
+        if let Some(slot @ None) = scopes.borrow_mut().last_mut() {
+            *slot = Some(match error.value() {
+                Some(exception) => DeferredError::Exception(BoxValue::new(exception)),
+                None => DeferredError::Other(error),
+            });
+        }
+    });
+}
+
+/// Runs a commit in a new scope for the errors of observers, and raises the
+/// first error an observer raised during the commit.
+pub(crate) fn catch_observer_errors<T, F: FnOnce() -> T>(commit: F) -> Result<T, Error> {
+    DEFERRED_ERRORS.with(|scopes| scopes.borrow_mut().push(None));
+    let result = commit();
+    match DEFERRED_ERRORS.with(|scopes| scopes.borrow_mut().pop().flatten()) {
+        Some(DeferredError::Exception(exception)) => Err(Error::from(*exception)),
+        Some(DeferredError::Other(error)) => Err(error),
+        None => Ok(result),
+    }
+}
+
+pub(crate) fn map_rhash_to_attrs(hash: RHash, options: ConversionOptions) -> Result<Attrs, Error> {
     let mut a: Attrs = Default::default();
 
-    let result = hash.foreach(|key: Value, value: Value| {
+    hash.foreach(|key: Value, value: Value| {
         let k = Arc::from(key.to_string());
-        let v = Any::from(YValue::from(value));
+        let v = YValue::from(value).try_into_any(options)?;
 
         a.insert(k, v);
 
         Ok(Continue)
-    });
-
-    if result.is_err() {
-        let ruby = Ruby::get().unwrap();
-        return Err(Error::new(
-            ruby.exception_runtime_error(),
-            "could not map hash to attrs",
-        ));
-    }
+    })?;
 
     Ok(a)
 }
 
-pub(crate) fn convert_yvalue_to_ruby_value(value: YrsValue, tx: &TransactionMut) -> YValue {
+pub(crate) fn convert_yvalue_to_ruby_value(
+    value: YrsValue,
+    tx: &TransactionMut,
+) -> Result<YValue, Error> {
     let ruby = unsafe { Ruby::get_unchecked() };
-    match value {
-        YrsValue::Any(val) => YValue::from(val),
+    let value = match value {
+        YrsValue::Any(val) => YValue::try_from(val)?,
         YrsValue::YText(text) => YValue::from(text),
         YrsValue::YXmlElement(el) => YValue::from(el),
         YrsValue::YXmlText(text) => YValue::from(text),
         YrsValue::YArray(val) => {
             let arr = ruby.ary_new();
             for item in val.iter(tx) {
-                let val = convert_yvalue_to_ruby_value(item.clone(), tx);
-                let val = *val.0.borrow();
-                arr.push(val).expect("cannot push item event to array");
+                let val = convert_yvalue_to_ruby_value(item.clone(), tx)?;
+                arr.push(val.0.into_inner())?;
             }
             YValue::from(arr)
         }
         YrsValue::YMap(val) => {
             let hash = ruby.hash_new();
             for (key, value) in val.iter(tx) {
-                let val = convert_yvalue_to_ruby_value(value.clone(), tx);
-                let val = val.0.into_inner();
-                hash.aset(key, val).expect("cannot insert into hash");
+                let val = convert_yvalue_to_ruby_value(value.clone(), tx)?;
+                hash.aset(key, val.0.into_inner())?;
             }
             YValue::from(hash)
         }
         v => panic!("cannot map given yrs values to yvalue: {:?}", v),
+    };
+    Ok(value)
+}
+
+pub(crate) fn convert_json_to_ruby_value(value: JsonValue) -> Value {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    match value {
+        JsonValue::Null => ruby.qnil().as_value(),
+        JsonValue::Bool(v) => v.into_value_with(&ruby),
+        JsonValue::Number(v) => {
+            if let Some(i) = v.as_i64() {
+                i.into_value_with(&ruby)
+            } else if let Some(u) = v.as_u64() {
+                u.into_value_with(&ruby)
+            } else {
+                v.as_f64().unwrap_or(f64::NAN).into_value_with(&ruby)
+            }
+        }
+        JsonValue::String(v) => v.into_value_with(&ruby),
+        JsonValue::Array(v) => {
+            let arr = ruby.ary_new_capa(v.len());
+            for item in v {
+                arr.push(convert_json_to_ruby_value(item))
+                    .expect("cannot push item to array");
+            }
+            arr.as_value()
+        }
+        JsonValue::Object(v) => {
+            let hash = ruby.hash_new();
+            for (key, val) in v {
+                hash.aset(key, convert_json_to_ruby_value(val))
+                    .expect("cannot insert into hash");
+            }
+            hash.as_value()
+        }
+    }
+}
+
+pub(crate) fn convert_ruby_value_to_json(value: Value) -> Result<JsonValue, Error> {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    if value.is_nil() {
+        Ok(JsonValue::Null)
+    } else if value.is_kind_of(ruby.class_true_class()) {
+        Ok(JsonValue::Bool(true))
+    } else if value.is_kind_of(ruby.class_false_class()) {
+        Ok(JsonValue::Bool(false))
+    } else if let Some(i) = Integer::from_value(value) {
+        i.to_i64()
+            .map(|v| JsonValue::Number(JsonNumber::from(v)))
+            .map_err(|_e| {
+                Error::new(
+                    ruby.exception_range_error(),
+                    "integer is too big to be represented as JSON number",
+                )
+            })
+    } else if let Some(f) = Float::from_value(value) {
+        JsonNumber::from_f64(f.to_f64())
+            .map(JsonValue::Number)
+            .ok_or_else(|| {
+                Error::new(
+                    ruby.exception_range_error(),
+                    "NaN and Infinity cannot be represented as JSON number",
+                )
+            })
+    } else if let Some(s) = Symbol::from_value(value) {
+        Ok(JsonValue::String(s.name()?.to_string()))
+    } else if let Some(s) = RString::from_value(value) {
+        Ok(JsonValue::String(s.to_string()?))
+    } else if let Some(arr) = RArray::from_value(value) {
+        arr.into_iter()
+            .map(convert_ruby_value_to_json)
+            .collect::<Result<Vec<JsonValue>, Error>>()
+            .map(JsonValue::Array)
+    } else if let Some(hash) = RHash::from_value(value) {
+        let mut map = JsonMap::new();
+        hash.foreach(|key: Value, val: Value| {
+            let k = indifferent_hash_key(key).ok_or_else(|| {
+                Error::new(
+                    ruby.exception_type_error(),
+                    "invalid key type, make sure it is either of type Symbol or String",
+                )
+            })?;
+            map.insert(k, convert_ruby_value_to_json(val)?);
+            Ok(Continue)
+        })?;
+        Ok(JsonValue::Object(map))
+    } else {
+        let class_name = unsafe { value.classname() };
+        Err(Error::new(
+            ruby.exception_type_error(),
+            format!("cannot convert value of type {} to JSON", class_name),
+        ))
     }
 }
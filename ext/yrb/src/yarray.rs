@@ -1,11 +1,11 @@
-use crate::utils::convert_yvalue_to_ruby_value;
+use crate::utils::{convert_yvalue_to_ruby_value, defer_error};
+use crate::yevent::YEvent;
 use crate::ytransaction::YTransaction;
 use crate::yvalue::YValue;
 use magnus::block::Proc;
 use magnus::value::Qnil;
-use magnus::{Error, IntoValue, RArray, Ruby, Value};
+use magnus::{Error, RArray, Ruby, Value};
 use std::cell::RefCell;
-use yrs::types::Change;
 use yrs::{Any, Array, ArrayRef, Observable};
 
 #[magnus::wrap(class = "Y::Array")]
@@ -20,49 +20,61 @@ impl YArray {
         let tx = tx.as_ref().unwrap();
 
         let arr = self.0.borrow();
-        arr.iter(tx).for_each(|val| {
-            let yvalue = *convert_yvalue_to_ruby_value(val, tx).0.borrow();
+        for val in arr.iter(tx) {
+            let yvalue = convert_yvalue_to_ruby_value(val, tx)?.0.into_inner();
             let args = (yvalue,);
             let _ = block.call::<(Value,), Qnil>(args);
-        });
+        }
 
         Ok(())
     }
 
-    pub(crate) fn yarray_get(&self, transaction: &YTransaction, index: u32) -> Value {
+    pub(crate) fn yarray_get(
+        &self,
+        transaction: &YTransaction,
+        index: u32,
+    ) -> Result<Value, Error> {
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
 
         let arr = self.0.borrow();
         let v = arr.get(tx, index).unwrap();
-        *convert_yvalue_to_ruby_value(v, tx).0.borrow()
+        Ok(convert_yvalue_to_ruby_value(v, tx)?.0.into_inner())
     }
-    pub(crate) fn yarray_insert(&self, transaction: &YTransaction, index: u32, value: Value) {
+    pub(crate) fn yarray_insert(
+        &self,
+        transaction: &YTransaction,
+        index: u32,
+        value: Value,
+    ) -> Result<(), Error> {
         let yvalue = YValue::from(value);
-        let avalue = Any::from(yvalue);
+        let avalue = yvalue.try_into_any(transaction.conversion_options())?;
 
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         let arr = self.0.borrow_mut();
         arr.insert(tx, index, avalue);
+        Ok(())
     }
     pub(crate) fn yarray_insert_range(
         &self,
         transaction: &YTransaction,
         index: u32,
         values: RArray,
-    ) {
+    ) -> Result<(), Error> {
+        let options = transaction.conversion_options();
+        let add_values: Vec<Any> = values
+            .into_iter()
+            .map(|value| YValue::from(value).try_into_any(options))
+            .collect::<Result<Vec<Any>, Error>>()?;
+
         let arr = self.0.borrow_mut();
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        let add_values: Vec<Any> = values
-            .into_iter()
-            .map(|value| YValue::from(value).into())
-            .collect();
-
-        arr.insert_range(tx, index, add_values)
+        arr.insert_range(tx, index, add_values);
+        Ok(())
     }
     pub(crate) fn yarray_length(&self, transaction: &YTransaction) -> u32 {
         let arr = self.0.borrow();
@@ -72,86 +84,47 @@ impl YArray {
         arr.len(tx)
     }
     pub(crate) fn yarray_observe(&self, block: Proc) -> Result<u32, Error> {
-        let ruby = unsafe { Ruby::get_unchecked() };
-        let change_added = ruby.to_symbol("added").to_static();
-        let change_retain = ruby.to_symbol("retain").to_static();
-        let change_removed = ruby.to_symbol("removed").to_static();
-
-        // let mut error: Option<Error> = None;
-
-        let subscription_id =
-            self.0
-                .borrow_mut()
-                .observe(move |transaction, array_event| {
-                    let ruby = unsafe { Ruby::get_unchecked() };
-                    let delta = array_event.delta(transaction);
-                    // let mut changes = RArray::with_capacity(delta.len());
-                    let (changes, errors): (Vec<_>, Vec<_>) = delta
-                        .iter()
-                        .map(|change| {
-                            let payload = ruby.hash_new();
-                            let result =
-                                match change {
-                                    Change::Added(v) => {
-                                        let values = ruby.ary_new();
-                                        for val in v.iter() {
-                                            let value: Value = YValue::from(val.clone()).into();
-                                            values.push(value).expect("cannot push value to array");
-                                        }
-                                        payload.aset(change_added, values)
-                                    }
-                                    Change::Retain(position) => payload
-                                        .aset(change_retain, (*position).into_value_with(&ruby)),
-                                    Change::Removed(position) => payload
-                                        .aset(change_removed, (*position).into_value_with(&ruby)),
-                                };
-
-                            match result {
-                                Ok(()) => Ok(payload),
-                                Err(e) => Err(e),
-                            }
-                        })
-                        .partition(Result::is_ok);
-
-                    if errors.is_empty() {
-                        let args_changes = ruby.ary_new();
-                        for change in changes.iter() {
-                            let c = *change.as_ref().unwrap();
-                            args_changes
-                                .push(c)
-                                .expect("cannot push change event to args");
-                        }
-
-                        let args = (args_changes,);
-                        let _ = block.call::<(RArray,), Qnil>(args);
-                        // todo: make sure we respect the result and bubble up the
-                        //  error so that we can return as part of the Result
-                    }
-
-                    // todo: make sure we respect errors and let the method fail by
-                    //  by returning a Result containing an Error
-                })
-                .into();
+        let subscription_id = self
+            .0
+            .borrow_mut()
+            .observe(move |transaction, event| {
+                let result = YEvent::array(transaction, event)
+                    .and_then(|event| block.call::<(Value,), Value>((event,)));
+                if let Err(error) = result {
+                    defer_error(error);
+                }
+            })
+            .into();
 
         Ok(subscription_id)
     }
-    pub(crate) fn yarray_push_back(&self, transaction: &YTransaction, value: Value) {
+    pub(crate) fn yarray_push_back(
+        &self,
+        transaction: &YTransaction,
+        value: Value,
+    ) -> Result<(), Error> {
         let yvalue = YValue::from(value);
-        let avalue = Any::from(yvalue);
+        let avalue = yvalue.try_into_any(transaction.conversion_options())?;
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         self.0.borrow_mut().push_back(tx, avalue);
+        Ok(())
     }
-    pub(crate) fn yarray_push_front(&self, transaction: &YTransaction, value: Value) {
+    pub(crate) fn yarray_push_front(
+        &self,
+        transaction: &YTransaction,
+        value: Value,
+    ) -> Result<(), Error> {
         let yvalue = YValue::from(value);
-        let avalue = Any::from(yvalue);
+        let avalue = yvalue.try_into_any(transaction.conversion_options())?;
 
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         let arr = self.0.borrow_mut();
         arr.push_front(tx, avalue);
+        Ok(())
     }
     pub(crate) fn yarray_remove(&self, transaction: &YTransaction, index: u32) {
         let mut tx = transaction.transaction();
@@ -167,7 +140,7 @@ impl YArray {
         let arr = self.0.borrow_mut();
         arr.remove_range(tx, index, len)
     }
-    pub(crate) fn yarray_to_a(&self, transaction: &YTransaction) -> RArray {
+    pub(crate) fn yarray_to_a(&self, transaction: &YTransaction) -> Result<RArray, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
         let arr = self.0.borrow();
         let tx = transaction.transaction();
@@ -175,11 +148,10 @@ impl YArray {
 
         let r_arr = ruby.ary_new();
         for item in arr.iter(tx) {
-            let r_val = YValue::from(item);
-            let r_val = *r_val.0.borrow();
-            r_arr.push(r_val).expect("cannot push item event to array");
+            let r_val = YValue::try_from(item)?;
+            r_arr.push(r_val.0.into_inner())?;
         }
-        r_arr
+        Ok(r_arr)
     }
     pub(crate) fn yarray_unobserve(&self, subscription_id: u32) {
         self.0.borrow_mut().unobserve(subscription_id);
@@ -1,10 +1,8 @@
-use crate::yvalue::YValue;
-use magnus::r_hash::ForEach::Continue;
-use magnus::{RHash, Value};
+use crate::utils::map_rhash_to_attrs;
+use crate::yvalue::ConversionOptions;
+use magnus::{Error, RHash};
 use std::cell::RefCell;
-use std::sync::Arc;
 use yrs::types::Attrs;
-use yrs::Any;
 
 #[magnus::wrap(class = "Y::Attrs")]
 #[derive(Clone)]
@@ -19,21 +17,8 @@ impl From<Attrs> for YAttrs {
     }
 }
 
-impl From<RHash> for YAttrs {
-    fn from(value: RHash) -> Self {
-        let mut attrs = Attrs::new();
-
-        value
-            .foreach(|key: Value, value: Value| {
-                let k = key.to_string();
-                let yvalue = YValue::from(value);
-                let avalue = Any::from(yvalue);
-                attrs.insert(Arc::from(k), avalue);
-
-                Ok(Continue)
-            })
-            .expect("cannot iterate attributes hash");
-
-        YAttrs(RefCell::from(attrs))
+impl YAttrs {
+    pub(crate) fn from_hash(value: RHash, options: ConversionOptions) -> Result<Self, Error> {
+        map_rhash_to_attrs(value, options).map(|attrs| YAttrs(RefCell::from(attrs)))
     }
 }
@@ -1,19 +1,50 @@
-use magnus::{block::Proc, Error, Ruby, Value};
-use std::borrow::Borrow;
+use crate::utils::{convert_json_to_ruby_value, convert_ruby_value_to_json};
+use magnus::{block::Proc, Error, RHash, Ruby, Value};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::{Rc, Weak};
 use y_sync::awareness::{Awareness, AwarenessUpdate, Event, UpdateSubscription};
 use yrs::block::ClientID;
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
 use yrs::{Doc, OffsetKind, Options};
 
+/// Origin assigned to events caused by changes of the local state.
+const LOCAL_ORIGIN: &str = "local";
+
 #[magnus::wrap(class = "Y::Awareness")]
-pub(crate) struct YAwareness(pub(crate) RefCell<Awareness>);
+pub(crate) struct YAwareness {
+    awareness: RefCell<Awareness>,
+    observer: Rc<RefCell<AwarenessObserver>>,
+    listeners: Rc<RefCell<AwarenessListeners>>,
+    _subscription: UpdateSubscription,
+}
 
 /// SAFETY: This is safe because we only access this data when the GVL is held.
 unsafe impl Send for YAwareness {}
 
+/// Tracks the last known client states, so that a clock bump (`update`) can
+/// be told apart from an actual change of a state (`change`). Events are
+/// queued while the awareness instance is borrowed and emitted afterwards.
+struct AwarenessObserver {
+    origin: Option<String>,
+    states: HashMap<ClientID, String>,
+    pending: Vec<(Option<YAwarenessEvent>, YAwarenessEvent)>,
+}
+
+#[derive(Default)]
+struct AwarenessListeners {
+    next_id: u32,
+    on_change: Vec<(u32, Proc)>,
+    on_update: Vec<(u32, Proc)>,
+}
+
+#[derive(Clone, Copy)]
+enum AwarenessEventKind {
+    Change,
+    Update,
+}
+
 impl YAwareness {
     pub(crate) fn yawareness_new() -> Self {
         let mut options = Options {
@@ -26,64 +57,97 @@ impl YAwareness {
 
         let awareness = Awareness::new(doc);
 
-        Self(RefCell::new(awareness))
+        Self::from(awareness)
     }
 
-    pub(crate) fn yawareness_apply_update(&self, update: Vec<u8>) -> Result<(), Error> {
+    pub(crate) fn yawareness_apply_update(
+        &self,
+        update: Vec<u8>,
+        origin: Option<String>,
+    ) -> Result<(), Error> {
         let ruby = Ruby::get().unwrap();
-        AwarenessUpdate::decode_v1(update.as_slice())
-            .map_err(|_error| Error::new(ruby.exception_runtime_error(), "cannot decode update"))
-            .and_then(|value| {
-                self.0.borrow_mut().apply_update(value).map_err(|_error| {
-                    Error::new(
-                        ruby.exception_runtime_error(),
-                        "cannot apply awareness update",
-                    )
-                })
-            })
+        let update = AwarenessUpdate::decode_v1(update.as_slice())
+            .map_err(|_error| Error::new(ruby.exception_runtime_error(), "cannot decode update"))?;
+
+        self.observer.borrow_mut().origin = origin;
+        let result = self.awareness.borrow_mut().apply_update(update);
+        result.map_err(|_error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                "cannot apply awareness update",
+            )
+        })?;
+
+        self.emit()
     }
 
-    pub(crate) fn yawareness_clean_local_state(&self) {
-        self.0.borrow_mut().clean_local_state();
+    pub(crate) fn yawareness_clean_local_state(&self) -> Result<(), Error> {
+        self.observer.borrow_mut().origin = Some(LOCAL_ORIGIN.to_string());
+        self.awareness.borrow_mut().clean_local_state();
+        self.emit()
     }
 
     pub(crate) fn yawareness_client_id(&self) -> ClientID {
-        self.0.borrow().client_id()
+        self.awareness.borrow().client_id()
     }
 
     pub(crate) fn yawareness_clients(&self) -> HashMap<ClientID, String> {
-        self.0.borrow().clients().to_owned()
+        self.awareness.borrow().clients().to_owned()
+    }
+
+    pub(crate) fn yawareness_clients_value(&self) -> Result<RHash, Error> {
+        let ruby = Ruby::get().unwrap();
+        let hash = ruby.hash_new();
+        for (client_id, json) in self.awareness.borrow().clients().iter() {
+            let state = parse_state(json.as_str())?;
+            hash.aset(*client_id, state)?;
+        }
+        Ok(hash)
     }
 
     pub(crate) fn yawareness_local_state(&self) -> Option<String> {
-        self.0.borrow().local_state().map(|value| value.to_string())
+        self.awareness
+            .borrow()
+            .local_state()
+            .map(|value| value.to_string())
+    }
+
+    pub(crate) fn yawareness_local_state_value(&self) -> Result<Option<Value>, Error> {
+        self.awareness
+            .borrow()
+            .local_state()
+            .map(parse_state)
+            .transpose()
+    }
+
+    pub(crate) fn yawareness_on_change(&self, block: Proc) -> YAwarenessSubscription {
+        self.subscribe(AwarenessEventKind::Change, block)
     }
 
     pub(crate) fn yawareness_on_update(&self, block: Proc) -> YAwarenessSubscription {
-        let subscription = self.0.borrow_mut().on_update(move |_awareness, event| {
-            let awareness_event = YAwarenessEvent::from(event);
-            let args = (awareness_event,);
-            block
-                .call::<(YAwarenessEvent,), Value>(args)
-                .expect("cannot call block: on_update");
-        });
+        self.subscribe(AwarenessEventKind::Update, block)
+    }
 
-        // we need to make sure the event handler "survives" and is not being
-        // dropped after leaving this scope, so we pass it back to Ruby.
-        YAwarenessSubscription::from(subscription)
+    pub(crate) fn yawareness_remove_state(&self, client_id: ClientID) -> Result<(), Error> {
+        self.observer.borrow_mut().origin = Some(LOCAL_ORIGIN.to_string());
+        self.awareness.borrow_mut().remove_state(client_id);
+        self.emit()
     }
 
-    pub(crate) fn yawareness_remove_state(&self, client_id: ClientID) {
-        self.0.borrow_mut().remove_state(client_id)
+    pub(crate) fn yawareness_set_local_state(&self, json: String) -> Result<(), Error> {
+        self.observer.borrow_mut().origin = Some(LOCAL_ORIGIN.to_string());
+        self.awareness.borrow_mut().set_local_state(json);
+        self.emit()
     }
 
-    pub(crate) fn yawareness_set_local_state(&self, json: String) {
-        self.0.borrow_mut().set_local_state(json)
+    pub(crate) fn yawareness_set_local_state_value(&self, state: Value) -> Result<(), Error> {
+        let json = convert_ruby_value_to_json(state)?;
+        self.yawareness_set_local_state(json.to_string())
     }
 
     pub(crate) fn yawareness_update(&self) -> Result<Vec<u8>, Error> {
         let ruby = Ruby::get().unwrap();
-        self.0
+        self.awareness
             .borrow_mut()
             .update()
             .map(|update| update.encode_v1())
@@ -100,7 +164,7 @@ impl YAwareness {
         clients: Vec<ClientID>,
     ) -> Result<Vec<u8>, Error> {
         let ruby = Ruby::get().unwrap();
-        self.0
+        self.awareness
             .borrow_mut()
             .update_with_clients(clients)
             .map(|update| update.encode_v1())
@@ -111,48 +175,195 @@ impl YAwareness {
                 )
             })
     }
+
+    fn subscribe(&self, kind: AwarenessEventKind, block: Proc) -> YAwarenessSubscription {
+        let mut listeners = self.listeners.borrow_mut();
+        listeners.next_id += 1;
+        let id = listeners.next_id;
+        match kind {
+            AwarenessEventKind::Change => listeners.on_change.push((id, block)),
+            AwarenessEventKind::Update => listeners.on_update.push((id, block)),
+        }
+
+        YAwarenessSubscription {
+            id,
+            kind,
+            listeners: RefCell::new(Some(Rc::downgrade(&self.listeners))),
+        }
+    }
+
+    /// Calls the Ruby listeners for all events queued by the last operation.
+    /// This must only be called once the awareness instance is not borrowed
+    /// anymore, otherwise listeners would not be able to read the state.
+    ///
+    /// A raising listener does not prevent the remaining listeners and events
+    /// from being called, the first error is returned once all are done.
+    fn emit(&self) -> Result<(), Error> {
+        let pending = std::mem::take(&mut self.observer.borrow_mut().pending);
+        let mut first_error = None;
+        let mut call = |kind: AwarenessEventKind, event: &YAwarenessEvent| {
+            let blocks = self.listeners.borrow().blocks(kind);
+            for block in blocks {
+                if let Err(error) = block.call::<(YAwarenessEvent,), Value>((event.clone(),)) {
+                    first_error.get_or_insert(error);
+                }
+            }
+        };
+
+        for (change, update) in pending {
+            if let Some(change) = change {
+                call(AwarenessEventKind::Change, &change);
+            }
+            call(AwarenessEventKind::Update, &update);
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+impl AwarenessObserver {
+    fn observe(&mut self, awareness: &Awareness, event: &Event) {
+        let states = awareness.clients();
+        let updated: Vec<ClientID> = event
+            .updated()
+            .iter()
+            .filter(|client_id| self.states.get(*client_id) != states.get(*client_id))
+            .copied()
+            .collect();
+
+        let update = YAwarenessEvent::from(event).with_origin(self.origin.clone());
+        let change = if event.added().is_empty() && updated.is_empty() && event.removed().is_empty()
+        {
+            None
+        } else {
+            Some(YAwarenessEvent {
+                added: event.added().to_vec(),
+                updated,
+                removed: event.removed().to_vec(),
+                origin: self.origin.clone(),
+            })
+        };
+
+        self.states = states.clone();
+        self.pending.push((change, update));
+    }
+}
+
+impl AwarenessListeners {
+    fn blocks(&self, kind: AwarenessEventKind) -> Vec<Proc> {
+        let listeners = match kind {
+            AwarenessEventKind::Change => &self.on_change,
+            AwarenessEventKind::Update => &self.on_update,
+        };
+        listeners.iter().map(|(_id, block)| *block).collect()
+    }
+
+    fn remove(&mut self, kind: AwarenessEventKind, id: u32) {
+        let listeners = match kind {
+            AwarenessEventKind::Change => &mut self.on_change,
+            AwarenessEventKind::Update => &mut self.on_update,
+        };
+        listeners.retain(|(listener_id, _block)| *listener_id != id);
+    }
+}
+
+fn parse_state(json: &str) -> Result<Value, Error> {
+    let ruby = Ruby::get().unwrap();
+    serde_json::from_str(json)
+        .map(convert_json_to_ruby_value)
+        .map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot parse awareness state: {}", error),
+            )
+        })
 }
 
 impl From<Awareness> for YAwareness {
-    fn from(value: Awareness) -> Self {
-        Self(RefCell::from(value))
+    fn from(mut value: Awareness) -> Self {
+        let observer = Rc::new(RefCell::new(AwarenessObserver {
+            origin: None,
+            states: value.clients().clone(),
+            pending: Vec::new(),
+        }));
+
+        let observer_ref = Rc::clone(&observer);
+        let subscription = value.on_update(move |awareness, event| {
+            observer_ref.borrow_mut().observe(awareness, event);
+        });
+
+        Self {
+            awareness: RefCell::from(value),
+            observer,
+            listeners: Rc::new(RefCell::new(AwarenessListeners::default())),
+            _subscription: subscription,
+        }
     }
 }
 
 #[magnus::wrap(class = "Y::AwarenessEvent")]
-pub(crate) struct YAwarenessEvent(Event);
+#[derive(Clone)]
+pub(crate) struct YAwarenessEvent {
+    added: Vec<ClientID>,
+    updated: Vec<ClientID>,
+    removed: Vec<ClientID>,
+    origin: Option<String>,
+}
 
 /// SAFETY: This is safe because we only access this data when the GVL is held.
 unsafe impl Send for YAwarenessEvent {}
 
 impl YAwarenessEvent {
     pub(crate) fn added(&self) -> Vec<ClientID> {
-        self.0.borrow().added().to_vec()
+        self.added.clone()
     }
     pub(crate) fn updated(&self) -> Vec<ClientID> {
-        self.0.borrow().updated().to_vec()
+        self.updated.clone()
     }
     pub(crate) fn removed(&self) -> Vec<ClientID> {
-        self.0.borrow().removed().to_vec()
+        self.removed.clone()
+    }
+    pub(crate) fn origin(&self) -> Option<String> {
+        self.origin.clone()
+    }
+
+    fn with_origin(mut self, origin: Option<String>) -> Self {
+        self.origin = origin;
+        self
     }
 }
 
 impl From<&Event> for YAwarenessEvent {
     fn from(value: &Event) -> Self {
-        Self(value.clone())
+        Self {
+            added: value.added().to_vec(),
+            updated: value.updated().to_vec(),
+            removed: value.removed().to_vec(),
+            origin: None,
+        }
     }
 }
 
-#[allow(dead_code)]
-#[magnus::wrap(class = "Y::AwarenessEvent")]
-pub(crate) struct YAwarenessSubscription(UpdateSubscription);
+#[magnus::wrap(class = "Y::Awareness::Subscription")]
+pub(crate) struct YAwarenessSubscription {
+    id: u32,
+    kind: AwarenessEventKind,
+    listeners: RefCell<Option<Weak<RefCell<AwarenessListeners>>>>,
+}
 
+/// SAFETY: This is safe because we only access this data when the GVL is held.
 unsafe impl Send for YAwarenessSubscription {}
 
-impl YAwarenessSubscription {}
+impl YAwarenessSubscription {
+    pub(crate) fn yawareness_subscription_subscribed(&self) -> bool {
+        self.listeners
+            .borrow()
+            .as_ref()
+            .is_some_and(|listeners| listeners.strong_count() > 0)
+    }
 
-impl From<UpdateSubscription> for YAwarenessSubscription {
-    fn from(v: UpdateSubscription) -> Self {
-        YAwarenessSubscription(v)
+    pub(crate) fn yawareness_subscription_unsubscribe(&self) {
+        if let Some(listeners) = self.listeners.take().and_then(|value| value.upgrade()) {
+            listeners.borrow_mut().remove(self.kind, self.id);
+        }
     }
 }
@@ -1,6 +1,9 @@
 use crate::yarray::YArray;
 use crate::ymap::YMap;
+use crate::ysnapshot::YSnapshot;
 use crate::ytext::YText;
+use crate::yupdate::{content_heap_size, BlockKind, DecodedUpdate, PendingUpdates};
+use crate::yvalue::{ConversionOptions, IntegerEncoding};
 use crate::yxml_element::YXmlElement;
 use crate::yxml_fragment::YXmlFragment;
 use crate::yxml_text::YXmlText;
@@ -10,38 +13,31 @@ use magnus::value::ReprValue;
 use magnus::{Error, Integer, RArray, RHash, Ruby, Symbol, Value};
 use std::borrow::Borrow;
 use std::cell::RefCell;
+use std::mem::size_of;
+use std::rc::Rc;
+use yrs::block::{ClientID, Item, ItemContent};
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::{Encoder, EncoderV2};
-use yrs::{Doc, OffsetKind, Options, ReadTxn, StateVector, SubscriptionId, Transact};
+use yrs::{
+    DeleteSet, Doc, OffsetKind, Options, ReadTxn, StateVector, SubscriptionId, Transact, Update,
+};
 
 #[magnus::wrap(class = "Y::Doc")]
-pub(crate) struct YDoc(pub(crate) RefCell<Doc>);
+pub(crate) struct YDoc(
+    pub(crate) RefCell<Doc>,
+    pub(crate) ConversionOptions,
+    pub(crate) Rc<RefCell<PendingUpdates>>,
+);
 
 unsafe impl Send for YDoc {}
 
 impl YDoc {
-    pub(crate) fn ydoc_new(args: &[Value]) -> Self {
-        let ruby = Ruby::get().unwrap();
-        let mut options = Options::default();
-
-        for value in args {
-            if let Some(id) = Integer::from_value(*value) {
-                options.client_id = id.to_u64().unwrap();
-            } else if let Some(hash) = RHash::from_value(*value) {
-                let gc_key = Symbol::new("gc");
-                if let Ok(Some(gc_val)) = hash.lookup::<_, Option<Value>>(gc_key) {
-                    // gc: false means skip_gc = true (disable garbage collection)
-                    if gc_val.is_kind_of(ruby.class_false_class()) {
-                        options.skip_gc = true;
-                    }
-                }
-            }
-        }
-
-        options.offset_kind = OffsetKind::Utf16;
+    pub(crate) fn ydoc_new(args: &[Value]) -> Result<Self, Error> {
+        let options = doc_options(args);
+        let conversion_options = conversion_options(args)?;
 
         let doc = Doc::with_options(options);
-        Self(RefCell::new(doc))
+        Ok(Self(RefCell::new(doc), conversion_options, Rc::default()))
     }
 
     pub(crate) fn ydoc_encode_diff_v1(
@@ -74,6 +70,187 @@ impl YDoc {
             .map_err(|_e| Error::new(ruby.exception_runtime_error(), "cannot encode diff"))
     }
 
+    /// Creates a new document with the same content and options, but with
+    /// deleted content garbage collected and blocks squashed. Content deleted
+    /// after the `since` snapshot is kept, so that this and newer snapshots
+    /// can still be restored.
+    pub(crate) fn ydoc_compact(
+        &self,
+        transaction: &YTransaction,
+        since: Option<&YSnapshot>,
+    ) -> Result<YDoc, Error> {
+        let ruby = Ruby::get().unwrap();
+        let error = |message: &str| Error::new(ruby.exception_runtime_error(), message.to_string());
+
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let state = tx.encode_state_as_update_v1(&StateVector::default());
+        let mut state = DecodedUpdate::decode_v1(state.as_slice())
+            .map_err(|_e| error("cannot decode document state"))?;
+
+        // only the deletions of the snapshot are collected, the remaining
+        // ones are restored as tombstones
+        let delete_set = match since {
+            Some(snapshot) => {
+                std::mem::replace(&mut state.delete_set, snapshot.0.delete_set.clone())
+            }
+            None => DeleteSet::new(),
+        };
+        let collectable = state.encode_v1();
+
+        let options = self.0.borrow().options().clone();
+        let collector = Doc::with_options(Options {
+            skip_gc: false,
+            ..options.clone()
+        });
+        let collectable = Update::decode_v1(collectable.as_slice())
+            .map_err(|_e| error("cannot decode document state"))?;
+        collector.transact_mut().apply_update(collectable);
+        let compacted = collector
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+
+        let doc = Doc::with_options(options);
+        {
+            let mut txn = doc.transact_mut();
+            let compacted = Update::decode_v1(compacted.as_slice())
+                .map_err(|_e| error("cannot decode compacted state"))?;
+            txn.apply_update(compacted);
+
+            if !delete_set.is_empty() {
+                let deletions = DecodedUpdate {
+                    clients: Vec::new(),
+                    delete_set,
+                };
+                let deletions = Update::decode_v1(deletions.encode_v1().as_slice())
+                    .map_err(|_e| error("cannot decode delete set"))?;
+                txn.apply_update(deletions);
+            }
+        }
+
+        Ok(YDoc(RefCell::new(doc), self.1, Rc::default()))
+    }
+
+    /// Creates a new document with a copy of the whole store. The fork gets a
+    /// fresh client ID (unless one is given) and GUID, all other options are
+    /// the same. Both documents must not share a client ID, as their changes
+    /// would get the same IDs.
+    pub(crate) fn ydoc_fork(
+        &self,
+        transaction: &YTransaction,
+        client_id: Option<ClientID>,
+    ) -> Result<YDoc, Error> {
+        let ruby = Ruby::get().unwrap();
+        if client_id == Some(self.0.borrow().client_id()) {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                "the client ID of a fork must differ from the client ID of the document",
+            ));
+        }
+
+        let client_id = client_id.unwrap_or(Options::default().client_id);
+        self.copy(&ruby, transaction, client_id)
+    }
+
+    /// Creates a document with a copy of the whole store, the client ID and
+    /// a fresh GUID.
+    fn copy(
+        &self,
+        ruby: &Ruby,
+        transaction: &YTransaction,
+        client_id: ClientID,
+    ) -> Result<YDoc, Error> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let state = tx.encode_state_as_update_v1(&StateVector::default());
+
+        let doc = Doc::with_options(Options {
+            client_id,
+            guid: Options::default().guid,
+            ..self.0.borrow().options().clone()
+        });
+
+        let update = Update::decode_v1(state.as_slice()).map_err(|_e| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                "cannot decode document state",
+            )
+        })?;
+        doc.transact_mut().apply_update(update);
+
+        Ok(YDoc(RefCell::new(doc), self.1, Rc::default()))
+    }
+
+    /// Collects statistics about the blocks of the document. yrs keeps its
+    /// block store private, so the blocks are read from the encoded state of
+    /// the document (which is measured anyway). It contains every block of
+    /// the store, including tombstones and GC'd ranges.
+    pub(crate) fn ydoc_stats(&self, transaction: &YTransaction) -> Result<RHash, Error> {
+        let ruby = Ruby::get().unwrap();
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let update_v1 = tx.encode_state_as_update_v1(&StateVector::default());
+        let update_v2 = tx.encode_state_as_update_v2(&StateVector::default());
+        let update = DecodedUpdate::decode_v1(update_v1.as_slice()).map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode document state: {:?}", error),
+            )
+        })?;
+
+        let mut nested_types = 0;
+        let mut heap_size = 0;
+        let clients = ruby.ary_new_capa(update.clients.len());
+        for (client, blocks) in update.clients.iter() {
+            let mut items = 0;
+            let mut tombstones = 0;
+            let gc = ruby.ary_new();
+            for block in blocks {
+                match &block.kind {
+                    BlockKind::Item(item) => {
+                        items += 1;
+                        heap_size += size_of::<Item>() + content_heap_size(&item.content);
+                        if update.delete_set.is_deleted(&block.id) {
+                            tombstones += 1;
+                        } else if let ItemContent::Type(_) = item.content {
+                            nested_types += 1;
+                        }
+                    }
+                    BlockKind::GC => {
+                        let end = block.id.clock + block.len;
+                        gc.push(ruby.range_new(block.id.clock, end, true)?)?;
+                    }
+                    BlockKind::Skip => {}
+                }
+            }
+
+            let start = blocks.first().map_or(0, |block| block.id.clock);
+            let end = blocks.last().map_or(0, |block| block.id.clock + block.len);
+
+            let entry = ruby.hash_new();
+            entry.aset(ruby.to_symbol("client"), *client)?;
+            entry.aset(ruby.to_symbol("clock"), ruby.range_new(start, end, true)?)?;
+            entry.aset(ruby.to_symbol("blocks"), blocks.len())?;
+            entry.aset(ruby.to_symbol("items"), items)?;
+            entry.aset(ruby.to_symbol("tombstones"), tombstones)?;
+            entry.aset(ruby.to_symbol("gc"), gc)?;
+            clients.push(entry)?;
+        }
+
+        let encoded_size = ruby.hash_new();
+        encoded_size.aset(ruby.to_symbol("v1"), update_v1.len())?;
+        encoded_size.aset(ruby.to_symbol("v2"), update_v2.len())?;
+
+        let stats = ruby.hash_new();
+        stats.aset(ruby.to_symbol("clients"), clients)?;
+        stats.aset(ruby.to_symbol("root_types"), tx.root_refs().count())?;
+        stats.aset(ruby.to_symbol("nested_types"), nested_types)?;
+        stats.aset(ruby.to_symbol("encoded_size"), encoded_size)?;
+        stats.aset(ruby.to_symbol("heap_size"), heap_size)?;
+        Ok(stats)
+    }
+
     pub(crate) fn ydoc_get_or_insert_array(&self, name: String) -> YArray {
         let array_ref = self.0.borrow().get_or_insert_array(name.as_str());
         YArray::from(array_ref)
@@ -108,12 +285,16 @@ impl YDoc {
         let doc = self.0.borrow();
         let transaction = doc.transact_mut();
         YTransaction::from(transaction)
+            .with_conversion_options(self.1)
+            .with_pending_updates(Rc::clone(&self.2))
     }
 
     pub(crate) fn ydoc_transact_with(&self, origin: Vec<u8>) -> YTransaction {
         let doc = self.0.borrow();
         let transaction = doc.transact_mut_with(origin.as_slice());
         YTransaction::from(transaction)
+            .with_conversion_options(self.1)
+            .with_pending_updates(Rc::clone(&self.2))
     }
 
     pub(crate) fn ydoc_observe_update(&self, block: Proc) -> Result<SubscriptionId, Error> {
@@ -134,3 +315,54 @@ impl YDoc {
             .map_err(|err| Error::new(ruby.exception_runtime_error(), err.to_string()))
     }
 }
+
+/// Maps the optional `client_id` and options Hash passed to `Y::Doc.new` (and
+/// other constructors that create a document) to yrs document options.
+pub(crate) fn doc_options(args: &[Value]) -> Options {
+    let ruby = Ruby::get().unwrap();
+    let mut options = Options::default();
+
+    for value in args {
+        if let Some(id) = Integer::from_value(*value) {
+            options.client_id = id.to_u64().unwrap();
+        } else if let Some(hash) = RHash::from_value(*value) {
+            let gc_key = Symbol::new("gc");
+            if let Ok(Some(gc_val)) = hash.lookup::<_, Option<Value>>(gc_key) {
+                // gc: false means skip_gc = true (disable garbage collection)
+                if gc_val.is_kind_of(ruby.class_false_class()) {
+                    options.skip_gc = true;
+                }
+            }
+        }
+    }
+
+    options.offset_kind = OffsetKind::Utf16;
+    options
+}
+
+/// Maps the options Hash passed to `Y::Doc.new` to the options used to
+/// convert Ruby values, e.g. `integers: :bigint` to store all integers as
+/// BigInt instead of numbers.
+pub(crate) fn conversion_options(args: &[Value]) -> Result<ConversionOptions, Error> {
+    let ruby = Ruby::get().unwrap();
+    let mut options = ConversionOptions::default();
+
+    for value in args {
+        if let Some(hash) = RHash::from_value(*value) {
+            if let Some(integers) = hash.lookup::<_, Option<Symbol>>(Symbol::new("integers"))? {
+                options.integers = match integers.name()?.as_ref() {
+                    "number" => IntegerEncoding::Number,
+                    "bigint" => IntegerEncoding::BigInt,
+                    other => {
+                        return Err(Error::new(
+                            ruby.exception_arg_error(),
+                            format!("unknown integer encoding: {}", other),
+                        ))
+                    }
+                };
+            }
+        }
+    }
+
+    Ok(options)
+}
@@ -0,0 +1,289 @@
+use crate::yarray::YArray;
+use crate::ymap::YMap;
+use crate::ytext::YText;
+use crate::yvalue::YValue;
+use crate::yxml_element::YXmlElement;
+use crate::yxml_fragment::YXmlFragment;
+use crate::yxml_text::YXmlText;
+use magnus::gc::Marker;
+use magnus::value::ReprValue;
+use magnus::{
+    DataTypeFunctions, Error, IntoValue, Module, RArray, RClass, RHash, RModule, Ruby, TypedData,
+    Value,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use yrs::types::array::ArrayEvent;
+use yrs::types::map::MapEvent;
+use yrs::types::text::TextEvent;
+use yrs::types::xml::{XmlEvent, XmlTextEvent};
+use yrs::types::{Attrs, Change, Delta, EntryChange, Event, Path, PathSegment};
+use yrs::updates::encoder::Encode;
+use yrs::{TransactionMut, XmlNode};
+
+/// An event passed to observers. Events are created for subclasses of
+/// `Y::Event` (e.g. `Y::TextEvent`), all values are converted when the event
+/// is created, as the underlying yrs event is only valid during the callback.
+#[derive(TypedData)]
+#[magnus(class = "Y::Event", free_immediately, mark)]
+pub(crate) struct YEvent {
+    target: Value,
+    path: RArray,
+    delta: RArray,
+    keys: RHash,
+    origin: Value,
+    transaction: RHash,
+}
+
+/// SAFETY: This is safe because we only access this data when the GVL is held.
+unsafe impl Send for YEvent {}
+
+impl DataTypeFunctions for YEvent {
+    fn mark(&self, marker: &Marker) {
+        marker.mark(self.target);
+        marker.mark(self.path);
+        marker.mark(self.delta);
+        marker.mark(self.keys);
+        marker.mark(self.origin);
+        marker.mark(self.transaction);
+    }
+}
+
+impl YEvent {
+    pub(crate) fn yevent_delta(&self) -> RArray {
+        self.delta
+    }
+    pub(crate) fn yevent_keys(&self) -> RHash {
+        self.keys
+    }
+    pub(crate) fn yevent_origin(&self) -> Value {
+        self.origin
+    }
+    pub(crate) fn yevent_path(&self) -> RArray {
+        self.path
+    }
+    pub(crate) fn yevent_target(&self) -> Value {
+        self.target
+    }
+    pub(crate) fn yevent_transaction(&self) -> RHash {
+        self.transaction
+    }
+
+    /// Creates a `Y::ArrayEvent`.
+    pub(crate) fn array(transaction: &TransactionMut, event: &ArrayEvent) -> Result<Value, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let target = YArray::from(event.target().clone()).into_value_with(&ruby);
+        let mut yevent = YEvent::new(&ruby, transaction, target, event.path())?;
+        yevent.delta = change_delta(&ruby, event.delta(transaction))?;
+        yevent.wrap_as(&ruby, "ArrayEvent")
+    }
+
+    /// Creates a `Y::MapEvent`.
+    pub(crate) fn map(transaction: &TransactionMut, event: &MapEvent) -> Result<Value, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let target = YMap::from(event.target().clone()).into_value_with(&ruby);
+        let mut yevent = YEvent::new(&ruby, transaction, target, event.path())?;
+        yevent.keys = key_changes(&ruby, event.keys(transaction))?;
+        yevent.wrap_as(&ruby, "MapEvent")
+    }
+
+    /// Creates a `Y::TextEvent`.
+    pub(crate) fn text(transaction: &TransactionMut, event: &TextEvent) -> Result<Value, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let target = YText::from(event.target().clone()).into_value_with(&ruby);
+        let mut yevent = YEvent::new(&ruby, transaction, target, event.path())?;
+        yevent.delta = text_delta(&ruby, event.delta(transaction))?;
+        yevent.wrap_as(&ruby, "TextEvent")
+    }
+
+    /// Creates a `Y::XMLEvent` for a change of the children or attributes of
+    /// an element or fragment.
+    pub(crate) fn xml(transaction: &TransactionMut, event: &XmlEvent) -> Result<Value, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let target = xml_node_to_value(&ruby, event.target().clone());
+        let mut yevent = YEvent::new(&ruby, transaction, target, event.path())?;
+        yevent.delta = change_delta(&ruby, event.delta(transaction))?;
+        yevent.keys = key_changes(&ruby, event.keys(transaction))?;
+        yevent.wrap_as(&ruby, "XMLEvent")
+    }
+
+    /// Creates a `Y::XMLEvent` for a change of the content or attributes of
+    /// an XML text.
+    pub(crate) fn xml_text(
+        transaction: &TransactionMut,
+        event: &XmlTextEvent,
+    ) -> Result<Value, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let target = YXmlText::from(event.target().clone()).into_value_with(&ruby);
+        let mut yevent = YEvent::new(&ruby, transaction, target, event.path())?;
+        yevent.delta = text_delta(&ruby, event.delta(transaction))?;
+        yevent.keys = key_changes(&ruby, event.keys(transaction))?;
+        yevent.wrap_as(&ruby, "XMLEvent")
+    }
+
+    /// Creates the event for a change passed to a deep observer.
+    pub(crate) fn from_event(transaction: &TransactionMut, event: &Event) -> Result<Value, Error> {
+        match event {
+            Event::Array(event) => YEvent::array(transaction, event),
+            Event::Map(event) => YEvent::map(transaction, event),
+            Event::Text(event) => YEvent::text(transaction, event),
+            Event::XmlFragment(event) => YEvent::xml(transaction, event),
+            Event::XmlText(event) => YEvent::xml_text(transaction, event),
+        }
+    }
+
+    fn new(
+        ruby: &Ruby,
+        transaction: &TransactionMut,
+        target: Value,
+        path: Path,
+    ) -> Result<Self, Error> {
+        let origin = origin(ruby, transaction);
+
+        let info = ruby.hash_new();
+        info.aset(ruby.to_symbol("origin"), origin)?;
+        info.aset(
+            ruby.to_symbol("before_state"),
+            transaction.before_state().encode_v1(),
+        )?;
+        info.aset(
+            ruby.to_symbol("after_state"),
+            transaction.after_state().encode_v1(),
+        )?;
+        info.freeze();
+
+        Ok(YEvent {
+            target,
+            path: path_to_rarray(ruby, path)?,
+            delta: ruby.ary_new(),
+            keys: ruby.hash_new(),
+            origin,
+            transaction: info,
+        })
+    }
+
+    fn wrap_as(self, ruby: &Ruby, class: &str) -> Result<Value, Error> {
+        let class = ruby
+            .class_object()
+            .const_get::<_, RModule>("Y")?
+            .const_get::<_, RClass>(class)?;
+        Ok(ruby.obj_wrap_as(self, class).as_value())
+    }
+}
+
+pub(crate) fn xml_node_to_value(ruby: &Ruby, node: XmlNode) -> Value {
+    match node {
+        XmlNode::Element(element) => YXmlElement::from(element).into_value_with(ruby),
+        XmlNode::Fragment(fragment) => YXmlFragment::from(fragment).into_value_with(ruby),
+        XmlNode::Text(text) => YXmlText::from(text).into_value_with(ruby),
+    }
+}
+
+/// Converts the origin of a transaction into a String. Origins that are not
+/// valid UTF-8 are returned as binary strings.
+fn origin(ruby: &Ruby, transaction: &TransactionMut) -> Value {
+    match transaction.origin() {
+        Some(origin) => match std::str::from_utf8(origin.as_ref()) {
+            Ok(origin) => ruby.str_new(origin).as_value(),
+            Err(_) => ruby.str_from_slice(origin.as_ref()).as_value(),
+        },
+        None => ruby.qnil().as_value(),
+    }
+}
+
+/// Converts a path into a list of map keys (Strings) and array indices
+/// (Integers).
+fn path_to_rarray(ruby: &Ruby, path: Path) -> Result<RArray, Error> {
+    let segments = ruby.ary_new_capa(path.len());
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) => segments.push(key.to_string())?,
+            PathSegment::Index(index) => segments.push(index)?,
+        }
+    }
+    Ok(segments)
+}
+
+/// Converts a list of changes into `{insert: [values]}`, `{retain:}` and
+/// `{delete:}` Hashes.
+fn change_delta(ruby: &Ruby, delta: &[Change]) -> Result<RArray, Error> {
+    let changes = ruby.ary_new_capa(delta.len());
+    for change in delta {
+        let payload = ruby.hash_new();
+        match change {
+            Change::Added(values) => {
+                let inserted = ruby.ary_new_capa(values.len());
+                for value in values {
+                    inserted.push(YValue::try_from(value.clone())?.0.into_inner())?;
+                }
+                payload.aset(ruby.to_symbol("insert"), inserted)?;
+            }
+            Change::Retain(length) => payload.aset(ruby.to_symbol("retain"), *length)?,
+            Change::Removed(length) => payload.aset(ruby.to_symbol("delete"), *length)?,
+        }
+        changes.push(payload)?;
+    }
+    Ok(changes)
+}
+
+/// Converts key changes into a Hash of `{action:, old_value:, new_value:}`
+/// by key. The action is one of `:inserted`, `:updated` or `:removed`.
+fn key_changes(ruby: &Ruby, keys: &HashMap<Arc<str>, EntryChange>) -> Result<RHash, Error> {
+    let changes = ruby.hash_new_capa(keys.len());
+    for (key, change) in keys {
+        let (action, old_value, new_value) = match change {
+            EntryChange::Inserted(new) => ("inserted", None, Some(new)),
+            EntryChange::Updated(old, new) => ("updated", Some(old), Some(new)),
+            EntryChange::Removed(old) => ("removed", Some(old), None),
+        };
+        let to_value = |value: Option<&yrs::types::Value>| {
+            value
+                .map(|value| YValue::try_from(value.clone()).map(|value| value.0.into_inner()))
+                .transpose()
+        };
+
+        let payload = ruby.hash_new();
+        payload.aset(ruby.to_symbol("action"), ruby.to_symbol(action))?;
+        payload.aset(ruby.to_symbol("old_value"), to_value(old_value)?)?;
+        payload.aset(ruby.to_symbol("new_value"), to_value(new_value)?)?;
+        changes.aset(key.to_string(), payload)?;
+    }
+    Ok(changes)
+}
+
+/// Converts a text delta into a list of `{insert:, attributes:}`,
+/// `{retain:, attributes:}` and `{delete:}` Hashes.
+fn text_delta(ruby: &Ruby, delta: &[Delta]) -> Result<RArray, Error> {
+    let changes = ruby.ary_new_capa(delta.len());
+    for change in delta {
+        let payload = ruby.hash_new();
+        match change {
+            Delta::Inserted(value, attrs) => {
+                let value = YValue::try_from(value.clone())?.0.into_inner();
+                payload.aset(ruby.to_symbol("insert"), value)?;
+                set_attributes(ruby, payload, attrs)?;
+            }
+            Delta::Retain(length, attrs) => {
+                payload.aset(ruby.to_symbol("retain"), *length)?;
+                set_attributes(ruby, payload, attrs)?;
+            }
+            Delta::Deleted(length) => {
+                payload.aset(ruby.to_symbol("delete"), *length)?;
+            }
+        }
+        changes.push(payload)?;
+    }
+    Ok(changes)
+}
+
+fn set_attributes(ruby: &Ruby, payload: RHash, attrs: &Option<Box<Attrs>>) -> Result<(), Error> {
+    if let Some(attrs) = attrs {
+        let attributes = ruby.hash_new();
+        for (key, value) in attrs.iter() {
+            let value = YValue::try_from(value.clone())?.0.into_inner();
+            attributes.aset(key.to_string(), value)?;
+        }
+        payload.aset(ruby.to_symbol("attributes"), attributes)?;
+    }
+    Ok(())
+}
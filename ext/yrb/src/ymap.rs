@@ -1,10 +1,11 @@
-use crate::utils::{convert_yvalue_to_ruby_value, indifferent_hash_key};
+use crate::utils::{convert_yvalue_to_ruby_value, defer_error, indifferent_hash_key};
+use crate::yevent::YEvent;
 use crate::yvalue::YValue;
 use crate::YTransaction;
 use magnus::block::Proc;
-use magnus::{Error, RArray, RHash, Ruby, Value};
+use magnus::{Error, RHash, Ruby, Value};
 use std::cell::RefCell;
-use yrs::types::{EntryChange, Value as YrsValue};
+use yrs::types::Value as YrsValue;
 use yrs::{Any, Map, MapRef, Observable};
 
 #[magnus::wrap(class = "Y::Map")]
@@ -30,25 +31,31 @@ impl YMap {
         }
     }
 
-    pub(crate) fn ymap_each(&self, transaction: &YTransaction, proc: Proc) {
+    pub(crate) fn ymap_each(&self, transaction: &YTransaction, proc: Proc) -> Result<(), Error> {
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
-        self.0.borrow().iter(tx).for_each(|(key, val)| {
+        for (key, val) in self.0.borrow().iter(tx) {
             let k = key.to_string();
-            let v = *convert_yvalue_to_ruby_value(val, tx).0.borrow();
+            let v = convert_yvalue_to_ruby_value(val, tx)?.0.into_inner();
             proc.call::<(String, Value), Value>((k, v))
                 .expect("cannot iterate map");
-        })
+        }
+        Ok(())
     }
 
-    pub(crate) fn ymap_get(&self, transaction: &YTransaction, key: Value) -> Option<Value> {
+    pub(crate) fn ymap_get(
+        &self,
+        transaction: &YTransaction,
+        key: Value,
+    ) -> Result<Option<Value>, Error> {
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
 
         indifferent_hash_key(key)
             .map(|k| self.0.borrow().get(tx, k.as_str()))
             .map(|v| v.unwrap_or(YrsValue::Any(Any::Undefined)))
-            .map(|v| *convert_yvalue_to_ruby_value(v, tx).0.borrow())
+            .map(|v| convert_yvalue_to_ruby_value(v, tx).map(|v| v.0.into_inner()))
+            .transpose()
     }
     pub(crate) fn ymap_insert(
         &self,
@@ -57,6 +64,7 @@ impl YMap {
         value: Value,
     ) -> Result<(), Error> {
         let ruby = Ruby::get().unwrap();
+        let options = transaction.conversion_options();
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
@@ -66,88 +74,41 @@ impl YMap {
                 "invalid key type, make sure it is either of type Symbol or String",
             )),
             Some(k) => {
-                let v = Any::from(YValue::from(value));
+                let v = YValue::from(value).try_into_any(options)?;
                 self.0.borrow_mut().insert(tx, k, v);
 
                 Ok(())
             }
         }
     }
-    pub(crate) fn ymap_observe(&self, block: Proc) -> u32 {
-        let ruby = unsafe { Ruby::get_unchecked() };
-        let change_inserted = ruby.to_symbol("inserted").as_static();
-        let change_updated = ruby.to_symbol("updated").as_static();
-        let change_removed = ruby.to_symbol("removed").as_static();
-        self.0
+    pub(crate) fn ymap_observe(&self, block: Proc) -> Result<u32, Error> {
+        let subscription_id = self
+            .0
             .borrow_mut()
-            .observe(move |transaction, map_event| {
-                let ruby = unsafe { Ruby::get_unchecked() };
-                let delta = map_event.keys(transaction);
-                let changes = ruby.ary_new_capa(delta.len());
-
-                for (key, change) in delta {
-                    match change {
-                        EntryChange::Inserted(v) => {
-                            let h = ruby.hash_new();
-                            h.aset(ruby.to_symbol(key), *YValue::from(v.clone()).0.borrow())
-                                .expect("cannot add change::inserted");
-
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(change_inserted, h)
-                                .expect("cannot add change::inserted");
-
-                            changes.push(payload).expect("cannot push changes::payload");
-                        }
-                        EntryChange::Updated(old, new) => {
-                            let values = ruby.ary_new_capa(2);
-                            values
-                                .push(*YValue::from(old.clone()).0.borrow())
-                                .expect("cannot push change::updated");
-                            values
-                                .push(*YValue::from(new.clone()).0.borrow())
-                                .expect("cannot push change::updated");
-
-                            let h = ruby.hash_new();
-                            h.aset(ruby.to_symbol(key), values)
-                                .expect("cannot push change::updated");
-
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(change_updated, h)
-                                .expect("cannot push change::updated");
-
-                            changes.push(payload).expect("cannot push changes::payload");
-                        }
-                        EntryChange::Removed(v) => {
-                            let h = ruby.hash_new();
-                            h.aset(ruby.to_symbol(key), *YValue::from(v.clone()).0.borrow())
-                                .expect("cannot push change::removed");
-
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(change_removed, h)
-                                .expect("cannot push change::removed");
-
-                            changes.push(payload).expect("cannot push changes::payload");
-                        }
-                    }
+            .observe(move |transaction, event| {
+                let result = YEvent::map(transaction, event)
+                    .and_then(|event| block.call::<(Value,), Value>((event,)));
+                if let Err(error) = result {
+                    defer_error(error);
                 }
-
-                block
-                    .call::<(RArray,), Value>((changes,))
-                    .expect("cannot call block");
             })
-            .into()
+            .into();
+
+        Ok(subscription_id)
     }
-    pub(crate) fn ymap_remove(&self, transaction: &YTransaction, key: Value) -> Option<Value> {
+    pub(crate) fn ymap_remove(
+        &self,
+        transaction: &YTransaction,
+        key: Value,
+    ) -> Result<Option<Value>, Error> {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         indifferent_hash_key(key)
             .map(|k| self.0.borrow().remove(tx, k.as_str()))
             .map(|v| v.unwrap_or(YrsValue::Any(Any::Undefined)))
-            .map(|v| *YValue::from(v).0.borrow())
+            .map(|v| YValue::try_from(v).map(|v| v.0.into_inner()))
+            .transpose()
     }
     pub(crate) fn ymap_size(&self, transaction: &YTransaction) -> u32 {
         let tx = transaction.transaction();
@@ -155,18 +116,17 @@ impl YMap {
 
         self.0.borrow().len(tx)
     }
-    pub(crate) fn ymap_to_h(&self, transaction: &YTransaction) -> RHash {
+    pub(crate) fn ymap_to_h(&self, transaction: &YTransaction) -> Result<RHash, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
 
         let hash = ruby.hash_new();
         for (k, v) in self.0.borrow().iter(tx) {
-            let value = *YValue::from(v).0.borrow();
-            hash.aset(k.to_string(), value)
-                .expect("cannot insert into hash");
+            let value = YValue::try_from(v)?.0.into_inner();
+            hash.aset(k.to_string(), value)?;
         }
-        hash
+        Ok(hash)
     }
     pub(crate) fn ymap_unobserve(&self, subscription_id: u32) {
         self.0.borrow_mut().unobserve(subscription_id);
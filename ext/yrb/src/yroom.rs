@@ -0,0 +1,477 @@
+use crate::utils::catch_observer_errors;
+use crate::ydoc::{conversion_options, doc_options, YDoc};
+use crate::yupdate::PendingUpdates;
+use crate::yvalue::ConversionOptions;
+use magnus::block::Proc;
+use magnus::gc::Marker;
+use magnus::{DataTypeFunctions, Error, Ruby, TypedData, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use y_sync::awareness::{Awareness, AwarenessUpdate, UpdateSubscription as AwarenessSubscription};
+use y_sync::sync::{Message, MessageReader, SyncMessage};
+use yrs::block::ClientID;
+use yrs::encoding::read::{self, Read};
+use yrs::encoding::write::Write;
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{Doc, Origin, ReadTxn, Transact, Transaction, Update, UpdateSubscription};
+
+/// Prefix of the transaction origin used for updates received from a
+/// connection. It allows the document observer to skip the sender when
+/// broadcasting an update.
+const CONNECTION_ORIGIN_PREFIX: &str = "yrb-room-connection:";
+
+/// Reason sent with the `Auth` message when a read-only connection tries to
+/// change the document.
+const READ_ONLY_DENIED_REASON: &str = "permission denied: connection is read-only";
+
+type ConnectionId = u64;
+
+#[derive(TypedData)]
+#[magnus(class = "Y::Room", free_immediately, mark)]
+pub(crate) struct YRoom {
+    doc: Doc,
+    conversion_options: ConversionOptions,
+    pending: Rc<RefCell<PendingUpdates>>,
+    awareness: RefCell<Awareness>,
+    connections: RefCell<HashMap<ConnectionId, RoomConnection>>,
+    next_connection_id: Cell<ConnectionId>,
+    outbox: Rc<RefCell<RoomOutbox>>,
+    _doc_subscription: UpdateSubscription,
+    _awareness_subscription: AwarenessSubscription,
+}
+
+/// SAFETY: This is safe because we only access this data when the GVL is held.
+unsafe impl Send for YRoom {}
+
+impl DataTypeFunctions for YRoom {
+    /// The callbacks of connections are only referenced from Rust, they must
+    /// be marked to outlive a GC run. The connections are never borrowed
+    /// mutably while Ruby code runs or allocates.
+    fn mark(&self, marker: &Marker) {
+        if let Ok(connections) = self.connections.try_borrow() {
+            for connection in connections.values() {
+                marker.mark(connection.send);
+            }
+        }
+    }
+}
+
+struct RoomConnection {
+    send: Proc,
+    clients: HashSet<ClientID>,
+    read_only: bool,
+}
+
+/// Messages are queued while the document or the awareness instance is
+/// borrowed and sent once the operation that produced them is finished.
+#[derive(Default)]
+struct RoomOutbox {
+    origin: Option<ConnectionId>,
+    messages: Vec<(Recipient, Vec<u8>)>,
+}
+
+#[derive(Clone, Copy)]
+enum Recipient {
+    Only(ConnectionId),
+    AllExcept(Option<ConnectionId>),
+}
+
+impl YRoom {
+    pub(crate) fn yroom_new(args: &[Value]) -> Result<Self, Error> {
+        let ruby = Ruby::get().unwrap();
+        let doc = Doc::with_options(doc_options(args));
+        let conversion_options = conversion_options(args)?;
+        let outbox = Rc::new(RefCell::new(RoomOutbox::default()));
+
+        let doc_outbox = Rc::clone(&outbox);
+        let doc_subscription = doc
+            .observe_update_v1(move |txn, event| {
+                let origin = txn.origin().and_then(connection_id_from_origin);
+                let message = Message::Sync(SyncMessage::Update(event.update.clone()));
+                doc_outbox
+                    .borrow_mut()
+                    .messages
+                    .push((Recipient::AllExcept(origin), message.encode_v1()));
+            })
+            .map_err(|err| Error::new(ruby.exception_runtime_error(), err.to_string()))?;
+
+        let mut awareness = Awareness::new(doc.clone());
+        let awareness_outbox = Rc::clone(&outbox);
+        let awareness_subscription = awareness.on_update(move |awareness, event| {
+            let changed: Vec<ClientID> = event
+                .added()
+                .iter()
+                .chain(event.updated().iter())
+                .chain(event.removed().iter())
+                .copied()
+                .collect();
+
+            if let Ok(update) = awareness.update_with_clients(changed) {
+                let mut outbox = awareness_outbox.borrow_mut();
+                let recipient = Recipient::AllExcept(outbox.origin);
+                let message = Message::Awareness(update);
+                outbox.messages.push((recipient, message.encode_v1()));
+            }
+        });
+
+        Ok(Self {
+            doc,
+            conversion_options,
+            pending: Rc::default(),
+            awareness: RefCell::new(awareness),
+            connections: RefCell::new(HashMap::new()),
+            next_connection_id: Cell::new(0),
+            outbox,
+            _doc_subscription: doc_subscription,
+            _awareness_subscription: awareness_subscription,
+        })
+    }
+
+    pub(crate) fn yroom_connect(&self, send: Proc, read_only: bool) -> Result<ConnectionId, Error> {
+        // the server starts the handshake by sending its state vector, the
+        // client responds with the missing updates and its own state vector
+        let state_vector = self.read_transaction()?.state_vector();
+
+        let id = self.next_connection_id.get() + 1;
+        self.next_connection_id.set(id);
+
+        self.connections.borrow_mut().insert(
+            id,
+            RoomConnection {
+                send,
+                clients: HashSet::new(),
+                read_only,
+            },
+        );
+
+        let step1 = Message::Sync(SyncMessage::SyncStep1(state_vector));
+        self.enqueue(Recipient::Only(id), step1.encode_v1());
+
+        let awareness = self.awareness.borrow();
+        if !awareness.clients().is_empty() {
+            if let Ok(update) = awareness.update() {
+                self.enqueue(Recipient::Only(id), Message::Awareness(update).encode_v1());
+            }
+        }
+        drop(awareness);
+
+        self.yroom_flush()?;
+        Ok(id)
+    }
+
+    pub(crate) fn yroom_connections(&self) -> Vec<ConnectionId> {
+        let mut ids: Vec<ConnectionId> = self.connections.borrow().keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub(crate) fn yroom_disconnect(&self, connection_id: ConnectionId) -> Result<bool, Error> {
+        let connection = self.connections.borrow_mut().remove(&connection_id);
+        let Some(connection) = connection else {
+            return Ok(false);
+        };
+
+        self.outbox.borrow_mut().origin = Some(connection_id);
+        {
+            let mut awareness = self.awareness.borrow_mut();
+            for client_id in connection.clients {
+                awareness.remove_state(client_id);
+            }
+        }
+        self.outbox.borrow_mut().origin = None;
+
+        self.yroom_flush()?;
+        Ok(true)
+    }
+
+    pub(crate) fn yroom_is_read_only(&self, connection_id: ConnectionId) -> Option<bool> {
+        self.connections
+            .borrow()
+            .get(&connection_id)
+            .map(|connection| connection.read_only)
+    }
+
+    pub(crate) fn yroom_set_read_only(
+        &self,
+        connection_id: ConnectionId,
+        read_only: bool,
+    ) -> Result<(), Error> {
+        let ruby = Ruby::get().unwrap();
+        match self.connections.borrow_mut().get_mut(&connection_id) {
+            Some(connection) => {
+                connection.read_only = read_only;
+                Ok(())
+            }
+            None => Err(Error::new(
+                ruby.exception_arg_error(),
+                format!("unknown connection: {}", connection_id),
+            )),
+        }
+    }
+
+    pub(crate) fn yroom_doc(&self) -> YDoc {
+        YDoc(
+            RefCell::new(self.doc.clone()),
+            self.conversion_options,
+            Rc::clone(&self.pending),
+        )
+    }
+
+    pub(crate) fn yroom_flush(&self) -> Result<(), Error> {
+        let messages = std::mem::take(&mut self.outbox.borrow_mut().messages);
+
+        for (recipient, message) in messages {
+            let blocks: Vec<Proc> = self
+                .connections
+                .borrow()
+                .iter()
+                .filter(|(id, _connection)| match recipient {
+                    Recipient::Only(only) => **id == only,
+                    Recipient::AllExcept(except) => Some(**id) != except,
+                })
+                .map(|(_id, connection)| connection.send)
+                .collect();
+
+            for block in blocks {
+                block.call::<(Vec<u8>,), Value>((message.clone(),))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn yroom_receive(
+        &self,
+        connection_id: ConnectionId,
+        data: Vec<u8>,
+    ) -> Result<(), Error> {
+        let ruby = Ruby::get().unwrap();
+        if !self.connections.borrow().contains_key(&connection_id) {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                format!("unknown connection: {}", connection_id),
+            ));
+        }
+
+        let mut decoder = DecoderV1::from(data.as_slice());
+        let messages = MessageReader::new(&mut decoder)
+            .collect::<Result<Vec<Message>, _>>()
+            .map_err(|error| {
+                Error::new(
+                    ruby.exception_runtime_error(),
+                    format!("cannot decode message: {:?}", error),
+                )
+            })?;
+
+        for message in messages {
+            self.handle_message(connection_id, message)?;
+        }
+
+        self.yroom_flush()
+    }
+
+    fn handle_message(&self, connection_id: ConnectionId, message: Message) -> Result<(), Error> {
+        let ruby = Ruby::get().unwrap();
+        match message {
+            Message::Sync(SyncMessage::SyncStep1(sv)) => {
+                let update = self.read_transaction()?.encode_state_as_update_v1(&sv);
+                let step2 = Message::Sync(SyncMessage::SyncStep2(update));
+                self.enqueue(Recipient::Only(connection_id), step2.encode_v1());
+            }
+            Message::Sync(SyncMessage::SyncStep2(data))
+            | Message::Sync(SyncMessage::Update(data)) => {
+                let update = Update::decode_v1(data.as_slice()).map_err(|error| {
+                    Error::new(
+                        ruby.exception_runtime_error(),
+                        format!("cannot decode update: {:?}", error),
+                    )
+                })?;
+
+                // a read-only client answers the handshake with an empty
+                // update, anything else is an attempt to change the document
+                if self.is_read_only(connection_id) {
+                    if !is_empty_update(&update) {
+                        let denied = Message::Auth(Some(READ_ONLY_DENIED_REASON.to_string()));
+                        self.enqueue(Recipient::Only(connection_id), denied.encode_v1());
+                    }
+                    return Ok(());
+                }
+
+                // the transaction is committed when it is dropped, which
+                // triggers the update observer and queues the broadcast
+                let origin = connection_origin(connection_id);
+                let mut txn = self
+                    .doc
+                    .try_transact_mut_with(origin.as_bytes())
+                    .map_err(|_error| transaction_open_error(&ruby))?;
+                // errors of observers called during the commit come first
+                let applied = catch_observer_errors(|| {
+                    let applied = self
+                        .pending
+                        .borrow_mut()
+                        .apply_v1(&mut txn, data.as_slice());
+                    drop(txn);
+                    applied
+                })?;
+                applied.map_err(|error| {
+                    Error::new(
+                        ruby.exception_runtime_error(),
+                        format!("cannot decode update: {:?}", error),
+                    )
+                })?;
+            }
+            Message::Awareness(update) => {
+                let entries = awareness_update_entries(&update).map_err(|error| {
+                    Error::new(
+                        ruby.exception_runtime_error(),
+                        format!("cannot decode awareness update: {:?}", error),
+                    )
+                })?;
+
+                // a connection may announce new clients, and change or remove
+                // the clients it announced, but not those of other connections
+                // or the local state of the room
+                let local_client_id = self.awareness.borrow().client_id();
+                let entries: Vec<AwarenessEntry> = {
+                    let mut connections = self.connections.borrow_mut();
+                    let owned_elsewhere: HashSet<ClientID> = connections
+                        .iter()
+                        .filter(|(id, _connection)| **id != connection_id)
+                        .flat_map(|(_id, connection)| connection.clients.iter().copied())
+                        .collect();
+                    let Some(connection) = connections.get_mut(&connection_id) else {
+                        return Ok(());
+                    };
+                    entries
+                        .into_iter()
+                        .filter(|entry| {
+                            entry.client_id != local_client_id
+                                && !owned_elsewhere.contains(&entry.client_id)
+                        })
+                        .inspect(|entry| {
+                            if entry.is_removal() {
+                                connection.clients.remove(&entry.client_id);
+                            } else {
+                                connection.clients.insert(entry.client_id);
+                            }
+                        })
+                        .collect()
+                };
+                if entries.is_empty() {
+                    return Ok(());
+                }
+                let update = encode_awareness_update(&entries).map_err(|error| {
+                    Error::new(
+                        ruby.exception_runtime_error(),
+                        format!("cannot decode awareness update: {:?}", error),
+                    )
+                })?;
+
+                self.outbox.borrow_mut().origin = Some(connection_id);
+                let result = self.awareness.borrow_mut().apply_update(update);
+                self.outbox.borrow_mut().origin = None;
+                result.map_err(|_error| {
+                    Error::new(
+                        ruby.exception_runtime_error(),
+                        "cannot apply awareness update",
+                    )
+                })?;
+            }
+            Message::AwarenessQuery => {
+                if let Ok(update) = self.awareness.borrow().update() {
+                    let message = Message::Awareness(update);
+                    self.enqueue(Recipient::Only(connection_id), message.encode_v1());
+                }
+            }
+            Message::Auth(_) | Message::Custom(_, _) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Opens a read transaction, or raises if the document is changed in a
+    /// transaction that is still open (yrs would panic).
+    fn read_transaction(&self) -> Result<Transaction<'_>, Error> {
+        let ruby = Ruby::get().unwrap();
+        self.doc
+            .try_transact()
+            .map_err(|_error| transaction_open_error(&ruby))
+    }
+
+    fn is_read_only(&self, connection_id: ConnectionId) -> bool {
+        self.yroom_is_read_only(connection_id).unwrap_or(true)
+    }
+
+    fn enqueue(&self, recipient: Recipient, message: Vec<u8>) {
+        self.outbox.borrow_mut().messages.push((recipient, message));
+    }
+}
+
+/// The state of a client in an awareness update.
+struct AwarenessEntry {
+    client_id: ClientID,
+    clock: u32,
+    json: String,
+}
+
+impl AwarenessEntry {
+    fn is_removal(&self) -> bool {
+        self.json == "null"
+    }
+}
+
+/// Returns the entries of an awareness update. y-sync keeps them private,
+/// they are read from the encoded update instead.
+fn awareness_update_entries(update: &AwarenessUpdate) -> Result<Vec<AwarenessEntry>, read::Error> {
+    let encoded = update.encode_v1();
+    let mut decoder = DecoderV1::from(encoded.as_slice());
+    let len: usize = decoder.read_var()?;
+    let mut entries = Vec::with_capacity(len);
+    for _ in 0..len {
+        entries.push(AwarenessEntry {
+            client_id: decoder.read_var()?,
+            clock: decoder.read_var()?,
+            json: decoder.read_string()?.to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Encodes the entries into an awareness update, the reverse of
+/// [awareness_update_entries].
+fn encode_awareness_update(entries: &[AwarenessEntry]) -> Result<AwarenessUpdate, read::Error> {
+    let mut encoder = EncoderV1::new();
+    encoder.write_var(entries.len());
+    for entry in entries {
+        encoder.write_var(entry.client_id);
+        encoder.write_var(entry.clock);
+        encoder.write_string(&entry.json);
+    }
+    AwarenessUpdate::decode_v1(encoder.to_vec().as_slice())
+}
+
+/// `Update::is_empty` is private in yrs, an update without blocks and
+/// deletions encodes the same as a new update.
+fn is_empty_update(update: &Update) -> bool {
+    update.encode_v1() == Update::new().encode_v1()
+}
+
+fn transaction_open_error(ruby: &Ruby) -> Error {
+    Error::new(
+        ruby.exception_runtime_error(),
+        "cannot access the room document while a transaction is open",
+    )
+}
+
+fn connection_origin(connection_id: ConnectionId) -> String {
+    format!("{}{}", CONNECTION_ORIGIN_PREFIX, connection_id)
+}
+
+fn connection_id_from_origin(origin: &Origin) -> Option<ConnectionId> {
+    std::str::from_utf8(origin.as_ref())
+        .ok()
+        .and_then(|origin| origin.strip_prefix(CONNECTION_ORIGIN_PREFIX))
+        .and_then(|id| id.parse().ok())
+}
@@ -11,14 +11,12 @@ unsafe impl Send for YSnapshot {}
 impl YSnapshot {
     pub(crate) fn ysnapshot_decode_v1(data: Vec<u8>) -> Result<Self, Error> {
         let ruby = magnus::Ruby::get().unwrap();
-        Snapshot::decode_v1(&data)
-            .map(|s| YSnapshot(s))
-            .map_err(|e| {
-                Error::new(
-                    ruby.exception_runtime_error(),
-                    format!("cannot decode snapshot: {:?}", e),
-                )
-            })
+        Snapshot::decode_v1(&data).map(YSnapshot).map_err(|e| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode snapshot: {:?}", e),
+            )
+        })
     }
 
     pub(crate) fn ysnapshot_encode_v1(&self) -> Vec<u8> {
@@ -0,0 +1,83 @@
+use magnus::value::ReprValue;
+use magnus::{Error, IntoValue, RArray, Ruby, Value};
+use y_sync::awareness::AwarenessUpdate;
+use y_sync::sync::{Message, MessageReader, SyncMessage};
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::Encode;
+use yrs::StateVector;
+
+pub(crate) fn ysync_encode_sync_step1(state_vector: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let ruby = Ruby::get().unwrap();
+    StateVector::decode_v1(state_vector.as_slice())
+        .map(|sv| Message::Sync(SyncMessage::SyncStep1(sv)).encode_v1())
+        .map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode state vector: {:?}", error),
+            )
+        })
+}
+
+pub(crate) fn ysync_encode_sync_step2(update: Vec<u8>) -> Vec<u8> {
+    Message::Sync(SyncMessage::SyncStep2(update)).encode_v1()
+}
+
+pub(crate) fn ysync_encode_update(update: Vec<u8>) -> Vec<u8> {
+    Message::Sync(SyncMessage::Update(update)).encode_v1()
+}
+
+pub(crate) fn ysync_encode_auth(reason: Option<String>) -> Vec<u8> {
+    Message::Auth(reason).encode_v1()
+}
+
+pub(crate) fn ysync_encode_awareness(update: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let ruby = Ruby::get().unwrap();
+    AwarenessUpdate::decode_v1(update.as_slice())
+        .map(|update| Message::Awareness(update).encode_v1())
+        .map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode awareness update: {:?}", error),
+            )
+        })
+}
+
+pub(crate) fn ysync_encode_awareness_query() -> Vec<u8> {
+    Message::AwarenessQuery.encode_v1()
+}
+
+pub(crate) fn ysync_decode(data: Vec<u8>) -> Result<RArray, Error> {
+    let ruby = Ruby::get().unwrap();
+    let messages = ruby.ary_new();
+
+    let mut decoder = DecoderV1::from(data.as_slice());
+    for message in MessageReader::new(&mut decoder) {
+        let message = message.map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode message: {:?}", error),
+            )
+        })?;
+
+        let (kind, payload): (&str, Value) = match message {
+            Message::Sync(SyncMessage::SyncStep1(sv)) => {
+                ("sync_step1", sv.encode_v1().into_value_with(&ruby))
+            }
+            Message::Sync(SyncMessage::SyncStep2(update)) => {
+                ("sync_step2", update.into_value_with(&ruby))
+            }
+            Message::Sync(SyncMessage::Update(update)) => ("update", update.into_value_with(&ruby)),
+            Message::Auth(reason) => ("auth", reason.into_value_with(&ruby)),
+            Message::AwarenessQuery => ("awareness_query", ruby.qnil().as_value()),
+            Message::Awareness(update) => ("awareness", update.encode_v1().into_value_with(&ruby)),
+            Message::Custom(tag, data) => ("custom", (tag, data).into_value_with(&ruby)),
+        };
+
+        let entry = ruby.ary_new_capa(2);
+        entry.push(ruby.to_symbol(kind))?;
+        entry.push(payload)?;
+        messages.push(entry)?;
+    }
+
+    Ok(messages)
+}
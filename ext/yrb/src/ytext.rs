@@ -1,15 +1,18 @@
+use crate::utils::defer_error;
 use crate::yattrs::YAttrs;
 use crate::ydiff::YDiff;
-use crate::yvalue::YValue;
+use crate::yevent::YEvent;
+use crate::ytext_diff::{self, Token};
+use crate::yvalue::{ConversionOptions, YValue};
 use crate::YTransaction;
 use magnus::block::Proc;
-use magnus::value::Qnil;
-use magnus::RArray;
+use magnus::value::ReprValue;
 pub(crate) use magnus::{Error, IntoValue, RHash, Ruby, Value};
+use magnus::{RArray, TryConvert};
 use std::cell::RefCell;
 use yrs::types::text::YChange;
-use yrs::types::Delta;
-use yrs::{Any, GetString, Observable, Text, TextRef};
+use yrs::types::{Attrs, Delta, Value as YrsValue};
+use yrs::{Any, GetString, Observable, OffsetKind, Text, TextRef, TransactionMut};
 
 #[magnus::wrap(class = "Y::Text")]
 pub(crate) struct YText(pub(crate) RefCell<TextRef>);
@@ -18,36 +21,88 @@ pub(crate) struct YText(pub(crate) RefCell<TextRef>);
 unsafe impl Send for YText {}
 
 impl YText {
-    pub(crate) fn ytext_diff(&self, transaction: &YTransaction) -> RArray {
+    pub(crate) fn ytext_apply_delta(
+        &self,
+        transaction: &YTransaction,
+        delta: RArray,
+    ) -> Result<(), Error> {
+        let delta = parse_delta(delta, transaction.conversion_options())?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        apply_delta(&*self.0.borrow(), tx, delta)
+    }
+    pub(crate) fn ytext_diff(&self, transaction: &YTransaction) -> Result<RArray, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
 
         let array = ruby.ary_new();
         for diff in self.0.borrow().diff(tx, YChange::identity).iter() {
-            let yvalue = YValue::from(diff.insert.clone());
+            let yvalue = YValue::try_from(diff.insert.clone())?;
             let insert = yvalue.0.into_inner();
-            let attributes = diff.attributes.as_ref().map_or_else(
-                || None,
-                |boxed_attrs| {
+            let attributes = match diff.attributes.as_ref() {
+                Some(boxed_attrs) => {
                     let attributes = ruby.hash_new();
                     for (key, value) in boxed_attrs.iter() {
                         let key = key.to_string();
-                        let value = YValue::from(value.clone()).0.into_inner();
-                        attributes.aset(key, value).expect("cannot add value");
+                        let value = YValue::try_from(value.clone())?.0.into_inner();
+                        attributes.aset(key, value)?;
                     }
                     Some(attributes)
-                },
-            );
+                }
+                None => None,
+            };
             let ydiff = YDiff {
                 ydiff_insert: insert,
                 ydiff_attrs: attributes,
             };
-            array
-                .push(ydiff.into_value_with(&ruby))
-                .expect("cannot push diff to array");
+            array.push(ydiff.into_value_with(&ruby))?;
+        }
+        Ok(array)
+    }
+    pub(crate) fn ytext_to_delta(&self, transaction: &YTransaction) -> Result<RArray, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        // adjacent strings with equal attributes are merged into one insert
+        let mut runs: Vec<(Insert, Option<Box<Attrs>>)> = Vec::new();
+        for chunk in self.0.borrow().diff(tx, YChange::identity) {
+            let attrs = chunk.attributes.filter(|attrs| !attrs.is_empty());
+            match (runs.last_mut(), chunk.insert) {
+                (Some((Insert::Text(prev), prev_attrs)), YrsValue::Any(Any::String(s)))
+                    if *prev_attrs == attrs =>
+                {
+                    prev.push_str(&s);
+                }
+                (_, YrsValue::Any(Any::String(s))) => {
+                    runs.push((Insert::Text(s.to_string()), attrs));
+                }
+                (_, insert) => runs.push((Insert::Value(insert), attrs)),
+            }
+        }
+
+        let delta = ruby.ary_new_capa(runs.len());
+        for (insert, attrs) in runs {
+            let insert = match insert {
+                Insert::Text(text) => ruby.str_new(&text).as_value(),
+                Insert::Value(value) => YValue::try_from(value)?.0.into_inner(),
+            };
+            let op = ruby.hash_new();
+            op.aset(ruby.to_symbol("insert"), insert)?;
+            if let Some(attrs) = attrs {
+                let attributes = ruby.hash_new();
+                for (key, value) in attrs.iter() {
+                    let value = YValue::try_from(value.clone())?.0.into_inner();
+                    attributes.aset(key.to_string(), value)?;
+                }
+                op.aset(ruby.to_symbol("attributes"), attributes)?;
+            }
+            delta.push(op)?;
         }
-        array
+        Ok(delta)
     }
     pub(crate) fn ytext_format(
         &self,
@@ -55,15 +110,16 @@ impl YText {
         index: u32,
         length: u32,
         attrs: RHash,
-    ) {
+    ) -> Result<(), Error> {
+        let a = YAttrs::from_hash(attrs, transaction.conversion_options())?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        let a = YAttrs::from(attrs);
-
         self.0
             .borrow_mut()
-            .format(tx, index, length, a.0.into_inner())
+            .format(tx, index, length, a.0.into_inner());
+        Ok(())
     }
     pub(crate) fn ytext_insert(&self, transaction: &YTransaction, index: u32, chunk: String) {
         let mut tx = transaction.transaction();
@@ -76,14 +132,15 @@ impl YText {
         transaction: &YTransaction,
         index: u32,
         content: Value,
-    ) {
+    ) -> Result<(), Error> {
+        let yvalue = YValue::from(content);
+        let avalue = yvalue.try_into_any(transaction.conversion_options())?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        let yvalue = YValue::from(content);
-        let avalue = Any::from(yvalue);
-
         self.0.borrow_mut().insert_embed(tx, index, avalue);
+        Ok(())
     }
     pub(crate) fn ytext_insert_embed_with_attributes(
         &self,
@@ -91,18 +148,20 @@ impl YText {
         index: u32,
         embed: Value,
         attrs: RHash,
-    ) {
-        let mut tx = transaction.transaction();
-        let tx = tx.as_mut().unwrap();
-
+    ) -> Result<(), Error> {
+        let options = transaction.conversion_options();
         let yvalue = YValue::from(embed);
-        let avalue = Any::from(yvalue);
+        let avalue = yvalue.try_into_any(options)?;
 
-        let a = YAttrs::from(attrs);
+        let a = YAttrs::from_hash(attrs, options)?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
 
         self.0
             .borrow_mut()
             .insert_embed_with_attributes(tx, index, avalue, a.0.into_inner());
+        Ok(())
     }
     pub(crate) fn ytext_insert_with_attributes(
         &self,
@@ -110,15 +169,16 @@ impl YText {
         index: u32,
         chunk: String,
         attrs: RHash,
-    ) {
+    ) -> Result<(), Error> {
+        let a = YAttrs::from_hash(attrs, transaction.conversion_options())?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        let a = YAttrs::from(attrs);
-
         self.0
             .borrow_mut()
-            .insert_with_attributes(tx, index, chunk.as_str(), a.0.into_inner())
+            .insert_with_attributes(tx, index, chunk.as_str(), a.0.into_inner());
+        Ok(())
     }
     pub(crate) fn ytext_length(&self, transaction: &YTransaction) -> u32 {
         let tx = transaction.transaction();
@@ -127,81 +187,14 @@ impl YText {
         self.0.borrow().len(tx)
     }
     pub(crate) fn ytext_observe(&self, block: Proc) -> Result<u32, Error> {
-        let ruby = unsafe { Ruby::get_unchecked() };
-        let delta_insert = ruby.to_symbol("insert").to_static();
-        let delta_retain = ruby.to_symbol("retain").to_static();
-        let delta_delete = ruby.to_symbol("delete").to_static();
-        let attributes = ruby.to_symbol("attributes").to_static();
-
-        // let mut error: Option<Error> = None;
         let subscription_id = self
             .0
             .borrow_mut()
-            .observe(move |transaction, text_event| {
-                let ruby = unsafe { Ruby::get_unchecked() };
-                let delta = text_event.delta(transaction);
-                let (_, errors): (Vec<_>, Vec<_>) = delta
-                    .iter()
-                    .map(|change| match change {
-                        Delta::Inserted(value, attrs) => {
-                            let yvalue = YValue::from(value.clone());
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(delta_insert, yvalue.0.into_inner())
-                                .map(|()| match attrs {
-                                    Some(a) => {
-                                        let attrs_hash = ruby.hash_new();
-                                        for (key, val) in a.clone().into_iter() {
-                                            let yvalue = YValue::from(val);
-                                            attrs_hash
-                                                .aset(key.to_string(), yvalue.0.into_inner())
-                                                .expect("cannot add attr");
-                                        }
-                                        Some(attrs_hash)
-                                    }
-                                    None => None,
-                                })
-                                .map(|attrs_hash| attrs_hash.map(|v| payload.aset(attributes, v)))
-                                .map(|_| block.call::<(RHash,), Qnil>((payload,)))
-                        }
-                        Delta::Retain(index, attrs) => {
-                            let payload = ruby.hash_new();
-
-                            let yvalue = YValue::from(*index);
-
-                            payload
-                                .aset(delta_retain, yvalue.0.into_inner())
-                                .map(|()| match attrs {
-                                    Some(a) => {
-                                        let attrs_hash = ruby.hash_new();
-                                        for (key, val) in a.clone().into_iter() {
-                                            let yvalue = YValue::from(val);
-                                            attrs_hash
-                                                .aset(key.to_string(), yvalue.0.into_inner())
-                                                .expect("cannot add attr");
-                                        }
-                                        Some(attrs_hash)
-                                    }
-                                    None => None,
-                                })
-                                .map(|attrs_hash| attrs_hash.map(|v| payload.aset(attributes, v)))
-                                .map(|_| block.call::<(RHash,), Qnil>((payload,)))
-                        }
-                        Delta::Deleted(index) => {
-                            let payload = ruby.hash_new();
-
-                            let yvalue = YValue::from(*index);
-
-                            payload
-                                .aset(delta_delete, yvalue.0.into_inner())
-                                .map(|()| block.call::<(RHash,), Qnil>((payload,)))
-                        }
-                    })
-                    .partition(Result::is_ok);
-
-                if !errors.is_empty() {
-                    // todo: make sure we respect errors and let the method fail by
-                    //  by returning a Result containing an Error
+            .observe(move |transaction, event| {
+                let result = YEvent::text(transaction, event)
+                    .and_then(|event| block.call::<(Value,), Value>((event,)));
+                if let Err(error) = result {
+                    defer_error(error);
                 }
             })
             .into();
@@ -220,6 +213,35 @@ impl YText {
 
         self.0.borrow_mut().remove_range(tx, start, length)
     }
+    pub(crate) fn ytext_replace_with(&self, transaction: &YTransaction, new: String) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let text = self.0.borrow_mut();
+        let mut old = Vec::new();
+        for chunk in text.diff(tx, YChange::identity) {
+            match chunk.insert {
+                YrsValue::Any(Any::String(s)) => old.extend(s.chars().map(Token::Char)),
+                _ => old.push(Token::Embed),
+            }
+        }
+        let new: Vec<char> = new.chars().collect();
+
+        // documents always count offsets in UTF-16 code units (see
+        // `doc_options`), apply from the end, so that indices of earlier
+        // edits stay valid
+        for edit in ytext_diff::diff(&old, &new, OffsetKind::Utf16)
+            .into_iter()
+            .rev()
+        {
+            if edit.remove > 0 {
+                text.remove_range(tx, edit.index, edit.remove);
+            }
+            if !edit.insert.is_empty() {
+                text.insert(tx, edit.index, edit.insert.as_str());
+            }
+        }
+    }
     pub(crate) fn ytext_to_s(&self, transaction: &YTransaction) -> String {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
@@ -231,6 +253,150 @@ impl YText {
     }
 }
 
+/// The insert of an operation returned by [YText::ytext_to_delta].
+enum Insert {
+    Text(String),
+    Value(YrsValue),
+}
+
+/// Parses a Quill delta, an array of `{insert:, attributes:}`, `{retain:,
+/// attributes:}` and `{delete:}` operations with Symbol or String keys.
+/// Inserts are strings or embeds, attributes with a `nil` value are removed.
+pub(crate) fn parse_delta(delta: RArray, options: ConversionOptions) -> Result<Vec<Delta>, Error> {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    let invalid = |op: Value| {
+        Error::new(
+            ruby.exception_arg_error(),
+            format!("invalid delta operation: {}", op.inspect()),
+        )
+    };
+
+    delta
+        .into_iter()
+        .map(|op| {
+            let hash = RHash::from_value(op).ok_or_else(|| invalid(op))?;
+            let get = |key: &str| -> Option<Value> {
+                hash.get(ruby.to_symbol(key))
+                    .or_else(|| hash.get(key))
+                    .filter(|value| !value.is_nil())
+            };
+            let attrs = match get("attributes") {
+                Some(attrs) => {
+                    let attrs = RHash::from_value(attrs).ok_or_else(|| invalid(op))?;
+                    Some(Box::new(YAttrs::from_hash(attrs, options)?.0.into_inner()))
+                }
+                None => None,
+            };
+
+            if let Some(insert) = get("insert") {
+                let value = YValue::from(insert).try_into_any(options)?;
+                Ok(Delta::Inserted(YrsValue::Any(value), attrs))
+            } else if let Some(retain) = get("retain") {
+                let length = u32::try_convert(retain)?;
+                Ok(Delta::Retain(length, attrs))
+            } else if let Some(delete) = get("delete") {
+                let length = u32::try_convert(delete)?;
+                Ok(Delta::Deleted(length))
+            } else {
+                Err(invalid(op))
+            }
+        })
+        .collect()
+}
+
+/// Applies the operations of a delta parsed by [parse_delta] one by one, like
+/// `Y.Text#applyDelta` does. Inserts are formatted with exactly the given
+/// attributes, retains with attributes format the retained range.
+///
+/// The ranges of all operations are checked before the text is changed, a
+/// delta that exceeds the text leaves it untouched.
+pub(crate) fn apply_delta<T: Text>(
+    text: &T,
+    txn: &mut TransactionMut,
+    delta: Vec<Delta>,
+) -> Result<(), Error> {
+    check_delta(&delta, text.len(txn))?;
+
+    let mut index = 0;
+    for op in delta {
+        match op {
+            Delta::Inserted(value, attrs) => {
+                let attrs = attrs.map_or_else(Attrs::new, |attrs| *attrs);
+                let inserted = inserted_len(&value);
+                match value {
+                    YrsValue::Any(Any::String(chunk)) => {
+                        text.insert_with_attributes(txn, index, &chunk, attrs);
+                    }
+                    YrsValue::Any(embed) => {
+                        text.insert_embed_with_attributes(txn, index, embed, attrs);
+                    }
+                    _ => continue,
+                }
+                index += inserted;
+            }
+            Delta::Retain(retain, attrs) => {
+                if let Some(attrs) = attrs {
+                    text.format(txn, index, retain, *attrs);
+                }
+                index += retain;
+            }
+            Delta::Deleted(delete) => text.remove_range(txn, index, delete),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the retains and deletes of a delta stay within a text of the
+/// given length.
+fn check_delta(delta: &[Delta], mut length: u32) -> Result<(), Error> {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    let out_of_range = |length: u32| {
+        Error::new(
+            ruby.exception_arg_error(),
+            format!("delta exceeds the length of the text ({})", length),
+        )
+    };
+    let exceeds = |index: u32, range: u32, length: u32| {
+        index
+            .checked_add(range)
+            .filter(|end| *end <= length)
+            .is_none()
+    };
+
+    let mut index: u32 = 0;
+    for op in delta {
+        match op {
+            Delta::Inserted(value, _attrs) => {
+                let inserted = inserted_len(value);
+                index += inserted;
+                length += inserted;
+            }
+            Delta::Retain(retain, _attrs) => {
+                if exceeds(index, *retain, length) {
+                    return Err(out_of_range(length));
+                }
+                index += retain;
+            }
+            Delta::Deleted(delete) => {
+                if exceeds(index, *delete, length) {
+                    return Err(out_of_range(length));
+                }
+                length -= delete;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the length an insert adds to the text, in UTF-16 code units.
+fn inserted_len(value: &YrsValue) -> u32 {
+    match value {
+        YrsValue::Any(Any::String(chunk)) => chunk.encode_utf16().count() as u32,
+        YrsValue::Any(_embed) => 1,
+        _ => 0,
+    }
+}
+
 impl From<TextRef> for YText {
     fn from(v: TextRef) -> Self {
         YText(RefCell::from(v))
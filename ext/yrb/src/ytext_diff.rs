@@ -0,0 +1,228 @@
+use yrs::OffsetKind;
+
+/// Maximum number of edits searched for by the diff. Texts that differ in
+/// more characters are replaced from the first to the last change instead.
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+/// A single unit of existing text, either a character or an embed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    Char(char),
+    Embed,
+}
+
+impl Token {
+    /// Length of the token in the given offset unit. Embeds always have a
+    /// length of 1.
+    pub(crate) fn len(&self, offset_kind: OffsetKind) -> u32 {
+        match (self, offset_kind) {
+            (Token::Embed, _) => 1,
+            (Token::Char(c), OffsetKind::Bytes) => c.len_utf8() as u32,
+            (Token::Char(c), OffsetKind::Utf16) => c.len_utf16() as u32,
+        }
+    }
+}
+
+/// Replaces `remove` units at `index` with `insert`. Indices refer to the old
+/// text, edits are ordered by index and do not overlap.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct TextEdit {
+    pub(crate) index: u32,
+    pub(crate) remove: u32,
+    pub(crate) insert: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operation {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes the edits that turn `old` into `new` with Myers' algorithm.
+pub(crate) fn diff(old: &[Token], new: &[char], offset_kind: OffsetKind) -> Vec<TextEdit> {
+    let eq = |x: usize, y: usize| old[x] == Token::Char(new[y]);
+
+    let prefix = (0..old.len().min(new.len()))
+        .take_while(|&i| eq(i, i))
+        .count();
+    let suffix = (0..old.len().min(new.len()) - prefix)
+        .take_while(|&i| eq(old.len() - 1 - i, new.len() - 1 - i))
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let script = shortest_edit(old_middle.len(), new_middle.len(), |x, y| {
+        old_middle[x] == Token::Char(new_middle[y])
+    })
+    .unwrap_or_else(|| {
+        let mut script = vec![Operation::Delete; old_middle.len()];
+        script.extend(vec![Operation::Insert; new_middle.len()]);
+        script
+    });
+
+    let mut edits = Vec::new();
+    let mut index: u32 = old[..prefix].iter().map(|t| t.len(offset_kind)).sum();
+    let mut edit = TextEdit {
+        index,
+        ..TextEdit::default()
+    };
+    let (mut x, mut y) = (0, 0);
+    for operation in script {
+        match operation {
+            Operation::Equal => {
+                if edit.remove > 0 || !edit.insert.is_empty() {
+                    edits.push(edit);
+                }
+                index += old_middle[x].len(offset_kind);
+                edit = TextEdit {
+                    index,
+                    ..TextEdit::default()
+                };
+                x += 1;
+                y += 1;
+            }
+            Operation::Delete => {
+                let len = old_middle[x].len(offset_kind);
+                edit.remove += len;
+                index += len;
+                x += 1;
+            }
+            Operation::Insert => {
+                edit.insert.push(new_middle[y]);
+                y += 1;
+            }
+        }
+    }
+    if edit.remove > 0 || !edit.insert.is_empty() {
+        edits.push(edit);
+    }
+    edits
+}
+
+/// Finds the shortest edit script for sequences of length `n` and `m`.
+/// Returns `None` if it needs more than [MAX_EDIT_DISTANCE] edits.
+fn shortest_edit<F>(n: usize, m: usize, eq: F) -> Option<Vec<Operation>>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let max = (n + m).min(MAX_EDIT_DISTANCE) as isize;
+    let (n, m) = (n as isize, m as isize);
+    // `v[k]` is the furthest x reached on diagonal k, `trace[d]` holds the
+    // diagonals -d..=d before step d
+    let mut v: Vec<isize> = vec![0; 2 * max as usize + 2];
+    let offset = max;
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut found = false;
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| v[(k + offset) as usize];
+            let mut x = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                at(k + 1)
+            } else {
+                at(k - 1) + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && eq(x as usize, y as usize) {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // diagonals in `v` are shifted by d - 1, it was recorded before step d
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = at(prev_k);
+            (prev_x, prev_x - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            script.push(Operation::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                script.push(Operation::Insert);
+                y -= 1;
+            } else {
+                script.push(Operation::Delete);
+                x -= 1;
+            }
+        }
+    }
+    script.reverse();
+    Some(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ytext_diff::{diff, TextEdit, Token};
+    use yrs::OffsetKind;
+
+    fn tokens(s: &str) -> Vec<Token> {
+        s.chars().map(Token::Char).collect()
+    }
+
+    fn apply(old: &str, edits: &[TextEdit]) -> String {
+        let mut chars: Vec<char> = old.chars().collect();
+        for edit in edits.iter().rev() {
+            let (index, remove) = (edit.index as usize, edit.remove as usize);
+            chars.splice(index..index + remove, edit.insert.chars());
+        }
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn diff_changed_word() {
+        let new: Vec<char> = "Hello Ruby".chars().collect();
+        let edits = diff(&tokens("Hello World"), &new, OffsetKind::Utf16);
+
+        assert_eq!(apply("Hello World", &edits), "Hello Ruby");
+        assert!(edits.iter().all(|edit| edit.index >= 6));
+    }
+
+    #[test]
+    fn diff_identical_text() {
+        let new: Vec<char> = "same".chars().collect();
+
+        assert!(diff(&tokens("same"), &new, OffsetKind::Utf16).is_empty());
+    }
+
+    #[test]
+    fn diff_utf16_offsets() {
+        let new: Vec<char> = "😀 b".chars().collect();
+        let edits = diff(&tokens("😀 a"), &new, OffsetKind::Utf16);
+
+        assert_eq!(
+            edits,
+            vec![TextEdit {
+                index: 3,
+                remove: 1,
+                insert: "b".to_string()
+            }]
+        );
+    }
+}
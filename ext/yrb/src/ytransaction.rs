@@ -1,59 +1,121 @@
+use crate::utils::catch_observer_errors;
 use crate::yarray::YArray;
 use crate::ymap::YMap;
 use crate::ytext::YText;
+use crate::yupdate::{check_access, AccessAction, DecodedUpdate, PendingUpdates};
+use crate::yvalue::ConversionOptions;
 use crate::yxml_element::YXmlElement;
 use crate::yxml_fragment::YXmlFragment;
 use crate::ysnapshot::YSnapshot;
 use crate::yxml_text::YXmlText;
-use magnus::{Error, Ruby};
+use magnus::{Error, RArray, RHash, Ruby};
 use std::cell::{RefCell, RefMut};
-use yrs::updates::decoder::Decode;
+use std::rc::Rc;
+use yrs::block::ClientID;
+use yrs::encoding::read::Error as ReadError;
 use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
-use yrs::{ReadTxn, TransactionMut, Update};
+use yrs::{ReadTxn, TransactionMut};
 
 #[magnus::wrap(class = "Y::Transaction")]
-pub(crate) struct YTransaction(pub(crate) RefCell<Option<TransactionMut<'static>>>);
+pub(crate) struct YTransaction(
+    pub(crate) RefCell<Option<TransactionMut<'static>>>,
+    pub(crate) ConversionOptions,
+    pub(crate) Rc<RefCell<PendingUpdates>>,
+);
 
 /// SAFETY: This is safe because we only access this data when the GVL is held.
 unsafe impl Send for YTransaction {}
 
-impl YTransaction {}
+impl YTransaction {
+    pub(crate) fn with_conversion_options(mut self, options: ConversionOptions) -> Self {
+        self.1 = options;
+        self
+    }
+
+    /// Shares the pending updates of the document with the transaction.
+    pub(crate) fn with_pending_updates(mut self, pending: Rc<RefCell<PendingUpdates>>) -> Self {
+        self.2 = pending;
+        self
+    }
+}
 
 impl<'doc> From<TransactionMut<'doc>> for YTransaction {
     fn from(txn: TransactionMut<'doc>) -> Self {
         let txn: TransactionMut<'static> = unsafe { std::mem::transmute(txn) };
-        YTransaction(RefCell::from(Some(txn)))
+        YTransaction(
+            RefCell::from(Some(txn)),
+            ConversionOptions::default(),
+            Rc::default(),
+        )
     }
 }
 
 // API which is eventually publicly exposed
 impl YTransaction {
-    pub(crate) fn ytransaction_apply_update(&self, update: Vec<u8>) -> Result<(), Error> {
-        let ruby = Ruby::get().unwrap();
-        Update::decode_v1(update.as_slice())
-            .map_err(|error| {
-                Error::new(
-                    ruby.exception_runtime_error(),
-                    format!("cannot decode update: {:?}", error),
-                )
-            })
-            .map(|u| self.transaction().as_mut().unwrap().apply_update(u))
+    pub(crate) fn ytransaction_apply_update(&self, update: Vec<u8>) -> Result<RHash, Error> {
+        self.apply_and_report(|pending, txn| pending.apply_v1(txn, update.as_slice()))
     }
 
-    pub(crate) fn ytransaction_apply_update_v2(&self, update: Vec<u8>) -> Result<(), Error> {
+    pub(crate) fn ytransaction_apply_update_v2(&self, update: Vec<u8>) -> Result<RHash, Error> {
+        self.apply_and_report(|pending, txn| pending.apply_v2(txn, update.as_slice()))
+    }
+
+    /// Returns the changes of the update that touch root types which are not
+    /// in the list of allowed root types, without applying the update.
+    pub(crate) fn ytransaction_update_violations(
+        &self,
+        update: Vec<u8>,
+        allow: Vec<String>,
+    ) -> Result<RArray, Error> {
+        self.update_violations(DecodedUpdate::decode_v1(update.as_slice()), allow)
+    }
+
+    /// Like [YTransaction::ytransaction_update_violations], for v2 updates.
+    pub(crate) fn ytransaction_update_violations_v2(
+        &self,
+        update: Vec<u8>,
+        allow: Vec<String>,
+    ) -> Result<RArray, Error> {
+        self.update_violations(DecodedUpdate::decode_v2(update.as_slice()), allow)
+    }
+
+    fn update_violations(
+        &self,
+        decoded: Result<DecodedUpdate, ReadError>,
+        allow: Vec<String>,
+    ) -> Result<RArray, Error> {
         let ruby = Ruby::get().unwrap();
-        Update::decode_v2(update.as_slice())
-            .map_err(|error| {
-                Error::new(
-                    ruby.exception_runtime_error(),
-                    format!("cannot decode update: {:?}", error),
-                )
-            })
-            .map(|u| self.transaction().as_mut().unwrap().apply_update(u))
+        let decoded = decoded.map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode update: {:?}", error),
+            )
+        })?;
+
+        let txn = self.transaction();
+        let txn = txn.as_ref().unwrap();
+
+        let violations = ruby.ary_new();
+        for violation in check_access(txn, &decoded, allow.as_slice()) {
+            let action = match violation.action {
+                AccessAction::Insert => "insert",
+                AccessAction::Delete => "delete",
+            };
+            let entry = ruby.hash_new();
+            entry.aset(
+                ruby.to_symbol("root"),
+                violation.root.map(|root| root.to_string()),
+            )?;
+            entry.aset(ruby.to_symbol("client"), violation.id.client)?;
+            entry.aset(ruby.to_symbol("clock"), violation.id.clock)?;
+            entry.aset(ruby.to_symbol("action"), ruby.to_symbol(action))?;
+            violations.push(entry)?;
+        }
+        Ok(violations)
     }
 
-    pub(crate) fn ytransaction_commit(&self) {
-        self.transaction().as_mut().unwrap().commit();
+    pub(crate) fn ytransaction_commit(&self) -> Result<(), Error> {
+        catch_observer_errors(|| self.transaction().as_mut().unwrap().commit())
     }
 
     pub(crate) fn ytransaction_get_array(&self, name: String) -> Option<YArray> {
@@ -146,8 +208,85 @@ impl YTransaction {
             })
     }
 
-    pub(crate) fn ytransaction_free(&self) {
-        self.0.replace(None);
+    pub(crate) fn ytransaction_is_pending(&self) -> bool {
+        self.2.borrow().is_pending()
+    }
+
+    pub(crate) fn ytransaction_missing_state_vector(&self) -> Option<Vec<u8>> {
+        self.2.borrow().missing().map(|missing| missing.encode_v1())
+    }
+
+    /// Encodes the blocks and deletions that are waiting for missing
+    /// dependencies into a single update.
+    pub(crate) fn ytransaction_pending_update(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.2.borrow().update().map_err(|error| {
+            Error::new(
+                Ruby::get().unwrap().exception_runtime_error(),
+                format!("cannot encode pending update: {:?}", error),
+            )
+        })
+    }
+
+    pub(crate) fn ytransaction_free(&self) -> Result<(), Error> {
+        // dropping the transaction commits it
+        catch_observer_errors(|| drop(self.0.replace(None)))
+    }
+
+    /// Applies the update and reports the clock ranges that were integrated,
+    /// and whether parts of the update are waiting for missing updates.
+    fn apply_and_report<F>(&self, apply: F) -> Result<RHash, Error>
+    where
+        F: FnOnce(&mut PendingUpdates, &mut TransactionMut<'static>) -> Result<(), ReadError>,
+    {
+        let ruby = Ruby::get().unwrap();
+        let mut txn = self.transaction();
+        let txn = txn.as_mut().unwrap();
+        let mut pending = self.2.borrow_mut();
+
+        let before = txn.state_vector();
+        apply(&mut pending, txn).map_err(|error| {
+            Error::new(
+                ruby.exception_runtime_error(),
+                format!("cannot decode update: {:?}", error),
+            )
+        })?;
+        let after = txn.state_vector();
+
+        let mut clients: Vec<(ClientID, u32)> = after
+            .iter()
+            .map(|(client, clock)| (*client, *clock))
+            .filter(|(client, clock)| before.get(client) < *clock)
+            .collect();
+        clients.sort_unstable_by_key(|(client, _clock)| *client);
+
+        let integrated = ruby.ary_new_capa(clients.len());
+        for (client, clock) in clients {
+            let entry = ruby.hash_new();
+            entry.aset(ruby.to_symbol("client"), client)?;
+            entry.aset(
+                ruby.to_symbol("clock"),
+                ruby.range_new(before.get(&client), clock, true)?,
+            )?;
+            integrated.push(entry)?;
+        }
+
+        let missing = ruby.hash_new();
+        if let Some(state_vector) = pending.missing() {
+            for (client, clock) in state_vector.iter() {
+                missing.aset(*client, *clock)?;
+            }
+        }
+
+        let report = ruby.hash_new();
+        report.aset(ruby.to_symbol("integrated"), integrated)?;
+        report.aset(ruby.to_symbol("pending"), pending.is_pending())?;
+        report.aset(ruby.to_symbol("missing"), missing)?;
+        Ok(report)
+    }
+
+    /// Options used to convert Ruby values that are stored in the document.
+    pub(crate) fn conversion_options(&self) -> ConversionOptions {
+        self.1
     }
 
     pub(crate) fn transaction(&self) -> RefMut<'_, Option<TransactionMut<'static>>> {
@@ -36,7 +36,7 @@ impl YUndoManager {
             ..Options::default()
         };
 
-        let manager: undo::UndoManager<Vec<u8>> = undo::UndoManager::with_options(&*doc_ref, &*frag_ref, options);
+        let manager: undo::UndoManager<Vec<u8>> = undo::UndoManager::with_options(&doc_ref, &*frag_ref, options);
 
         let state = Arc::new(Mutex::new(ObserverState {
             undo_metas: Vec::new(),
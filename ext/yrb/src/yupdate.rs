@@ -0,0 +1,1106 @@
+use magnus::{Error, IntoValue, RHash, Ruby, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use yrs::block::{
+    ClientID, ItemContent, BLOCK_GC_REF_NUMBER, BLOCK_SKIP_REF_NUMBER, HAS_ORIGIN, HAS_PARENT_SUB,
+    HAS_RIGHT_ORIGIN, ID,
+};
+use yrs::encoding::read::{Cursor, Error as ReadError};
+use yrs::types::TypeRef;
+use yrs::updates::decoder::{Decode, Decoder, DecoderV1, DecoderV2};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{
+    Any, Assoc, DeleteSet, IndexScope, OffsetKind, ReadTxn, StateVector, StickyIndex,
+    TransactionMut, Update,
+};
+
+/// Maximum number of characters of a content preview.
+const PREVIEW_LENGTH: usize = 32;
+
+/// An update decoded into its blocks without integrating it into a document.
+/// This allows to inspect what an update is going to change before it is
+/// applied.
+///
+/// yrs keeps the blocks of a decoded `Update` private, so the block headers
+/// are read here, following the framing of `Update::decode`. The content of
+/// items is decoded by yrs itself.
+pub(crate) struct DecodedUpdate {
+    pub(crate) clients: Vec<(ClientID, Vec<DecodedBlock>)>,
+    pub(crate) delete_set: DeleteSet,
+}
+
+pub(crate) struct DecodedBlock {
+    pub(crate) id: ID,
+    pub(crate) len: u32,
+    pub(crate) kind: BlockKind,
+}
+
+pub(crate) enum BlockKind {
+    GC,
+    Skip,
+    Item(DecodedItem),
+}
+
+pub(crate) struct DecodedItem {
+    pub(crate) origin: Option<ID>,
+    pub(crate) right_origin: Option<ID>,
+    pub(crate) parent: BlockParent,
+    pub(crate) parent_sub: Option<Arc<str>>,
+    pub(crate) content: ItemContent,
+}
+
+pub(crate) enum BlockParent {
+    /// The item is a direct child of the named root type.
+    Root(Arc<str>),
+    /// The item is a child of the type stored in the item with this ID.
+    Nested(ID),
+    /// The parent is not encoded, it is the same as the one of the origin
+    /// (or right origin) of the item.
+    Inherited,
+}
+
+impl DecodedUpdate {
+    pub(crate) fn decode_v1(data: &[u8]) -> Result<Self, ReadError> {
+        Self::decode(&mut DecoderV1::from(data))
+    }
+
+    pub(crate) fn decode_v2(data: &[u8]) -> Result<Self, ReadError> {
+        Self::decode(&mut DecoderV2::new(Cursor::new(data))?)
+    }
+
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, ReadError> {
+        let clients_len: u32 = decoder.read_var()?;
+        let mut clients = Vec::with_capacity(clients_len as usize);
+        for _ in 0..clients_len {
+            let blocks_len: u32 = decoder.read_var()?;
+            let client = decoder.read_client()?;
+            let mut clock: u32 = decoder.read_var()?;
+
+            let mut blocks = Vec::with_capacity(blocks_len as usize);
+            for _ in 0..blocks_len {
+                let id = ID::new(client, clock);
+                let block = match decoder.read_info()? {
+                    BLOCK_GC_REF_NUMBER => DecodedBlock {
+                        id,
+                        len: decoder.read_len()?,
+                        kind: BlockKind::GC,
+                    },
+                    BLOCK_SKIP_REF_NUMBER => DecodedBlock {
+                        id,
+                        len: decoder.read_var()?,
+                        kind: BlockKind::Skip,
+                    },
+                    info => {
+                        let item = DecodedItem::decode(decoder, info)?;
+                        DecodedBlock {
+                            id,
+                            len: item.content.len(OffsetKind::Utf16),
+                            kind: BlockKind::Item(item),
+                        }
+                    }
+                };
+                clock += block.len;
+                blocks.push(block);
+            }
+            clients.push((client, blocks));
+        }
+
+        let delete_set = DeleteSet::decode(decoder)?;
+        Ok(Self {
+            clients,
+            delete_set,
+        })
+    }
+
+    pub(crate) fn encode_v1(&self) -> Vec<u8> {
+        let mut encoder = EncoderV1::new();
+        self.encode(&mut encoder);
+        encoder.to_vec()
+    }
+
+    /// Encodes the blocks and the delete set, following the framing of
+    /// `Update::encode`. Gaps between the blocks of a client are encoded as
+    /// skip blocks.
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        encoder.write_var(self.clients.len());
+        for (client, blocks) in self.clients.iter() {
+            let mut runs = Vec::with_capacity(blocks.len());
+            let mut clock = blocks.first().map_or(0, |block| block.id.clock);
+            for block in blocks {
+                if block.id.clock > clock {
+                    runs.push(Run::Skip(block.id.clock - clock));
+                }
+                runs.push(Run::Block(block));
+                clock = block.id.clock + block.len;
+            }
+
+            encoder.write_var(runs.len());
+            encoder.write_client(*client);
+            encoder.write_var(blocks.first().map_or(0, |block| block.id.clock));
+            for run in runs {
+                match run {
+                    Run::Skip(len) => {
+                        encoder.write_info(BLOCK_SKIP_REF_NUMBER);
+                        encoder.write_var(len);
+                    }
+                    Run::Block(block) => block.encode(encoder),
+                }
+            }
+        }
+        self.delete_set.encode(encoder);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.clients
+            .iter()
+            .all(|(_client, blocks)| blocks.is_empty())
+            && self.delete_set.is_empty()
+    }
+
+    /// Removes the blocks and deletions that are covered by the state vector,
+    /// what remains is ahead of it. Blocks that are partially covered are
+    /// kept, yrs integrates them from the offset of the known state.
+    fn retain_ahead_of(&mut self, state: &StateVector) {
+        for (client, blocks) in self.clients.iter_mut() {
+            let clock = state.get(client);
+            blocks.retain(|block| {
+                block.id.clock + block.len > clock && !matches!(block.kind, BlockKind::Skip)
+            });
+        }
+        self.clients.retain(|(_client, blocks)| !blocks.is_empty());
+
+        let mut delete_set = DeleteSet::new();
+        for (client, ranges) in self.delete_set.iter() {
+            let clock = state.get(client);
+            for range in ranges.iter() {
+                let start = range.start.max(clock);
+                if start < range.end {
+                    delete_set.insert(ID::new(*client, start), range.end - start);
+                }
+            }
+        }
+        self.delete_set = delete_set;
+    }
+
+    /// Returns the clock from which each client has to send its changes, so
+    /// that the blocks of the update can be integrated: the blocks of a
+    /// client must follow its known state, and the origins and parents of
+    /// the blocks must be known.
+    fn missing(&self, state: &StateVector) -> StateVector {
+        let mut missing = StateVector::default();
+        for (client, blocks) in self.clients.iter() {
+            if blocks
+                .first()
+                .is_some_and(|block| block.id.clock > state.get(client))
+            {
+                missing.set_min(*client, state.get(client));
+            }
+
+            for block in blocks {
+                let BlockKind::Item(item) = &block.kind else {
+                    continue;
+                };
+                let parent = match &item.parent {
+                    BlockParent::Nested(id) => Some(id),
+                    _ => None,
+                };
+                for dependency in [item.origin.as_ref(), item.right_origin.as_ref(), parent]
+                    .into_iter()
+                    .flatten()
+                {
+                    let known = state.get(&dependency.client);
+                    if dependency.client != *client && dependency.clock >= known {
+                        missing.set_min(dependency.client, known);
+                    }
+                }
+            }
+        }
+        missing
+    }
+
+    /// Maps the clients of the update to their position in `clients`.
+    fn index(&self) -> HashMap<ClientID, usize> {
+        self.clients
+            .iter()
+            .enumerate()
+            .map(|(position, (client, _blocks))| (*client, position))
+            .collect()
+    }
+
+    /// Returns the position of the block that contains the given ID.
+    fn find(&self, index: &HashMap<ClientID, usize>, id: &ID) -> Option<(usize, usize)> {
+        let client = *index.get(&id.client)?;
+        let blocks = &self.clients[client].1;
+        let position = blocks.partition_point(|block| block.id.clock + block.len <= id.clock);
+        blocks
+            .get(position)
+            .filter(|block| block.id.clock <= id.clock)
+            .map(|_block| (client, position))
+    }
+}
+
+enum Run<'a> {
+    Skip(u32),
+    Block(&'a DecodedBlock),
+}
+
+impl DecodedBlock {
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        match &self.kind {
+            BlockKind::GC => {
+                encoder.write_info(BLOCK_GC_REF_NUMBER);
+                encoder.write_len(self.len);
+            }
+            BlockKind::Skip => {
+                encoder.write_info(BLOCK_SKIP_REF_NUMBER);
+                encoder.write_var(self.len);
+            }
+            BlockKind::Item(item) => item.encode(encoder),
+        }
+    }
+}
+
+impl DecodedItem {
+    fn encode<E: Encoder>(&self, encoder: &mut E) {
+        let mut info = self.content.get_ref_number();
+        if self.origin.is_some() {
+            info |= HAS_ORIGIN;
+        }
+        if self.right_origin.is_some() {
+            info |= HAS_RIGHT_ORIGIN;
+        }
+        if self.parent_sub.is_some() {
+            info |= HAS_PARENT_SUB;
+        }
+        encoder.write_info(info);
+
+        if let Some(origin) = self.origin.as_ref() {
+            encoder.write_left_id(origin);
+        }
+        if let Some(right_origin) = self.right_origin.as_ref() {
+            encoder.write_right_id(right_origin);
+        }
+        match &self.parent {
+            BlockParent::Root(name) => {
+                encoder.write_parent_info(true);
+                encoder.write_string(name);
+            }
+            BlockParent::Nested(id) => {
+                encoder.write_parent_info(false);
+                encoder.write_left_id(id);
+            }
+            // decoded items only inherit the parent when they have an origin
+            BlockParent::Inherited => {}
+        }
+        if let Some(parent_sub) = self.parent_sub.as_ref() {
+            encoder.write_string(parent_sub);
+        }
+
+        self.content.encode(encoder);
+    }
+
+    fn decode<D: Decoder>(decoder: &mut D, info: u8) -> Result<Self, ReadError> {
+        let origin = if info & HAS_ORIGIN != 0 {
+            Some(decoder.read_left_id()?)
+        } else {
+            None
+        };
+        let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
+            Some(decoder.read_right_id()?)
+        } else {
+            None
+        };
+
+        // the parent is only encoded when it cannot be copied from an origin
+        let has_parent_info = info & (HAS_ORIGIN | HAS_RIGHT_ORIGIN) == 0;
+        let parent = if !has_parent_info {
+            BlockParent::Inherited
+        } else if decoder.read_parent_info()? {
+            BlockParent::Root(Arc::from(decoder.read_string()?))
+        } else {
+            BlockParent::Nested(decoder.read_left_id()?)
+        };
+        let parent_sub = if has_parent_info && info & HAS_PARENT_SUB != 0 {
+            Some(Arc::from(decoder.read_string()?))
+        } else {
+            None
+        };
+
+        let content = ItemContent::decode(decoder, info)?;
+        Ok(Self {
+            origin,
+            right_origin,
+            parent,
+            parent_sub,
+            content,
+        })
+    }
+}
+
+/// Keeps the blocks and deletions of applied updates that could not be
+/// integrated yet, because they depend on changes the document has not seen.
+///
+/// yrs stores them in its private block store, so a copy is tracked per
+/// document: after an update is applied, everything of it ahead of the state
+/// vector is kept decoded, together with the clocks it waits for. A pending
+/// update is applied again once the document is ahead of one of these clocks,
+/// as yrs drops deletions of unknown clients and does not retry blocks that
+/// follow a gap of their own client.
+#[derive(Default)]
+pub(crate) struct PendingUpdates {
+    updates: Vec<PendingUpdate>,
+    /// The state vector the pending blocks depend on.
+    missing: Option<StateVector>,
+    /// The earliest clock per client that one of the pending updates waits
+    /// for.
+    retry: StateVector,
+}
+
+struct PendingUpdate {
+    update: DecodedUpdate,
+    /// The state vector the blocks of the update depend on.
+    missing: StateVector,
+    /// The clock per client the update waits for, this includes the clients
+    /// of pending deletions.
+    retry: StateVector,
+}
+
+impl PendingUpdates {
+    pub(crate) fn apply_v1(
+        &mut self,
+        txn: &mut TransactionMut,
+        update: &[u8],
+    ) -> Result<(), ReadError> {
+        let decoded = DecodedUpdate::decode_v1(update)?;
+        txn.apply_update(Update::decode_v1(update)?);
+        self.track(txn, decoded)
+    }
+
+    pub(crate) fn apply_v2(
+        &mut self,
+        txn: &mut TransactionMut,
+        update: &[u8],
+    ) -> Result<(), ReadError> {
+        let decoded = DecodedUpdate::decode_v2(update)?;
+        txn.apply_update(Update::decode_v2(update)?);
+        self.track(txn, decoded)
+    }
+
+    pub(crate) fn is_pending(&self) -> bool {
+        !self.updates.is_empty()
+    }
+
+    /// Encodes the pending blocks and deletions into a single v1 update.
+    pub(crate) fn update(&self) -> Result<Option<Vec<u8>>, ReadError> {
+        if self.updates.is_empty() {
+            return Ok(None);
+        }
+        let updates = self
+            .updates
+            .iter()
+            .map(|pending| Update::decode_v1(pending.update.encode_v1().as_slice()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(Update::merge_updates(updates).encode_v1()))
+    }
+
+    pub(crate) fn missing(&self) -> Option<&StateVector> {
+        self.missing.as_ref()
+    }
+
+    /// Keeps what was not integrated of an applied update, and applies the
+    /// pending updates again as long as the document catches up with changes
+    /// they wait for.
+    fn track(&mut self, txn: &mut TransactionMut, update: DecodedUpdate) -> Result<(), ReadError> {
+        let mut state = txn.state_vector();
+        self.push(update, &state);
+
+        while is_ahead(&state, &self.retry) {
+            let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut self.updates)
+                .into_iter()
+                .partition(|pending| is_ahead(&state, &pending.retry));
+            self.missing = None;
+            self.retry = StateVector::default();
+            for pending in waiting {
+                self.insert(pending);
+            }
+            // an update waits for the state right after it was applied, the
+            // updates applied after it may be what it depends on
+            for pending in ready {
+                txn.apply_update(Update::decode_v1(pending.update.encode_v1().as_slice())?);
+                state = txn.state_vector();
+                self.push(pending.update, &state);
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, mut update: DecodedUpdate, state: &StateVector) {
+        update.retain_ahead_of(state);
+        if update.is_empty() {
+            return;
+        }
+
+        let missing = update.missing(state);
+        let mut retry = missing.clone();
+        for (client, _ranges) in update.delete_set.iter() {
+            retry.set_min(*client, state.get(client));
+        }
+        self.insert(PendingUpdate {
+            update,
+            missing,
+            retry,
+        });
+    }
+
+    fn insert(&mut self, pending: PendingUpdate) {
+        let has_blocks = !pending.update.clients.is_empty();
+        if has_blocks {
+            let missing = self.missing.get_or_insert_with(StateVector::default);
+            for (client, clock) in pending.missing.iter() {
+                missing.set_min(*client, *clock);
+            }
+        }
+        for (client, clock) in pending.retry.iter() {
+            self.retry.set_min(*client, *clock);
+        }
+        self.updates.push(pending);
+    }
+}
+
+/// Returns whether the state is ahead of one of the clocks.
+fn is_ahead(state: &StateVector, clocks: &StateVector) -> bool {
+    clocks
+        .iter()
+        .any(|(client, clock)| state.get(client) > *clock)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AccessAction {
+    Insert,
+    Delete,
+}
+
+/// A change in an update that touches a root type which is not allowed.
+/// `root` is `None` when the root type of the change cannot be determined,
+/// e.g. because the update depends on blocks the document does not have yet.
+pub(crate) struct AccessViolation {
+    pub(crate) root: Option<Arc<str>>,
+    pub(crate) id: ID,
+    pub(crate) action: AccessAction,
+}
+
+/// Checks which blocks of the update touch root types (or types nested
+/// beneath them) that are not in the list of allowed root types. Deleting
+/// blocks that are already deleted in the document is not a violation, as
+/// every update carries the complete delete set of the sender.
+pub(crate) fn check_access<T: ReadTxn>(
+    txn: &T,
+    update: &DecodedUpdate,
+    allow: &[String],
+) -> Vec<AccessViolation> {
+    let allowed: HashSet<&str> = allow.iter().map(String::as_str).collect();
+    let is_allowed = |root: &Option<Arc<str>>| {
+        root.as_ref()
+            .is_some_and(|root| allowed.contains(root.as_ref()))
+    };
+
+    let mut resolver = RootResolver::new(txn, update);
+    let mut violations = Vec::new();
+    for (client, (_client_id, blocks)) in update.clients.iter().enumerate() {
+        for (position, block) in blocks.iter().enumerate() {
+            if let BlockKind::Item(_) = block.kind {
+                let root = resolver.resolve((client, position));
+                if !is_allowed(&root) {
+                    violations.push(AccessViolation {
+                        root,
+                        id: block.id,
+                        action: AccessAction::Insert,
+                    });
+                }
+            }
+        }
+    }
+
+    if update.delete_set.is_empty() {
+        return violations;
+    }
+
+    // deletions of blocks contained in the update are covered by the checks
+    // above, only blocks that already exist in the document remain. These
+    // are read from a diff of the document that starts at the first deleted
+    // clock of every affected client.
+    let first_deleted: HashMap<ClientID, u32> = update
+        .delete_set
+        .iter()
+        .filter_map(|(client, ranges)| {
+            let start = ranges.iter().map(|range| range.start).min()?;
+            Some((*client, start))
+        })
+        .collect();
+    let mut since = StateVector::default();
+    for (client, clock) in txn.state_vector().iter() {
+        let clock = first_deleted
+            .get(client)
+            .map_or(*clock, |start| *start.min(clock));
+        // a client that is missing from the state vector is diffed from 0
+        if clock > 0 {
+            since.set_max(*client, clock);
+        }
+    }
+    let Ok(existing) = DecodedUpdate::decode_v1(txn.encode_diff_v1(&since).as_slice()) else {
+        return violations;
+    };
+
+    let document_deletions = txn.snapshot().delete_set;
+    let existing_index = existing.index();
+    let update_index = update.index();
+    let mut resolver = RootResolver::new(txn, &existing);
+    for (client, ranges) in update.delete_set.iter() {
+        let Some(&position) = existing_index.get(client) else {
+            continue;
+        };
+        let blocks = &existing.clients[position].1;
+        for range in ranges.iter() {
+            let first = blocks.partition_point(|block| block.id.clock + block.len <= range.start);
+            for (offset, block) in blocks[first..].iter().enumerate() {
+                if block.id.clock >= range.end {
+                    break;
+                }
+                let id = ID::new(*client, block.id.clock.max(range.start));
+                let is_item = matches!(block.kind, BlockKind::Item(_));
+                if !is_item
+                    || update.find(&update_index, &id).is_some()
+                    || document_deletions.is_deleted(&id)
+                {
+                    continue;
+                }
+
+                let root = resolver.resolve((position, first + offset));
+                if !is_allowed(&root) {
+                    violations.push(AccessViolation {
+                        root,
+                        id,
+                        action: AccessAction::Delete,
+                    });
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+/// Resolves the root type of decoded blocks. Blocks either name their root
+/// type, or reference another block (their parent type or an origin), which
+/// is decoded as well or already integrated into the document.
+struct RootResolver<'a, T: ReadTxn> {
+    txn: &'a T,
+    update: &'a DecodedUpdate,
+    index: HashMap<ClientID, usize>,
+    roots: HashMap<(usize, usize), Option<Arc<str>>>,
+}
+
+impl<'a, T: ReadTxn> RootResolver<'a, T> {
+    fn new(txn: &'a T, update: &'a DecodedUpdate) -> Self {
+        Self {
+            txn,
+            update,
+            index: update.index(),
+            roots: HashMap::new(),
+        }
+    }
+
+    /// References are followed iteratively, chains of origins are as long as
+    /// the number of consecutive insertions and can easily exceed the stack.
+    fn resolve(&mut self, start: (usize, usize)) -> Option<Arc<str>> {
+        let mut chain = vec![start];
+        let mut visited = HashSet::from([start]);
+        let root = loop {
+            let current = *chain.last().unwrap();
+            if let Some(root) = self.roots.get(&current) {
+                break root.clone();
+            }
+
+            let reference = match &self.update.clients[current.0].1[current.1].kind {
+                BlockKind::Item(item) => match &item.parent {
+                    BlockParent::Root(name) => break Some(name.clone()),
+                    BlockParent::Nested(id) => Some(*id),
+                    BlockParent::Inherited => item.origin.or(item.right_origin),
+                },
+                BlockKind::GC | BlockKind::Skip => None,
+            };
+            let Some(id) = reference else {
+                break None;
+            };
+
+            match self.update.find(&self.index, &id) {
+                Some(next) if !visited.insert(next) => break None,
+                Some(next) => chain.push(next),
+                None => break existing_root(self.txn, id),
+            }
+        };
+
+        for position in chain {
+            self.roots.insert(position, root.clone());
+        }
+        root
+    }
+}
+
+/// Walks up from an integrated item to the root type it belongs to. The
+/// block store of yrs is private, a sticky index pointing at an item
+/// resolves the type that contains it.
+fn existing_root<T: ReadTxn>(txn: &T, mut id: ID) -> Option<Arc<str>> {
+    let mut visited = HashSet::new();
+    while visited.insert(id) {
+        let offset = StickyIndex::from_id(id, Assoc::After).get_offset(txn)?;
+        match StickyIndex::from_type(txn, &offset.branch, Assoc::After).scope() {
+            IndexScope::Root(name) => return Some(name.clone()),
+            IndexScope::Nested(parent) => id = *parent,
+            IndexScope::Relative(_) => return None,
+        }
+    }
+    None
+}
+
+/// Decodes an update into a Hash of clients with their blocks, and the delete
+/// set. This is a debugging aid, the structure mirrors the binary encoding.
+pub(crate) fn yupdate_decode(update: Vec<u8>, version: u8) -> Result<RHash, Error> {
+    let ruby = Ruby::get().unwrap();
+    // yrs decides whether the update is valid, the block headers it keeps
+    // private are read afterwards
+    let decoded = match version {
+        1 => Update::decode_v1(update.as_slice())
+            .and_then(|_update| DecodedUpdate::decode_v1(update.as_slice())),
+        2 => Update::decode_v2(update.as_slice())
+            .and_then(|_update| DecodedUpdate::decode_v2(update.as_slice())),
+        _ => {
+            return Err(Error::new(
+                ruby.exception_arg_error(),
+                format!("unsupported update version: {}", version),
+            ))
+        }
+    }
+    .map_err(|error| {
+        Error::new(
+            ruby.exception_runtime_error(),
+            format!("cannot decode update: {:?}", error),
+        )
+    })?;
+
+    let clients = ruby.ary_new_capa(decoded.clients.len());
+    for (client, blocks) in decoded.clients.iter() {
+        let start = blocks.first().map_or(0, |block| block.id.clock);
+        let end = blocks.last().map_or(0, |block| block.id.clock + block.len);
+
+        let entries = ruby.ary_new_capa(blocks.len());
+        for block in blocks {
+            entries.push(block_to_ruby(&ruby, block)?)?;
+        }
+
+        let entry = ruby.hash_new();
+        entry.aset(ruby.to_symbol("client"), *client)?;
+        entry.aset(ruby.to_symbol("clock"), ruby.range_new(start, end, true)?)?;
+        entry.aset(ruby.to_symbol("blocks"), entries)?;
+        clients.push(entry)?;
+    }
+
+    let mut deleted: Vec<(ClientID, Vec<(u32, u32)>)> = decoded
+        .delete_set
+        .iter()
+        .map(|(client, ranges)| {
+            let ranges = ranges.iter().map(|range| (range.start, range.end));
+            (*client, ranges.collect())
+        })
+        .collect();
+    deleted.sort_unstable_by_key(|(client, _ranges)| *client);
+
+    let delete_set = ruby.ary_new_capa(deleted.len());
+    for (client, ranges) in deleted {
+        let clocks = ruby.ary_new_capa(ranges.len());
+        for (start, end) in ranges {
+            clocks.push(ruby.range_new(start, end, true)?)?;
+        }
+
+        let entry = ruby.hash_new();
+        entry.aset(ruby.to_symbol("client"), client)?;
+        entry.aset(ruby.to_symbol("clock"), clocks)?;
+        delete_set.push(entry)?;
+    }
+
+    let hash = ruby.hash_new();
+    hash.aset(ruby.to_symbol("clients"), clients)?;
+    hash.aset(ruby.to_symbol("delete_set"), delete_set)?;
+    Ok(hash)
+}
+
+fn block_to_ruby(ruby: &Ruby, block: &DecodedBlock) -> Result<RHash, Error> {
+    let hash = ruby.hash_new();
+    let kind = match block.kind {
+        BlockKind::GC => "gc",
+        BlockKind::Skip => "skip",
+        BlockKind::Item(_) => "item",
+    };
+    hash.aset(ruby.to_symbol("kind"), ruby.to_symbol(kind))?;
+    hash.aset(ruby.to_symbol("clock"), block.id.clock)?;
+    hash.aset(ruby.to_symbol("length"), block.len)?;
+
+    if let BlockKind::Item(item) = &block.kind {
+        let parent = match &item.parent {
+            BlockParent::Root(name) => {
+                let parent = ruby.hash_new();
+                parent.aset(ruby.to_symbol("root"), name.to_string())?;
+                parent.into_value_with(ruby)
+            }
+            BlockParent::Nested(id) => id_to_ruby(ruby, id)?.into_value_with(ruby),
+            BlockParent::Inherited => ruby.qnil().into_value_with(ruby),
+        };
+        hash.aset(ruby.to_symbol("parent"), parent)?;
+        hash.aset(
+            ruby.to_symbol("parent_sub"),
+            item.parent_sub.as_ref().map(|key| key.to_string()),
+        )?;
+        hash.aset(
+            ruby.to_symbol("origin"),
+            optional_id_to_ruby(ruby, &item.origin)?,
+        )?;
+        hash.aset(
+            ruby.to_symbol("right_origin"),
+            optional_id_to_ruby(ruby, &item.right_origin)?,
+        )?;
+        hash.aset(
+            ruby.to_symbol("content"),
+            ruby.to_symbol(content_kind(&item.content)),
+        )?;
+        hash.aset(ruby.to_symbol("preview"), content_preview(&item.content))?;
+    }
+
+    Ok(hash)
+}
+
+fn id_to_ruby(ruby: &Ruby, id: &ID) -> Result<RHash, Error> {
+    let hash = ruby.hash_new();
+    hash.aset(ruby.to_symbol("client"), id.client)?;
+    hash.aset(ruby.to_symbol("clock"), id.clock)?;
+    Ok(hash)
+}
+
+fn optional_id_to_ruby(ruby: &Ruby, id: &Option<ID>) -> Result<Value, Error> {
+    match id {
+        Some(id) => id_to_ruby(ruby, id).map(|hash| hash.into_value_with(ruby)),
+        None => Ok(ruby.qnil().into_value_with(ruby)),
+    }
+}
+
+/// Name of the kind of content, as exposed to Ruby.
+pub(crate) fn content_kind(content: &ItemContent) -> &'static str {
+    match content {
+        ItemContent::Deleted(_) => "deleted",
+        ItemContent::JSON(_) => "json",
+        ItemContent::Binary(_) => "binary",
+        ItemContent::String(_) => "string",
+        ItemContent::Embed(_) => "embed",
+        ItemContent::Format(_, _) => "format",
+        ItemContent::Type(_) => "type",
+        ItemContent::Any(_) => "any",
+        ItemContent::Doc(_, _) => "doc",
+        ItemContent::Move(_) => "move",
+    }
+}
+
+/// Approximate number of bytes the content occupies in memory, in addition
+/// to the item itself.
+pub(crate) fn content_heap_size(content: &ItemContent) -> usize {
+    match content {
+        ItemContent::Deleted(_) | ItemContent::Doc(_, _) | ItemContent::Move(_) => 0,
+        ItemContent::JSON(values) => values.iter().map(String::len).sum(),
+        ItemContent::Binary(data) => data.len(),
+        ItemContent::String(value) => value.as_str().len(),
+        ItemContent::Embed(value) => value.to_string().len(),
+        ItemContent::Format(key, value) => key.len() + value.to_string().len(),
+        ItemContent::Type(branch) => match branch.type_ref() {
+            TypeRef::XmlElement(name) => name.len(),
+            _ => 0,
+        },
+        ItemContent::Any(values) => values.iter().map(|value| value.to_string().len()).sum(),
+    }
+}
+
+/// A short, human readable representation of the content.
+fn content_preview(content: &ItemContent) -> String {
+    let preview = match content {
+        ItemContent::Deleted(len) => format!("{} deleted", len),
+        ItemContent::JSON(values) => values.join(", "),
+        ItemContent::Binary(data) => format!("{} bytes", data.len()),
+        ItemContent::String(value) => value.to_string(),
+        ItemContent::Embed(value) => value.to_string(),
+        ItemContent::Format(key, value) => format!("{}={}", key, value),
+        ItemContent::Type(branch) => match branch.type_ref() {
+            TypeRef::Array => "Array".to_string(),
+            TypeRef::Map => "Map".to_string(),
+            TypeRef::Text => "Text".to_string(),
+            TypeRef::XmlElement(name) => format!("XMLElement({})", name),
+            TypeRef::XmlFragment => "XMLFragment".to_string(),
+            TypeRef::XmlHook => "XMLHook".to_string(),
+            TypeRef::XmlText => "XMLText".to_string(),
+            TypeRef::SubDoc => "Doc".to_string(),
+            _ => "Undefined".to_string(),
+        },
+        ItemContent::Any(values) => values
+            .iter()
+            .map(|value| match value {
+                Any::BigInt(value) => format!("{}n", value),
+                value => value.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", "),
+        ItemContent::Doc(_, doc) => doc.guid().to_string(),
+        ItemContent::Move(_) => "Move".to_string(),
+    };
+
+    if preview.chars().count() > PREVIEW_LENGTH {
+        let truncated: String = preview.chars().take(PREVIEW_LENGTH).collect();
+        format!("{}…", truncated)
+    } else {
+        preview
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yupdate::{check_access, AccessAction, BlockKind, DecodedUpdate, PendingUpdates};
+    use yrs::block::ItemContent;
+    use yrs::updates::decoder::Decode;
+    use yrs::{
+        Array, Doc, GetString, Map, MapPrelim, Options, ReadTxn, StateVector, Text, Transact,
+        Update,
+    };
+
+    fn doc(client_id: u64) -> Doc {
+        Doc::with_options(Options {
+            client_id,
+            ..Options::default()
+        })
+    }
+
+    fn allow(roots: &[&str]) -> Vec<String> {
+        roots.iter().map(|root| root.to_string()).collect()
+    }
+
+    #[test]
+    fn decode_move_block() {
+        let doc = doc(1);
+        let array = doc.get_or_insert_array("list");
+        let mut txn = doc.transact_mut();
+        array.insert_range(&mut txn, 0, [1, 2, 3]);
+        array.move_to(&mut txn, 0, 3);
+        drop(txn);
+
+        let state = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let update = DecodedUpdate::decode_v1(state.as_slice()).unwrap();
+
+        let (_client, blocks) = &update.clients[0];
+        let moved = blocks.iter().any(|block| {
+            matches!(&block.kind, BlockKind::Item(item) if matches!(item.content, ItemContent::Move(_)))
+        });
+        assert!(moved);
+        let end = blocks.last().map(|block| block.id.clock + block.len);
+        assert_eq!(end, Some(doc.transact().state_vector().get(&1)));
+    }
+
+    #[test]
+    fn check_nested_inserts() {
+        let remote = doc(1);
+        let local = doc(2);
+        let meta = remote.get_or_insert_map("meta");
+        let nested = meta.insert(
+            &mut remote.transact_mut(),
+            "nested",
+            MapPrelim::<i32>::new(),
+        );
+        let update = remote
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        local
+            .transact_mut()
+            .apply_update(Update::decode_v1(update.as_slice()).unwrap());
+
+        nested.insert(&mut remote.transact_mut(), "key", "value");
+        let diff = remote
+            .transact()
+            .encode_diff_v1(&local.transact().state_vector());
+        let decoded = DecodedUpdate::decode_v1(diff.as_slice()).unwrap();
+
+        let txn = local.transact();
+        let violations = check_access(&txn, &decoded, &allow(&["content"]));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].root.as_deref(), Some("meta"));
+        assert!(check_access(&txn, &decoded, &allow(&["meta"])).is_empty());
+    }
+
+    #[test]
+    fn check_deletions() {
+        let remote = doc(1);
+        let local = doc(2);
+        let text = remote.get_or_insert_text("meta");
+        text.insert(&mut remote.transact_mut(), 0, "Hello");
+        let update = remote
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        local
+            .transact_mut()
+            .apply_update(Update::decode_v1(update.as_slice()).unwrap());
+
+        text.remove_range(&mut remote.transact_mut(), 1, 2);
+        let diff = remote
+            .transact()
+            .encode_diff_v1(&local.transact().state_vector());
+        let decoded = DecodedUpdate::decode_v1(diff.as_slice()).unwrap();
+
+        let txn = local.transact();
+        let violations = check_access(&txn, &decoded, &allow(&["content"]));
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].action == AccessAction::Delete);
+        assert_eq!(violations[0].root.as_deref(), Some("meta"));
+        assert_eq!(violations[0].id.clock, 1);
+        assert!(check_access(&txn, &decoded, &allow(&["meta"])).is_empty());
+        drop(txn);
+
+        // the deletion is already known, it is not checked again
+        local
+            .transact_mut()
+            .apply_update(Update::decode_v1(diff.as_slice()).unwrap());
+        let text = local.get_or_insert_text("meta");
+        let txn = local.transact();
+        assert!(check_access(&txn, &decoded, &allow(&["content"])).is_empty());
+        assert_eq!(text.get_string(&txn), "Hlo");
+    }
+
+    #[test]
+    fn encode_decoded_update() {
+        let doc = doc(1);
+        let text = doc.get_or_insert_text("content");
+        let meta = doc.get_or_insert_map("meta");
+        text.insert(&mut doc.transact_mut(), 0, "Hello");
+        meta.insert(&mut doc.transact_mut(), "nested", MapPrelim::<i32>::new());
+        text.remove_range(&mut doc.transact_mut(), 1, 2);
+
+        let state = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let decoded = DecodedUpdate::decode_v1(state.as_slice()).unwrap();
+        let state_v2 = doc
+            .transact()
+            .encode_state_as_update_v2(&StateVector::default());
+        let decoded_v2 = DecodedUpdate::decode_v2(state_v2.as_slice()).unwrap();
+        for update in [
+            Update::decode_v1(decoded.encode_v1().as_slice()).unwrap(),
+            Update::decode_v1(decoded_v2.encode_v1().as_slice()).unwrap(),
+        ] {
+            let copy = self::doc(2);
+            copy.transact_mut().apply_update(update);
+            let text = copy.get_or_insert_text("content");
+            assert_eq!(text.get_string(&copy.transact()), "Hlo");
+            assert_eq!(
+                copy.transact().state_vector(),
+                doc.transact().state_vector()
+            );
+        }
+    }
+
+    #[test]
+    fn track_pending_updates() {
+        let remote = doc(1);
+        let local = doc(2);
+        let text = remote.get_or_insert_text("content");
+        text.insert(&mut remote.transact_mut(), 0, "Hello");
+        let first = remote
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let state = remote.transact().state_vector();
+        text.insert(&mut remote.transact_mut(), 5, " World");
+        text.remove_range(&mut remote.transact_mut(), 0, 1);
+        let second = remote.transact().encode_diff_v1(&state);
+
+        let mut pending = PendingUpdates::default();
+        pending
+            .apply_v1(&mut local.transact_mut(), second.as_slice())
+            .unwrap();
+        assert!(pending.is_pending());
+        let missing = pending.missing().unwrap();
+        assert_eq!(missing.get(&1), 0);
+
+        // the pending update can be applied to a document that has the
+        // missing changes
+        let copy = self::doc(3);
+        copy.transact_mut()
+            .apply_update(Update::decode_v1(first.as_slice()).unwrap());
+        copy.transact_mut().apply_update(
+            Update::decode_v1(pending.update().unwrap().unwrap().as_slice()).unwrap(),
+        );
+        let copied = copy.get_or_insert_text("content");
+        assert_eq!(copied.get_string(&copy.transact()), "ello World");
+
+        pending
+            .apply_v1(&mut local.transact_mut(), first.as_slice())
+            .unwrap();
+        assert!(!pending.is_pending());
+        assert!(pending.missing().is_none());
+        let text = local.get_or_insert_text("content");
+        assert_eq!(text.get_string(&local.transact()), "ello World");
+    }
+
+    #[test]
+    fn track_pending_deletions() {
+        let remote = doc(1);
+        let local = doc(2);
+        let text = remote.get_or_insert_text("content");
+        text.insert(&mut remote.transact_mut(), 0, "Hello");
+        let first = remote
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let state = remote.transact().state_vector();
+        text.remove_range(&mut remote.transact_mut(), 0, 1);
+        let second = remote.transact().encode_diff_v1(&state);
+
+        let mut pending = PendingUpdates::default();
+        pending
+            .apply_v1(&mut local.transact_mut(), second.as_slice())
+            .unwrap();
+        assert!(pending.is_pending());
+        assert!(pending.missing().is_none());
+
+        pending
+            .apply_v1(&mut local.transact_mut(), first.as_slice())
+            .unwrap();
+        assert!(!pending.is_pending());
+        let text = local.get_or_insert_text("content");
+        assert_eq!(text.get_string(&local.transact()), "ello");
+    }
+
+    #[test]
+    fn retry_pending_updates_in_order() {
+        let remote = doc(1);
+        let local = doc(2);
+        let text = remote.get_or_insert_text("content");
+        let mut updates = Vec::new();
+        for (index, chunk) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            let state = remote.transact().state_vector();
+            text.insert(&mut remote.transact_mut(), index as u32, chunk);
+            updates.push(remote.transact().encode_diff_v1(&state));
+        }
+
+        let mut pending = PendingUpdates::default();
+        for update in updates.iter().rev() {
+            pending
+                .apply_v1(&mut local.transact_mut(), update.as_slice())
+                .unwrap();
+        }
+        assert!(!pending.is_pending());
+        assert!(pending.missing().is_none());
+        let text = local.get_or_insert_text("content");
+        assert_eq!(text.get_string(&local.transact()), "abcd");
+    }
+}
@@ -1,7 +1,11 @@
+use crate::utils::error_class;
 use crate::{YText, YXmlElement, YXmlText};
+use magnus::encoding::EncodingCapable;
 use magnus::r_hash::ForEach::Continue;
 use magnus::value::{Qnil, ReprValue};
-use magnus::{Float, Integer, IntoValue, RArray, RHash, RString, Ruby, Symbol, Value};
+use magnus::{
+    Error, Float, Integer, IntoValue, Module, RArray, RHash, RModule, RString, Ruby, Symbol, Value,
+};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -130,45 +134,59 @@ impl From<YXmlText> for YValue {
     }
 }
 
-impl From<Any> for YValue {
-    fn from(value: Any) -> Self {
+impl TryFrom<Any> for YValue {
+    type Error = Error;
+
+    fn try_from(value: Any) -> Result<Self, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
-        match value {
+        let value = match value {
             Any::Null => YValue::from(ruby.qnil()),
             Any::Undefined => YValue::from(ruby.qnil()),
             Any::Bool(v) => YValue::from(v),
+            // numbers are the only numeric type in JavaScript, integral
+            // numbers are most likely integers on the other side
+            Any::Number(v) if v.fract() == 0.0 && v.abs() <= MAX_SAFE_INTEGER as f64 => {
+                YValue::from(v as i64)
+            }
             Any::Number(v) => YValue::from(v),
             Any::BigInt(v) => YValue::from(v),
             Any::String(v) => YValue::from(v.to_string()),
-            Any::Buffer(v) => YValue::from(v.to_vec()),
+            Any::Buffer(v) => {
+                let buffer = ruby.str_from_slice(v.as_ref());
+                buffer.freeze();
+                YValue::from(buffer.as_value())
+            }
             Any::Array(v) => {
                 let arr = ruby.ary_new();
                 for item in v.iter() {
-                    let val = YValue::from(item.clone());
-                    let val = *val.0.borrow();
-                    arr.push(val).expect("cannot push item event to array");
+                    let val = YValue::try_from(item.clone())?;
+                    arr.push(val.0.into_inner())?;
                 }
                 YValue::from(arr)
             }
             Any::Map(v) => {
+                if let Some(value) = decode_with_codec(&ruby, &v)? {
+                    return Ok(YValue::from(value));
+                }
                 let hash = ruby.hash_new();
                 for (key, val) in v.iter() {
-                    let v = val.clone();
-                    let value: Value = YValue::from(v).into();
-                    hash.aset(key.to_string(), value)
-                        .expect("cannot insert into hash");
+                    let value = YValue::try_from(val.clone())?;
+                    hash.aset(key.to_string(), value.0.into_inner())?;
                 }
                 YValue::from(hash)
             }
-        }
+        };
+        Ok(value)
     }
 }
 
-impl From<YrsValue> for YValue {
-    fn from(value: YrsValue) -> Self {
+impl TryFrom<YrsValue> for YValue {
+    type Error = Error;
+
+    fn try_from(value: YrsValue) -> Result<Self, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
-        match value {
-            YrsValue::Any(val) => YValue::from(val),
+        let value = match value {
+            YrsValue::Any(val) => YValue::try_from(val)?,
             YrsValue::YText(text) => YValue::from(text),
             YrsValue::YXmlElement(el) => YValue::from(el),
             YrsValue::YXmlText(text) => YValue::from(text),
@@ -176,9 +194,8 @@ impl From<YrsValue> for YValue {
                 let tx = val.transact();
                 let arr = ruby.ary_new();
                 for item in val.iter(&tx) {
-                    let val = YValue::from(item.clone());
-                    let val = *val.0.borrow();
-                    arr.push(val).expect("cannot push item event to array");
+                    let val = YValue::try_from(item.clone())?;
+                    arr.push(val.0.into_inner())?;
                 }
                 YValue::from(arr)
             }
@@ -186,29 +203,50 @@ impl From<YrsValue> for YValue {
                 let tx = val.transact();
                 let hash = ruby.hash_new();
                 for (key, value) in val.iter(&tx) {
-                    let val = YValue::from(value);
-                    let val = val.0.into_inner();
-                    hash.aset(key, val).expect("cannot insert into hash");
+                    let val = YValue::try_from(value)?;
+                    hash.aset(key, val.0.into_inner())?;
                 }
                 YValue::from(hash)
             }
             v => panic!("cannot map complex yrs values to yvalue: {:?}", v),
-        }
+        };
+        Ok(value)
     }
 }
 
-impl From<YValue> for Any {
-    fn from(val: YValue) -> Self {
+/// Largest integer that can be represented exactly by a JavaScript number.
+pub(crate) const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+/// Options that control how Ruby values are converted into values that can be
+/// stored in a document. They are configured per document.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ConversionOptions {
+    pub(crate) integers: IntegerEncoding,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum IntegerEncoding {
+    /// Integers within the safe range of JavaScript numbers are stored as
+    /// numbers, larger integers as BigInt.
+    #[default]
+    Number,
+    /// All integers are stored as BigInt.
+    BigInt,
+}
+
+impl YValue {
+    /// Converts a Ruby value into a value that can be stored in a document.
+    pub(crate) fn try_into_any(self, options: ConversionOptions) -> Result<Any, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
-        let value = val.0.into_inner();
-        if value.is_nil() {
+        let value = self.0.into_inner();
+        let any = if value.is_nil() {
             Any::Null
         } else if value.is_kind_of(ruby.class_float()) {
             let f = Float::from_value(value).unwrap();
             Any::Number(f.to_f64())
         } else if value.is_kind_of(ruby.class_integer()) {
             let i = Integer::from_value(value).unwrap();
-            Any::BigInt(i.to_i64().unwrap())
+            integer_to_any(i, options.integers)?
         } else if value.is_kind_of(ruby.class_symbol()) {
             let s = Symbol::from_value(value).unwrap();
             Any::String(Arc::from(s.name().unwrap()))
@@ -218,16 +256,13 @@ impl From<YValue> for Any {
             Any::Bool(false)
         } else if value.is_kind_of(ruby.class_string()) {
             let s = RString::from_value(value).unwrap();
-            unsafe { Any::String(Arc::from(s.as_str().unwrap().to_string())) }
+            string_to_any(s)?
         } else if value.is_kind_of(ruby.class_array()) {
             let arr = RArray::from_value(value).unwrap();
             let items = arr
                 .into_iter()
-                .map(|item| {
-                    let yvalue = YValue::from(item);
-                    Any::from(yvalue)
-                })
-                .collect::<Vec<Any>>();
+                .map(|item| YValue::from(item).try_into_any(options))
+                .collect::<Result<Vec<Any>, Error>>()?;
             Any::Array(Arc::from(items))
         } else if value.is_kind_of(ruby.class_hash()) {
             let map = RHash::from_value(value).unwrap();
@@ -243,15 +278,103 @@ impl From<YValue> for Any {
                     let result = converted_key.to_string();
                     result.unwrap()
                 };
-                m.insert(k, Any::from(YValue::from(val)));
+                m.insert(k, YValue::from(val).try_into_any(options)?);
                 Ok(Continue)
-            })
-            .expect("cannot map key/value pair");
+            })?;
 
             Any::Map(Arc::from(m))
         } else {
-            Any::Undefined
-        }
+            encode_with_codec(value, options)?
+        };
+        Ok(any)
+    }
+}
+
+/// Key of the map that holds the name of the codec an object was encoded with.
+const CODEC_NAME_KEY: &str = "$codec";
+/// Key of the map that holds the value encoded by a codec.
+const CODEC_VALUE_KEY: &str = "value";
+
+fn codecs(ruby: &Ruby) -> Result<RModule, Error> {
+    ruby.class_object()
+        .const_get::<_, RModule>("Y")?
+        .const_get::<_, RModule>("Codecs")
+}
+
+/// Objects of other classes are encoded by the codec registered in
+/// `Y::Codecs`, which raises if there is none.
+fn encode_with_codec(value: Value, options: ConversionOptions) -> Result<Any, Error> {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    let (name, encoded): (Option<String>, Value) = codecs(&ruby)?.funcall("encode", (value,))?;
+    let encoded = YValue::from(encoded).try_into_any(options)?;
+    let any = match name {
+        Some(name) => Any::Map(Arc::from(HashMap::from([
+            (CODEC_NAME_KEY.to_string(), Any::String(Arc::from(name))),
+            (CODEC_VALUE_KEY.to_string(), encoded),
+        ]))),
+        None => encoded,
+    };
+    Ok(any)
+}
+
+/// Decodes a map written by [encode_with_codec]. Other maps are read as plain
+/// hashes, like the maps of codecs that are not registered (see
+/// `Y::Codecs.decode`). Errors raised by a codec are propagated.
+fn decode_with_codec(ruby: &Ruby, map: &HashMap<String, Any>) -> Result<Option<Value>, Error> {
+    if map.len() != 2 {
+        return Ok(None);
+    }
+    let (name, value) = match (map.get(CODEC_NAME_KEY), map.get(CODEC_VALUE_KEY)) {
+        (Some(Any::String(name)), Some(value)) => (name.to_string(), value.clone()),
+        _ => return Ok(None),
+    };
+    let value = YValue::try_from(value)?.0.into_inner();
+    codecs(ruby)?.funcall("decode", (name, value)).map(Some)
+}
+
+/// Binary strings (`ASCII-8BIT`) are stored as buffers, all other strings as
+/// UTF-8 text.
+fn string_to_any(value: RString) -> Result<Any, Error> {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    let encoding = value.enc_get();
+    if encoding == ruby.ascii8bit_encindex() {
+        // SAFETY: the bytes are copied before the string can be modified
+        let bytes = unsafe { value.as_slice() };
+        return Ok(Any::Buffer(Arc::from(bytes)));
+    }
+
+    let invalid = || {
+        Error::new(
+            error_class(&ruby, "InvalidEncodingError"),
+            "string contains bytes that are invalid in its encoding",
+        )
+    };
+    if encoding == ruby.utf8_encindex() || encoding == ruby.usascii_encindex() {
+        // SAFETY: the bytes are copied before the string can be modified
+        let bytes = unsafe { value.as_slice() };
+        let text = std::str::from_utf8(bytes).map_err(|_error| invalid())?;
+        Ok(Any::String(Arc::from(text)))
+    } else {
+        // strings in other encodings are transcoded to UTF-8
+        let text = value.to_string().map_err(|_error| invalid())?;
+        Ok(Any::String(Arc::from(text)))
+    }
+}
+
+fn integer_to_any(value: Integer, encoding: IntegerEncoding) -> Result<Any, Error> {
+    let ruby = unsafe { Ruby::get_unchecked() };
+    let i = value.to_i64().map_err(|_error| {
+        Error::new(
+            error_class(&ruby, "IntegerOutOfRangeError"),
+            format!("integer {} does not fit into 64 bits", value),
+        )
+    })?;
+
+    let is_safe = (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&i);
+    if encoding == IntegerEncoding::Number && is_safe {
+        Ok(Any::Number(i as f64))
+    } else {
+        Ok(Any::BigInt(i))
     }
 }
 
@@ -272,7 +395,7 @@ mod tests {
     fn convert_any_to_yvalue() {
         let _cleanup = unsafe { magnus::embed::init() };
         let value = Any::Null;
-        let yvalue: YValue = value.into();
+        let yvalue = YValue::try_from(value).unwrap();
 
         assert!(yvalue.0.into_inner().is_nil());
     }
@@ -1,11 +1,13 @@
-use crate::yvalue::YValue;
+use crate::utils::defer_error;
+use crate::yevent::YEvent;
 use crate::yxml_fragment::YXmlFragment;
+use crate::yxml_markup;
+use crate::yxml_query;
 use crate::yxml_text::YXmlText;
 use crate::YTransaction;
 use magnus::block::Proc;
 use magnus::{Error, IntoValue, RArray, RHash, Ruby, Value};
 use std::cell::RefCell;
-use yrs::types::Change;
 use yrs::{
     GetString, Observable, Xml, XmlElementPrelim, XmlElementRef, XmlFragment, XmlNode,
     XmlTextPrelim,
@@ -29,6 +31,25 @@ impl YXmlElement {
         }
         hash
     }
+    pub(crate) fn yxml_element_descendants(&self, transaction: &YTransaction) -> RArray {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        yxml_query::descendants(&ruby, tx, &*self.0.borrow())
+    }
+    pub(crate) fn yxml_element_find_all(
+        &self,
+        transaction: &YTransaction,
+        tag: Option<String>,
+        attributes: Vec<(String, Option<String>)>,
+    ) -> RArray {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        yxml_query::find_all(&ruby, tx, &*self.0.borrow(), tag, attributes)
+    }
     pub(crate) fn yxml_element_first_child(&self, transaction: &YTransaction) -> Option<Value> {
         self.yxml_element_get(transaction, 0)
     }
@@ -106,67 +127,19 @@ impl YXmlElement {
         })
     }
     pub(crate) fn yxml_element_observe(&self, block: Proc) -> Result<u32, Error> {
-        let ruby = unsafe { Ruby::get_unchecked() };
-        let change_added = ruby.to_symbol("added").to_static();
-        let change_retain = ruby.to_symbol("retain").to_static();
-        let change_removed = ruby.to_symbol("removed").to_static();
-
         let subscription_id = self
             .0
             .borrow_mut()
-            .observe(move |transaction, xml_element_event| {
-                let ruby = unsafe { Ruby::get_unchecked() };
-                let delta = xml_element_event.delta(transaction);
-                let changes = ruby.ary_new_capa(delta.len());
-
-                for change in delta {
-                    match change {
-                        Change::Added(v) => {
-                            let values = ruby.ary_new();
-                            for value in v.iter() {
-                                let value = YValue::from(value.clone());
-                                let value = *value.0.borrow();
-                                values.push(value).expect("cannot push value to array");
-                            }
-
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(change_added, values)
-                                .expect("cannot create change::added payload");
-
-                            changes
-                                .push(payload)
-                                .expect("cannot push payload to list of changes");
-                        }
-                        Change::Retain(position) => {
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(change_retain, *position)
-                                .expect("cannot create change::retain payload");
-
-                            changes
-                                .push(payload)
-                                .expect("cannot push payload to list of changes");
-                        }
-                        Change::Removed(position) => {
-                            let payload = ruby.hash_new();
-                            payload
-                                .aset(change_removed, *position)
-                                .expect("cannot create change::removed payload");
-
-                            changes
-                                .push(payload)
-                                .expect("cannot push payload to list of changes");
-                        }
-                    }
+            .observe(move |transaction, event| {
+                let result = YEvent::xml(transaction, event)
+                    .and_then(|event| block.call::<(Value,), Value>((event,)));
+                if let Err(error) = result {
+                    defer_error(error);
                 }
+            })
+            .into();
 
-                block
-                    .call::<(RArray,), Value>((changes,))
-                    .expect("cannot call block");
-            });
-
-        Ok(subscription_id.into())
+        Ok(subscription_id)
     }
     pub(crate) fn yxml_element_parent(&self) -> Option<Value> {
         let ruby = unsafe { Ruby::get_unchecked() };
@@ -235,6 +208,17 @@ impl YXmlElement {
         let text = XmlTextPrelim::new(content.as_str());
         YXmlText::from(self.0.borrow_mut().push_front(tx, text))
     }
+    pub(crate) fn yxml_element_query(
+        &self,
+        transaction: &YTransaction,
+        selector: String,
+    ) -> Result<RArray, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        yxml_query::query(&ruby, tx, &*self.0.borrow(), selector.as_str())
+    }
     pub(crate) fn yxml_element_remove_attribute(&self, transaction: &YTransaction, name: String) {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
@@ -252,6 +236,22 @@ impl YXmlElement {
 
         self.0.borrow_mut().remove_range(tx, index, length)
     }
+    pub(crate) fn yxml_element_replace_children_with_xml(
+        &self,
+        transaction: &YTransaction,
+        markup: String,
+    ) -> Result<RArray, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let nodes = yxml_markup::parse_markup(&ruby, markup.as_str())?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let element = self.0.borrow_mut();
+        let length = element.len(tx);
+        element.remove_range(tx, 0, length);
+        Ok(yxml_markup::insert(&ruby, tx, &*element, 0, &nodes))
+    }
     pub(crate) fn yxml_element_siblings(&self, transaction: &YTransaction) -> RArray {
         let ruby = unsafe { Ruby::get_unchecked() };
         let tx = transaction.transaction();
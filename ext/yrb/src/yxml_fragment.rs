@@ -1,9 +1,19 @@
+use crate::utils::{convert_json_to_ruby_value, convert_ruby_value_to_json, defer_error};
+use crate::yevent::YEvent;
 use crate::ytransaction::YTransaction;
 use crate::yxml_element::YXmlElement;
+use crate::yxml_markup;
+use crate::yxml_prosemirror;
+use crate::yxml_query;
+use crate::yxml_reconcile;
 use crate::yxml_text::YXmlText;
-use magnus::{IntoValue, RArray, Ruby, Value};
+use magnus::block::Proc;
+use magnus::{Error, IntoValue, RArray, Ruby, Value};
 use std::cell::RefCell;
-use yrs::{GetString, XmlElementPrelim, XmlFragment, XmlFragmentRef, XmlNode, XmlTextPrelim};
+use yrs::{
+    DeepObservable, GetString, Observable, XmlElementPrelim, XmlFragment, XmlFragmentRef, XmlNode,
+    XmlTextPrelim,
+};
 
 #[magnus::wrap(class = "Y::XMLFragment")]
 pub(crate) struct YXmlFragment(pub(crate) RefCell<XmlFragmentRef>);
@@ -12,6 +22,27 @@ pub(crate) struct YXmlFragment(pub(crate) RefCell<XmlFragmentRef>);
 unsafe impl Send for YXmlFragment {}
 
 impl YXmlFragment {
+    pub(crate) fn yxml_fragment_descendants(&self, transaction: &YTransaction) -> RArray {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        yxml_query::descendants(&ruby, tx, &*self.0.borrow())
+    }
+
+    pub(crate) fn yxml_fragment_find_all(
+        &self,
+        transaction: &YTransaction,
+        tag: Option<String>,
+        attributes: Vec<(String, Option<String>)>,
+    ) -> RArray {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        yxml_query::find_all(&ruby, tx, &*self.0.borrow(), tag, attributes)
+    }
+
     pub(crate) fn yxml_fragment_first_child(&self) -> Option<Value> {
         let ruby = unsafe { Ruby::get_unchecked() };
         self.0.borrow().first_child().map(|node| match node {
@@ -50,6 +81,22 @@ impl YXmlFragment {
         YXmlElement::from(self.0.borrow_mut().insert(tx, index, node))
     }
 
+    pub(crate) fn yxml_fragment_insert_xml(
+        &self,
+        transaction: &YTransaction,
+        index: u32,
+        markup: String,
+    ) -> Result<RArray, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let nodes = yxml_markup::parse_markup(&ruby, markup.as_str())?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let fragment = self.0.borrow_mut();
+        Ok(yxml_markup::insert(&ruby, tx, &*fragment, index, &nodes))
+    }
+
     pub(crate) fn yxml_fragment_len(&self, transaction: &YTransaction) -> u32 {
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
@@ -124,6 +171,34 @@ impl YXmlFragment {
         YXmlText::from(self.0.borrow_mut().insert(tx, index, text))
     }
 
+    pub(crate) fn yxml_fragment_query(
+        &self,
+        transaction: &YTransaction,
+        selector: String,
+    ) -> Result<RArray, Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        yxml_query::query(&ruby, tx, &*self.0.borrow(), selector.as_str())
+    }
+
+    pub(crate) fn yxml_fragment_reconcile(
+        &self,
+        transaction: &YTransaction,
+        json: Value,
+    ) -> Result<(), Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let json = convert_ruby_value_to_json(json)?;
+        let nodes = yxml_prosemirror::parse_doc(&json)
+            .map_err(|message| Error::new(ruby.exception_arg_error(), message))?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        yxml_reconcile::reconcile(tx, &*self.0.borrow(), &nodes)
+    }
+
     pub(crate) fn yxml_fragment_remove_range(
         &self,
         transaction: &YTransaction,
@@ -155,12 +230,83 @@ impl YXmlFragment {
         array
     }
 
+    pub(crate) fn yxml_fragment_load_prosemirror_json(
+        &self,
+        transaction: &YTransaction,
+        json: Value,
+    ) -> Result<(), Error> {
+        let ruby = unsafe { Ruby::get_unchecked() };
+        let json = convert_ruby_value_to_json(json)?;
+        let nodes = yxml_prosemirror::parse_doc(&json)
+            .map_err(|message| Error::new(ruby.exception_arg_error(), message))?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let fragment = self.0.borrow();
+        let index = fragment.len(tx);
+        yxml_prosemirror::insert(tx, &*fragment, index, &nodes)
+    }
+
+    pub(crate) fn yxml_fragment_observe(&self, block: Proc) -> Result<u32, Error> {
+        let subscription_id = self
+            .0
+            .borrow_mut()
+            .observe(move |transaction, event| {
+                let result = YEvent::xml(transaction, event)
+                    .and_then(|event| block.call::<(Value,), Value>((event,)));
+                if let Err(error) = result {
+                    defer_error(error);
+                }
+            })
+            .into();
+
+        Ok(subscription_id)
+    }
+
+    pub(crate) fn yxml_fragment_observe_deep(&self, block: Proc) -> Result<u32, Error> {
+        let subscription_id = self
+            .0
+            .borrow_mut()
+            .observe_deep(move |transaction, events| {
+                let ruby = unsafe { Ruby::get_unchecked() };
+                let result = events
+                    .iter()
+                    .map(|event| YEvent::from_event(transaction, event))
+                    .collect::<Result<Vec<Value>, Error>>()
+                    .and_then(|events| {
+                        block.call::<(RArray,), Value>((ruby.ary_from_vec(events),))
+                    });
+                if let Err(error) = result {
+                    defer_error(error);
+                }
+            })
+            .into();
+
+        Ok(subscription_id)
+    }
+
+    pub(crate) fn yxml_fragment_to_prosemirror_json(&self, transaction: &YTransaction) -> Value {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        convert_json_to_ruby_value(yxml_prosemirror::to_json(tx, &*self.0.borrow()))
+    }
+
     pub(crate) fn yxml_fragment_to_s(&self, transaction: &YTransaction) -> String {
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
 
         self.0.borrow().get_string(tx)
     }
+
+    pub(crate) fn yxml_fragment_unobserve(&self, subscription_id: u32) {
+        self.0.borrow_mut().unobserve(subscription_id);
+    }
+
+    pub(crate) fn yxml_fragment_unobserve_deep(&self, subscription_id: u32) {
+        self.0.borrow_mut().unobserve_deep(subscription_id);
+    }
 }
 
 impl From<XmlFragmentRef> for YXmlFragment {
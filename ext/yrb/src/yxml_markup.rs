@@ -0,0 +1,466 @@
+use crate::utils::error_class;
+use crate::yevent::xml_node_to_value;
+use magnus::{Error, RArray, Ruby};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use yrs::{TransactionMut, Xml, XmlElementPrelim, XmlFragment, XmlNode, XmlTextPrelim};
+
+/// HTML elements that never have children and may omit the closing slash.
+const VOID_ELEMENTS: [&str; 8] = ["area", "br", "col", "hr", "img", "input", "meta", "wbr"];
+
+/// A node of parsed XML or HTML markup.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum MarkupNode {
+    Element {
+        tag: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<MarkupNode>,
+    },
+    Text(String),
+}
+
+/// Raised for markup that is not well-formed, with the byte offset at which
+/// parsing failed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct MarkupError {
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+/// Parses XML or HTML markup into a list of nodes.
+///
+/// Comments, processing instructions and doctypes are skipped, and entities in
+/// text and attribute values are decoded. Whitespace between elements is
+/// kept, except for line breaks and indentation in an element without text,
+/// which only lay out block-level siblings.
+pub(crate) fn parse(input: &str) -> Result<Vec<MarkupNode>, MarkupError> {
+    let mut parser = Parser {
+        input,
+        chars: input.char_indices().peekable(),
+    };
+    let nodes = parser.parse_children(None)?;
+    Ok(nodes)
+}
+
+/// Parses markup like [parse], raising a `Y::MarkupError` if it is not
+/// well-formed.
+pub(crate) fn parse_markup(ruby: &Ruby, input: &str) -> Result<Vec<MarkupNode>, Error> {
+    parse(input).map_err(|error| Error::new(error_class(ruby, "MarkupError"), error.to_string()))
+}
+
+/// Inserts the nodes as children of the parent, starting at the index.
+/// Returns the inserted nodes as Ruby objects.
+pub(crate) fn insert<T: XmlFragment>(
+    ruby: &Ruby,
+    transaction: &mut TransactionMut,
+    parent: &T,
+    index: u32,
+    nodes: &[MarkupNode],
+) -> RArray {
+    let inserted = ruby.ary_new_capa(nodes.len());
+    for (offset, node) in nodes.iter().enumerate() {
+        let node = insert_node(transaction, parent, index + offset as u32, node);
+        inserted
+            .push(xml_node_to_value(ruby, node))
+            .expect("cannot push node to array");
+    }
+    inserted
+}
+
+fn insert_node<T: XmlFragment>(
+    transaction: &mut TransactionMut,
+    parent: &T,
+    index: u32,
+    node: &MarkupNode,
+) -> XmlNode {
+    match node {
+        MarkupNode::Element {
+            tag,
+            attributes,
+            children,
+        } => {
+            let element = parent.insert(transaction, index, XmlElementPrelim::empty(tag.as_str()));
+            for (name, value) in attributes {
+                element.insert_attribute(transaction, name.as_str(), value.as_str());
+            }
+            for (index, child) in children.iter().enumerate() {
+                insert_node(transaction, &element, index as u32, child);
+            }
+            XmlNode::Element(element)
+        }
+        MarkupNode::Text(text) => {
+            let text = parent.insert(transaction, index, XmlTextPrelim::new(text.as_str()));
+            XmlNode::Text(text)
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.input.len(), |(offset, _)| *offset)
+    }
+
+    fn error(&mut self, message: impl Into<String>) -> MarkupError {
+        MarkupError {
+            offset: self.offset(),
+            message: message.into(),
+        }
+    }
+
+    fn rest(&mut self) -> &'a str {
+        let offset = self.offset();
+        &self.input[offset..]
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        let end = self.offset() + bytes;
+        while self.offset() < end {
+            self.chars.next();
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    /// Skips everything up to and including the terminator.
+    fn skip_past(&mut self, terminator: &str) -> Result<(), MarkupError> {
+        match self.rest().find(terminator) {
+            Some(position) => {
+                self.advance(position + terminator.len());
+                Ok(())
+            }
+            None => Err(self.error(format!("expected {:?}", terminator))),
+        }
+    }
+
+    /// Parses nodes until the closing tag of the parent, or the end of the
+    /// input if there is no parent.
+    fn parse_children(&mut self, parent: Option<&str>) -> Result<Vec<MarkupNode>, MarkupError> {
+        let mut children = Vec::new();
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return match parent {
+                    Some(tag) => Err(self.error(format!("missing closing tag </{}>", tag))),
+                    None => Ok(drop_layout(children)),
+                };
+            } else if rest.starts_with("</") {
+                self.advance(2);
+                let tag = self.parse_name()?;
+                self.skip_whitespace();
+                if self.chars.next_if(|(_, c)| *c == '>').is_none() {
+                    return Err(self.error("expected '>'"));
+                }
+                return match parent {
+                    Some(parent) if parent == tag => Ok(drop_layout(children)),
+                    _ => Err(self.error(format!("unexpected closing tag </{}>", tag))),
+                };
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else if rest.starts_with('<') {
+                children.push(self.parse_element()?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = decode_entities(&rest[..end]);
+                self.advance(end);
+                if !text.is_empty() {
+                    children.push(MarkupNode::Text(text));
+                }
+            }
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<MarkupNode, MarkupError> {
+        self.advance(1);
+        let tag = self.parse_name()?;
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek().map(|(_, c)| *c) {
+                Some('/') => {
+                    self.advance(1);
+                    if self.chars.next_if(|(_, c)| *c == '>').is_none() {
+                        return Err(self.error("expected '>'"));
+                    }
+                    return Ok(MarkupNode::Element {
+                        tag,
+                        attributes,
+                        children: Vec::new(),
+                    });
+                }
+                Some('>') => {
+                    self.advance(1);
+                    let children = if VOID_ELEMENTS.contains(&tag.to_ascii_lowercase().as_str()) {
+                        self.skip_end_tag(&tag);
+                        Vec::new()
+                    } else {
+                        self.parse_children(Some(&tag))?
+                    };
+                    return Ok(MarkupNode::Element {
+                        tag,
+                        attributes,
+                        children,
+                    });
+                }
+                Some(_) => attributes.push(self.parse_attribute()?),
+                None => return Err(self.error(format!("unterminated tag <{}>", tag))),
+            }
+        }
+    }
+
+    fn parse_attribute(&mut self) -> Result<(String, String), MarkupError> {
+        let name = self.parse_name()?;
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == '=').is_none() {
+            // boolean HTML attribute, e.g. `<input disabled>`
+            return Ok((name, String::new()));
+        }
+        self.skip_whitespace();
+
+        let value = match self.chars.next_if(|(_, c)| *c == '"' || *c == '\'') {
+            Some((_, quote)) => {
+                let rest = self.rest();
+                let end = rest
+                    .find(quote)
+                    .ok_or_else(|| self.error("unterminated attribute value"))?;
+                let value = decode_entities(&rest[..end]);
+                self.advance(end + 1);
+                value
+            }
+            None => {
+                let rest = self.rest();
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                let value = decode_entities(&rest[..end]);
+                self.advance(end);
+                value
+            }
+        };
+        Ok((name, value))
+    }
+
+    /// Skips the end tag of a void element, HTML allows it to follow the
+    /// start tag, e.g. `<br></br>`.
+    fn skip_end_tag(&mut self, tag: &str) {
+        let rest = self.rest();
+        let Some(name) = rest.strip_prefix("</") else {
+            return;
+        };
+        let end = name
+            .find(|c: char| c.is_whitespace() || c == '>')
+            .unwrap_or(name.len());
+        if !name[..end].eq_ignore_ascii_case(tag) {
+            return;
+        }
+        let after = name[end..].trim_start();
+        if after.starts_with('>') {
+            self.advance(rest.len() - after.len() + 1);
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, MarkupError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("expected a name"));
+        }
+        let name = rest[..end].to_string();
+        self.advance(end);
+        Ok(name)
+    }
+}
+
+/// Drops whitespace with line breaks from children without text, as it only
+/// lays out block-level elements. Whitespace next to text, or between inline
+/// elements on the same line, is content.
+fn drop_layout(mut children: Vec<MarkupNode>) -> Vec<MarkupNode> {
+    let has_text = children
+        .iter()
+        .any(|child| matches!(child, MarkupNode::Text(text) if !text.trim().is_empty()));
+    if !has_text {
+        children.retain(|child| match child {
+            MarkupNode::Text(text) => !text.contains(['\n', '\r']),
+            MarkupNode::Element { .. } => true,
+        });
+    }
+    children
+}
+
+/// Decodes the predefined XML entities and numeric character references.
+/// Unknown entities are kept as they are.
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yxml_markup::{parse, MarkupNode};
+
+    fn element(tag: &str, attributes: &[(&str, &str)], children: Vec<MarkupNode>) -> MarkupNode {
+        MarkupNode::Element {
+            tag: tag.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            children,
+        }
+    }
+
+    fn text(text: &str) -> MarkupNode {
+        MarkupNode::Text(text.to_string())
+    }
+
+    #[test]
+    fn parse_nested_elements() {
+        let nodes = parse("<paragraph>Hello <bold>x</bold></paragraph>").unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![element(
+                "paragraph",
+                &[],
+                vec![text("Hello "), element("bold", &[], vec![text("x")])]
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_attributes_and_entities() {
+        let nodes = parse(r#"<heading level="2" id='a&amp;b'>1 &lt; 2</heading><br>"#).unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![
+                element(
+                    "heading",
+                    &[("level", "2"), ("id", "a&b")],
+                    vec![text("1 < 2")]
+                ),
+                element("br", &[], vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_whitespace() {
+        let nodes =
+            parse("<doc>\n  <paragraph><bold>a</bold> <italic>b</italic></paragraph>\n</doc>\n")
+                .unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![element(
+                "doc",
+                &[],
+                vec![element(
+                    "paragraph",
+                    &[],
+                    vec![
+                        element("bold", &[], vec![text("a")]),
+                        text(" "),
+                        element("italic", &[], vec![text("b")])
+                    ]
+                )]
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_void_elements_with_end_tags() {
+        let nodes = parse(r#"<p>a<br></br>b<img src="x.png"></IMG></p>"#).unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![element(
+                "p",
+                &[],
+                vec![
+                    text("a"),
+                    element("br", &[], vec![]),
+                    text("b"),
+                    element("img", &[("src", "x.png")], vec![])
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_unquoted_attribute_values() {
+        let nodes = parse("<a href=/path/to title=x>link</a>").unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![element(
+                "a",
+                &[("href", "/path/to"), ("title", "x")],
+                vec![text("link")]
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_mismatched_closing_tag() {
+        let error = parse("<a><b></a>").unwrap_err();
+
+        assert_eq!(error.message, "unexpected closing tag </a>");
+    }
+}
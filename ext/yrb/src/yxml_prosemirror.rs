@@ -0,0 +1,476 @@
+use crate::ytext::apply_delta;
+use crate::yvalue::MAX_SAFE_INTEGER;
+use crate::yxml_query::children;
+use magnus::Error;
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use yrs::types::text::YChange;
+use yrs::types::{Attrs, Delta, Value as YrsValue};
+use yrs::{
+    Any, ReadTxn, Text, TransactionMut, Xml, XmlElementPrelim, XmlElementRef, XmlFragment, XmlNode,
+    XmlTextPrelim, XmlTextRef,
+};
+
+/// Element attribute that holds the JSON encoded marks of a node.
+const MARKS_ATTRIBUTE: &str = "marks";
+
+/// Element attribute that holds the JSON encoded names of the attributes
+/// whose values are JSON, as attribute values can only be strings.
+const JSON_ATTRIBUTES_ATTRIBUTE: &str = "json-attrs";
+
+/// Text attribute used by y-prosemirror to track changes, it is not a mark.
+const YCHANGE_ATTRIBUTE: &str = "ychange";
+
+/// A ProseMirror node that was validated and can be written to a fragment.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Node {
+    Element {
+        tag: String,
+        attributes: Vec<(String, String)>,
+        content: Vec<Node>,
+    },
+    Text {
+        text: String,
+        attributes: Attrs,
+    },
+}
+
+/// Converts the children of a fragment into a ProseMirror `doc` node.
+///
+/// Elements become nodes with their tag as type and their attributes as
+/// `attrs`, texts become text nodes with one node per formatting run, like
+/// y-prosemirror does.
+pub(crate) fn to_json<T: ReadTxn, F: XmlFragment>(txn: &T, fragment: &F) -> JsonValue {
+    let mut doc = JsonMap::new();
+    doc.insert("type".to_string(), JsonValue::from("doc"));
+    doc.insert(
+        "content".to_string(),
+        JsonValue::Array(children_to_json(txn, fragment)),
+    );
+    JsonValue::Object(doc)
+}
+
+fn children_to_json<T: ReadTxn, F: XmlFragment>(txn: &T, parent: &F) -> Vec<JsonValue> {
+    let mut content = Vec::new();
+    for child in children(txn, parent) {
+        match child {
+            XmlNode::Element(element) => content.push(element_to_json(txn, &element)),
+            XmlNode::Text(text) => content.extend(text_to_json(txn, &text)),
+            XmlNode::Fragment(fragment) => content.extend(children_to_json(txn, &fragment)),
+        }
+    }
+    content
+}
+
+fn element_to_json<T: ReadTxn>(txn: &T, element: &XmlElementRef) -> JsonValue {
+    let encoded: HashSet<String> = element
+        .get_attribute(txn, JSON_ATTRIBUTES_ATTRIBUTE)
+        .and_then(|names| serde_json::from_str(&names).ok())
+        .unwrap_or_default();
+    let mut attrs = JsonMap::new();
+    let mut marks = None;
+    for (name, value) in element.attributes(txn) {
+        if name == MARKS_ATTRIBUTE {
+            marks = serde_json::from_str::<JsonValue>(&value).ok();
+        } else if name != JSON_ATTRIBUTES_ATTRIBUTE {
+            attrs.insert(
+                name.to_string(),
+                attribute_to_json(value, encoded.contains(name)),
+            );
+        }
+    }
+
+    let mut node = JsonMap::new();
+    node.insert(
+        "type".to_string(),
+        JsonValue::from(element.tag().to_string()),
+    );
+    if !attrs.is_empty() {
+        node.insert("attrs".to_string(), JsonValue::Object(attrs));
+    }
+    if let Some(marks) = marks.filter(|marks| marks.as_array().is_some_and(|m| !m.is_empty())) {
+        node.insert("marks".to_string(), marks);
+    }
+    let content = children_to_json(txn, element);
+    if !content.is_empty() {
+        node.insert("content".to_string(), JsonValue::Array(content));
+    }
+    JsonValue::Object(node)
+}
+
+/// Attribute values are stored as strings. Only values that were written as
+/// JSON by [insert] (numbers, booleans, arrays or objects) are decoded, all
+/// other values stay strings, even if they look like JSON.
+fn attribute_to_json(value: String, encoded: bool) -> JsonValue {
+    if !encoded {
+        return JsonValue::String(value);
+    }
+    serde_json::from_str(&value).unwrap_or(JsonValue::String(value))
+}
+
+fn text_to_json<T: ReadTxn>(txn: &T, text: &XmlTextRef) -> Vec<JsonValue> {
+    text.diff(txn, YChange::identity)
+        .into_iter()
+        .filter_map(|chunk| {
+            let text = match chunk.insert {
+                YrsValue::Any(Any::String(text)) if !text.is_empty() => text,
+                _ => return None,
+            };
+
+            let mut attributes: Vec<(&Arc<str>, &Any)> = chunk
+                .attributes
+                .iter()
+                .flat_map(|attrs| attrs.iter())
+                .filter(|(name, _)| &***name != YCHANGE_ATTRIBUTE)
+                .collect();
+            attributes.sort_by_key(|(name, _)| *name);
+
+            let mut node = JsonMap::new();
+            node.insert("type".to_string(), JsonValue::from("text"));
+            node.insert("text".to_string(), JsonValue::from(text.to_string()));
+            if !attributes.is_empty() {
+                let marks = attributes
+                    .into_iter()
+                    .map(|(name, value)| mark_to_json(name, value))
+                    .collect();
+                node.insert("marks".to_string(), JsonValue::Array(marks));
+            }
+            Some(JsonValue::Object(node))
+        })
+        .collect()
+}
+
+fn mark_to_json(name: &str, value: &Any) -> JsonValue {
+    let mut mark = JsonMap::new();
+    mark.insert(
+        "type".to_string(),
+        JsonValue::from(decode_mark_name(name).to_string()),
+    );
+    if let Any::Map(attrs) = value {
+        if !attrs.is_empty() {
+            mark.insert("attrs".to_string(), any_to_json(value));
+        }
+    }
+    JsonValue::Object(mark)
+}
+
+/// Removes the `--<hash>` suffix y-prosemirror appends to the names of marks
+/// that can overlap, e.g. `comment--Xy12+/ab` is a `comment` mark.
+pub(crate) fn decode_mark_name(name: &str) -> &str {
+    let Some(split) = name.len().checked_sub(10) else {
+        return name;
+    };
+    if !name.is_char_boundary(split) {
+        return name;
+    }
+    let (prefix, suffix) = name.split_at(split);
+    let is_hash = suffix.starts_with("--")
+        && suffix[2..]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '='));
+    if is_hash {
+        prefix
+    } else {
+        name
+    }
+}
+
+/// Appends an 8 character base64 hash of the mark to its name. Used for
+/// marks that occur more than once on the same text, which can only be
+/// stored as separate attributes with distinct names.
+fn encode_mark_name(name: &str, mark: &JsonValue) -> String {
+    // FNV-1a, the hash only needs to be stable, clients strip it
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in mark.to_string().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let suffix: String = (0..8)
+        .map(|i| ALPHABET[((hash >> (i * 6)) & 0x3f) as usize] as char)
+        .collect();
+    format!("{}--{}", name, suffix)
+}
+
+fn any_to_json(value: &Any) -> JsonValue {
+    match value {
+        Any::Null | Any::Undefined => JsonValue::Null,
+        Any::Bool(v) => JsonValue::Bool(*v),
+        Any::Number(v) if v.fract() == 0.0 && v.abs() <= MAX_SAFE_INTEGER as f64 => {
+            JsonValue::from(*v as i64)
+        }
+        Any::Number(v) => JsonNumber::from_f64(*v).map_or(JsonValue::Null, JsonValue::Number),
+        Any::BigInt(v) => JsonValue::from(*v),
+        Any::String(v) => JsonValue::from(v.to_string()),
+        Any::Buffer(v) => JsonValue::from(v.to_vec()),
+        Any::Array(v) => JsonValue::Array(v.iter().map(any_to_json).collect()),
+        Any::Map(v) => JsonValue::Object(
+            v.iter()
+                .map(|(key, value)| (key.clone(), any_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn json_to_any(value: &JsonValue) -> Any {
+    match value {
+        JsonValue::Null => Any::Null,
+        JsonValue::Bool(v) => Any::Bool(*v),
+        JsonValue::Number(v) => match v.as_i64() {
+            Some(i) if !(-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&i) => Any::BigInt(i),
+            _ => Any::Number(v.as_f64().unwrap_or(f64::NAN)),
+        },
+        JsonValue::String(v) => Any::String(Arc::from(v.as_str())),
+        JsonValue::Array(v) => Any::Array(v.iter().map(json_to_any).collect()),
+        JsonValue::Object(v) => Any::Map(Arc::new(
+            v.iter()
+                .map(|(key, value)| (key.clone(), json_to_any(value)))
+                .collect::<HashMap<String, Any>>(),
+        )),
+    }
+}
+
+/// Validates the content of a ProseMirror `doc` node. Errors name the path
+/// of the invalid node, e.g. `content/0/content/2`.
+pub(crate) fn parse_doc(json: &JsonValue) -> Result<Vec<Node>, String> {
+    let doc = json
+        .as_object()
+        .ok_or_else(|| "expected a ProseMirror doc Hash".to_string())?;
+    parse_content(doc.get("content"), "content")
+}
+
+fn parse_content(content: Option<&JsonValue>, path: &str) -> Result<Vec<Node>, String> {
+    match content {
+        None | Some(JsonValue::Null) => Ok(Vec::new()),
+        Some(JsonValue::Array(nodes)) => nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| parse_node(node, &format!("{}/{}", path, index)))
+            .collect(),
+        Some(_) => Err(format!("expected an Array at {}", path)),
+    }
+}
+
+fn parse_node(json: &JsonValue, path: &str) -> Result<Node, String> {
+    let node = json
+        .as_object()
+        .ok_or_else(|| format!("expected a node Hash at {}", path))?;
+    let node_type = node
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| format!("missing node type at {}", path))?;
+    let marks = parse_marks(node.get("marks"), path)?;
+
+    if node_type == "text" {
+        let text = node
+            .get("text")
+            .and_then(JsonValue::as_str)
+            .ok_or_else(|| format!("missing text at {}", path))?;
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (mark_type, _) in &marks {
+            *counts.entry(mark_type).or_default() += 1;
+        }
+        let mut attributes = Attrs::new();
+        for (mark_type, mark) in &marks {
+            let name = if counts[mark_type] > 1 {
+                encode_mark_name(mark_type, mark)
+            } else {
+                mark_type.to_string()
+            };
+            let attrs = match mark.get("attrs") {
+                Some(attrs @ JsonValue::Object(_)) => json_to_any(attrs),
+                _ => Any::Map(Arc::new(HashMap::new())),
+            };
+            attributes.insert(Arc::from(name), attrs);
+        }
+        return Ok(Node::Text {
+            text: text.to_string(),
+            attributes,
+        });
+    }
+
+    let mut attributes = Vec::new();
+    let mut encoded = Vec::new();
+    match node.get("attrs") {
+        None | Some(JsonValue::Null) => {}
+        Some(JsonValue::Object(attrs)) => {
+            for (name, value) in attrs {
+                match value {
+                    JsonValue::Null => {}
+                    JsonValue::String(value) => attributes.push((name.clone(), value.clone())),
+                    value => {
+                        attributes.push((name.clone(), value.to_string()));
+                        encoded.push(JsonValue::from(name.as_str()));
+                    }
+                }
+            }
+        }
+        Some(_) => return Err(format!("expected attrs to be a Hash at {}", path)),
+    }
+    if !encoded.is_empty() {
+        attributes.push((
+            JSON_ATTRIBUTES_ATTRIBUTE.to_string(),
+            JsonValue::Array(encoded).to_string(),
+        ));
+    }
+    if !marks.is_empty() {
+        let marks = marks.into_iter().map(|(_, mark)| mark).collect();
+        attributes.push((
+            MARKS_ATTRIBUTE.to_string(),
+            JsonValue::Array(marks).to_string(),
+        ));
+    }
+
+    Ok(Node::Element {
+        tag: node_type.to_string(),
+        attributes,
+        content: parse_content(node.get("content"), &format!("{}/content", path))?,
+    })
+}
+
+fn parse_marks<'a>(
+    marks: Option<&'a JsonValue>,
+    path: &str,
+) -> Result<Vec<(&'a str, JsonValue)>, String> {
+    match marks {
+        None | Some(JsonValue::Null) => Ok(Vec::new()),
+        Some(JsonValue::Array(marks)) => marks
+            .iter()
+            .map(|mark| {
+                mark.get("type")
+                    .and_then(JsonValue::as_str)
+                    .map(|mark_type| (mark_type, mark.clone()))
+                    .ok_or_else(|| format!("missing mark type at {}", path))
+            })
+            .collect(),
+        Some(_) => Err(format!("expected marks to be an Array at {}", path)),
+    }
+}
+
+/// Inserts the nodes as children of the parent, starting at the index.
+/// Adjacent text nodes are written into a single XML text, with their marks
+/// as formatting attributes.
+pub(crate) fn insert<F: XmlFragment>(
+    txn: &mut TransactionMut,
+    parent: &F,
+    mut index: u32,
+    nodes: &[Node],
+) -> Result<(), Error> {
+    let mut position = 0;
+    while position < nodes.len() {
+        match &nodes[position] {
+            Node::Element {
+                tag,
+                attributes,
+                content,
+            } => {
+                let element = parent.insert(txn, index, XmlElementPrelim::empty(tag.as_str()));
+                for (name, value) in attributes {
+                    element.insert_attribute(txn, name.as_str(), value.as_str());
+                }
+                insert(txn, &element, 0, content)?;
+                position += 1;
+            }
+            Node::Text { .. } => {
+                let delta: Vec<Delta> = nodes[position..]
+                    .iter()
+                    .map_while(|node| match node {
+                        Node::Text { text, attributes } => {
+                            let attributes =
+                                (!attributes.is_empty()).then(|| Box::new(attributes.clone()));
+                            Some(Delta::Inserted(
+                                YrsValue::Any(Any::String(Arc::from(text.as_str()))),
+                                attributes,
+                            ))
+                        }
+                        Node::Element { .. } => None,
+                    })
+                    .collect();
+                position += delta.len();
+
+                let text = parent.insert(txn, index, XmlTextPrelim::new(""));
+                apply_delta(&text, txn, delta)?;
+            }
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yxml_prosemirror::{decode_mark_name, insert, parse_doc, to_json};
+    use serde_json::json;
+    use yrs::{Doc, Transact};
+
+    #[test]
+    fn decode_hashed_mark_names() {
+        assert_eq!(decode_mark_name("comment--Xy12+/ab"), "comment");
+        assert_eq!(decode_mark_name("bold"), "bold");
+        assert_eq!(decode_mark_name("a--b"), "a--b");
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = json!({
+            "type": "doc",
+            "content": [
+                {
+                    "type": "heading",
+                    "attrs": {"level": 1},
+                    "content": [{"type": "text", "text": "Title"}]
+                },
+                {
+                    "type": "paragraph",
+                    "marks": [{"type": "highlight"}],
+                    "content": [
+                        {"type": "text", "text": "Hello "},
+                        {
+                            "type": "text",
+                            "text": "World",
+                            "marks": [
+                                {"type": "bold"},
+                                {"type": "link", "attrs": {"href": "https://y-crdt.github.io"}}
+                            ]
+                        }
+                    ]
+                }
+            ]
+        });
+
+        let doc = Doc::new();
+        let fragment = doc.get_or_insert_xml_fragment("default");
+        let nodes = parse_doc(&json).unwrap();
+        insert(&mut doc.transact_mut(), &fragment, 0, &nodes).unwrap();
+
+        assert_eq!(to_json(&doc.transact(), &fragment), json);
+    }
+
+    #[test]
+    fn keep_string_attributes() {
+        let json = json!({
+            "type": "doc",
+            "content": [{"type": "codeBlock", "attrs": {"language": "true", "line": 3}}]
+        });
+
+        let doc = Doc::new();
+        let fragment = doc.get_or_insert_xml_fragment("default");
+        let nodes = parse_doc(&json).unwrap();
+        insert(&mut doc.transact_mut(), &fragment, 0, &nodes).unwrap();
+
+        assert_eq!(to_json(&doc.transact(), &fragment), json);
+    }
+
+    #[test]
+    fn parse_invalid_node() {
+        let json = json!({"type": "doc", "content": [{"content": []}]});
+
+        assert_eq!(
+            parse_doc(&json).unwrap_err(),
+            "missing node type at content/0"
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use crate::yevent::xml_node_to_value;
+use magnus::{Error, RArray, Ruby};
+use std::fmt;
+use yrs::{ReadTxn, Xml, XmlFragment, XmlNode};
+
+/// How a compound selector relates to the one on its left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Combinator {
+    /// `a b`: b is a descendant of a
+    Descendant,
+    /// `a > b`: b is a child of a
+    Child,
+}
+
+/// A tag and attribute conditions that a single element must match, e.g.
+/// `heading[level="2"]`. A missing tag matches every element, an attribute
+/// without value only needs to be present.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Compound {
+    pub(crate) tag: Option<String>,
+    pub(crate) attributes: Vec<(String, Option<String>)>,
+}
+
+impl Compound {
+    fn matches<T: ReadTxn>(&self, txn: &T, node: &XmlNode) -> bool {
+        let XmlNode::Element(element) = node else {
+            return false;
+        };
+        if let Some(tag) = &self.tag {
+            if element.tag().to_string() != *tag {
+                return false;
+            }
+        }
+        self.attributes.iter().all(|(name, expected)| {
+            match (element.get_attribute(txn, name), expected) {
+                (Some(value), Some(expected)) => value == *expected,
+                (Some(_), None) => true,
+                (None, _) => false,
+            }
+        })
+    }
+}
+
+/// A small subset of CSS selectors for XML elements: tags, `*`, attribute
+/// conditions (`[name]`, `[name=value]`, `[name="value"]`) and the
+/// descendant and child combinators, e.g. `bulletList > listItem paragraph`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Selector {
+    compounds: Vec<Compound>,
+    /// `combinators[i]` is the relation between `compounds[i]` and
+    /// `compounds[i + 1]`
+    combinators: Vec<Combinator>,
+}
+
+/// Raised for selectors that cannot be parsed, with the byte offset at which
+/// parsing failed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SelectorError {
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl Selector {
+    pub(crate) fn parse(input: &str) -> Result<Self, SelectorError> {
+        SelectorParser { input, offset: 0 }.parse()
+    }
+
+    fn matches<T: ReadTxn>(&self, txn: &T, node: &XmlNode, ancestors: &[XmlNode]) -> bool {
+        let last = self.compounds.len() - 1;
+        self.compounds[last].matches(txn, node) && self.matches_ancestors(txn, last, ancestors)
+    }
+
+    /// Matches the compounds left of `step` against the ancestors, the
+    /// nearest ancestor is last. Descendant combinators try every ancestor
+    /// that matches before giving up.
+    fn matches_ancestors<T: ReadTxn>(&self, txn: &T, step: usize, ancestors: &[XmlNode]) -> bool {
+        if step == 0 {
+            return true;
+        }
+        let compound = &self.compounds[step - 1];
+        match self.combinators[step - 1] {
+            Combinator::Child => match ancestors.split_last() {
+                Some((parent, rest)) => {
+                    compound.matches(txn, parent) && self.matches_ancestors(txn, step - 1, rest)
+                }
+                None => false,
+            },
+            Combinator::Descendant => (0..ancestors.len()).rev().any(|index| {
+                compound.matches(txn, &ancestors[index])
+                    && self.matches_ancestors(txn, step - 1, &ancestors[..index])
+            }),
+        }
+    }
+}
+
+impl From<Compound> for Selector {
+    fn from(compound: Compound) -> Self {
+        Selector {
+            compounds: vec![compound],
+            combinators: Vec::new(),
+        }
+    }
+}
+
+struct SelectorParser<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> SelectorParser<'a> {
+    fn parse(mut self) -> Result<Selector, SelectorError> {
+        let mut compounds = Vec::new();
+        let mut combinators = Vec::new();
+
+        self.skip_whitespace();
+        compounds.push(self.compound()?);
+        loop {
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                None => break,
+                Some('>') => {
+                    self.offset += 1;
+                    self.skip_whitespace();
+                    Combinator::Child
+                }
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(c) => return Err(self.error(format!("unexpected '{c}'"))),
+            };
+            combinators.push(combinator);
+            compounds.push(self.compound()?);
+        }
+
+        Ok(Selector {
+            compounds,
+            combinators,
+        })
+    }
+
+    fn compound(&mut self) -> Result<Compound, SelectorError> {
+        let mut compound = Compound::default();
+        if self.peek() == Some('*') {
+            self.offset += 1;
+        } else {
+            let tag = self.name();
+            if !tag.is_empty() {
+                compound.tag = Some(tag.to_string());
+            } else if self.peek() != Some('[') {
+                return Err(match self.peek() {
+                    Some(c) => self.error(format!("unexpected '{c}'")),
+                    None => self.error("expected a tag".to_string()),
+                });
+            }
+        }
+
+        while self.peek() == Some('[') {
+            self.offset += 1;
+            self.skip_whitespace();
+            let name = self.name();
+            if name.is_empty() {
+                return Err(self.error("expected an attribute name".to_string()));
+            }
+            self.skip_whitespace();
+            let value = if self.peek() == Some('=') {
+                self.offset += 1;
+                self.skip_whitespace();
+                Some(self.value()?)
+            } else {
+                None
+            };
+            self.skip_whitespace();
+            if self.peek() != Some(']') {
+                return Err(self.error("expected ']'".to_string()));
+            }
+            self.offset += 1;
+            compound.attributes.push((name.to_string(), value));
+        }
+        Ok(compound)
+    }
+
+    fn value(&mut self) -> Result<String, SelectorError> {
+        let Some(quote @ ('"' | '\'')) = self.peek() else {
+            let value = self.name();
+            if value.is_empty() {
+                return Err(self.error("expected an attribute value".to_string()));
+            }
+            return Ok(value.to_string());
+        };
+
+        let start = self.offset;
+        self.offset += 1;
+        let mut value = String::new();
+        let mut chars = self.input[self.offset..].chars();
+        while let Some(c) = chars.next() {
+            self.offset += c.len_utf8();
+            match c {
+                '\\' => match chars.next() {
+                    Some(escaped) => {
+                        self.offset += escaped.len_utf8();
+                        value.push(escaped);
+                    }
+                    None => break,
+                },
+                c if c == quote => return Ok(value),
+                c => value.push(c),
+            }
+        }
+        Err(SelectorError {
+            offset: start,
+            message: "unterminated string".to_string(),
+        })
+    }
+
+    /// Tag or attribute name, empty if there is none at the current offset.
+    fn name(&mut self) -> &'a str {
+        let rest = &self.input[self.offset..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "*>[]='\"\\".contains(c))
+            .unwrap_or(rest.len());
+        self.offset += len;
+        &rest[..len]
+    }
+
+    /// Returns if any whitespace was skipped.
+    fn skip_whitespace(&mut self) -> bool {
+        let rest = &self.input[self.offset..];
+        let len = rest.len() - rest.trim_start().len();
+        self.offset += len;
+        len > 0
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.offset..].chars().next()
+    }
+
+    fn error(&self, message: String) -> SelectorError {
+        SelectorError {
+            offset: self.offset,
+            message,
+        }
+    }
+}
+
+/// Walks all descendants of the root in document order, using the tree
+/// walker of yrs. Besides the node, `visit` receives its ancestors below the
+/// root (the parent is last) and its path, the index of every ancestor and of
+/// the node within their parent.
+pub(crate) fn walk<T, F, V>(txn: &T, root: &F, mut visit: V)
+where
+    T: ReadTxn,
+    F: XmlFragment,
+    V: FnMut(&XmlNode, &[XmlNode], &[u32]),
+{
+    let mut ancestors: Vec<XmlNode> = Vec::new();
+    let mut path: Vec<u32> = Vec::new();
+    // index of the next child, for the root and every ancestor
+    let mut next: Vec<u32> = vec![0];
+
+    for node in root.successors(txn) {
+        // the walker goes depth first, so the parent is one of the ancestors
+        // or the root if there is no match
+        let parent = parent(&node);
+        while !ancestors.is_empty() && ancestors.last() != parent.as_ref() {
+            ancestors.pop();
+            path.pop();
+            next.pop();
+        }
+
+        let counter = next.last_mut().expect("root is never popped");
+        path.push(*counter);
+        *counter += 1;
+
+        visit(&node, &ancestors, &path);
+
+        ancestors.push(node);
+        next.push(0);
+    }
+}
+
+/// Returns the children of the parent in order. yrs has no iterator over
+/// the children of a fragment, so the siblings of the first child are used.
+pub(crate) fn children<T: ReadTxn, F: XmlFragment>(txn: &T, parent: &F) -> Vec<XmlNode> {
+    let Some(first) = parent.first_child() else {
+        return Vec::new();
+    };
+    let siblings: Vec<XmlNode> = match &first {
+        XmlNode::Element(element) => element.siblings(txn).collect(),
+        XmlNode::Text(text) => text.siblings(txn).collect(),
+        // fragments are only used as root types, never as children
+        XmlNode::Fragment(_) => Vec::new(),
+    };
+    std::iter::once(first).chain(siblings).collect()
+}
+
+fn parent(node: &XmlNode) -> Option<XmlNode> {
+    match node {
+        XmlNode::Element(element) => element.parent(),
+        XmlNode::Fragment(fragment) => fragment.parent(),
+        XmlNode::Text(text) => text.parent(),
+    }
+}
+
+/// Returns the nodes below the root that match the selector, in document
+/// order.
+pub(crate) fn select<T: ReadTxn, F: XmlFragment>(
+    txn: &T,
+    root: &F,
+    selector: &Selector,
+) -> Vec<XmlNode> {
+    let mut nodes = Vec::new();
+    walk(txn, root, |node, ancestors, _| {
+        if selector.matches(txn, node, ancestors) {
+            nodes.push(node.clone());
+        }
+    });
+    nodes
+}
+
+/// Returns all descendants of the root as Ruby Arrays of node, depth and
+/// path. Children of the root have a depth of 1.
+pub(crate) fn descendants<T: ReadTxn, F: XmlFragment>(ruby: &Ruby, txn: &T, root: &F) -> RArray {
+    let array = ruby.ary_new();
+    walk(txn, root, |node, _, path| {
+        let entry = ruby.ary_new_capa(3);
+        entry
+            .push(xml_node_to_value(ruby, node.clone()))
+            .expect("cannot push value to array");
+        entry.push(path.len()).expect("cannot push value to array");
+        entry
+            .push(ruby.ary_from_vec(path.to_vec()))
+            .expect("cannot push value to array");
+        array.push(entry).expect("cannot push value to array");
+    });
+    array
+}
+
+/// Parses the selector and returns the matching nodes as Ruby objects.
+/// Raises an ArgumentError for invalid selectors.
+pub(crate) fn query<T: ReadTxn, F: XmlFragment>(
+    ruby: &Ruby,
+    txn: &T,
+    root: &F,
+    selector: &str,
+) -> Result<RArray, Error> {
+    let selector = Selector::parse(selector).map_err(|error| {
+        Error::new(
+            ruby.exception_arg_error(),
+            format!("invalid selector: {error}"),
+        )
+    })?;
+    Ok(to_array(ruby, select(txn, root, &selector)))
+}
+
+/// Returns the elements with the tag, or any tag if none is given, that have
+/// all the attributes. Attributes with a nil value only need to be present.
+pub(crate) fn find_all<T: ReadTxn, F: XmlFragment>(
+    ruby: &Ruby,
+    txn: &T,
+    root: &F,
+    tag: Option<String>,
+    attributes: Vec<(String, Option<String>)>,
+) -> RArray {
+    let selector = Selector::from(Compound { tag, attributes });
+    to_array(ruby, select(txn, root, &selector))
+}
+
+fn to_array(ruby: &Ruby, nodes: Vec<XmlNode>) -> RArray {
+    let array = ruby.ary_new_capa(nodes.len());
+    for node in nodes {
+        array
+            .push(xml_node_to_value(ruby, node))
+            .expect("cannot push value to array");
+    }
+    array
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yxml_query::{select, walk, Combinator, Compound, Selector};
+    use yrs::{Doc, GetString, Transact, XmlElementPrelim, XmlFragment, XmlNode, XmlTextPrelim};
+
+    #[test]
+    fn parse_selector() {
+        let selector = Selector::parse(" bulletList > listItem  p[id][lang = 'en'] ").unwrap();
+        assert_eq!(
+            selector,
+            Selector {
+                compounds: vec![
+                    Compound {
+                        tag: Some("bulletList".to_string()),
+                        attributes: vec![],
+                    },
+                    Compound {
+                        tag: Some("listItem".to_string()),
+                        attributes: vec![],
+                    },
+                    Compound {
+                        tag: Some("p".to_string()),
+                        attributes: vec![
+                            ("id".to_string(), None),
+                            ("lang".to_string(), Some("en".to_string())),
+                        ],
+                    },
+                ],
+                combinators: vec![Combinator::Child, Combinator::Descendant],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_invalid_selector() {
+        let error = Selector::parse("a > > b").unwrap_err();
+        assert_eq!(error.to_string(), "unexpected '>' at offset 4");
+        let error = Selector::parse("a[title=\"x]").unwrap_err();
+        assert_eq!(error.to_string(), "unterminated string at offset 8");
+    }
+
+    #[test]
+    fn walk_and_select() {
+        let doc = Doc::new();
+        let fragment = doc.get_or_insert_xml_fragment("default");
+        {
+            let mut txn = doc.transact_mut();
+            let list = fragment.push_back(&mut txn, XmlElementPrelim::empty("ul"));
+            for text in ["a", "b"] {
+                let item = list.push_back(&mut txn, XmlElementPrelim::empty("li"));
+                let p = item.push_back(&mut txn, XmlElementPrelim::empty("p"));
+                p.push_back(&mut txn, XmlTextPrelim::new(text));
+            }
+            fragment.push_back(&mut txn, XmlElementPrelim::empty("p"));
+        }
+
+        let txn = doc.transact();
+        let mut paths = Vec::new();
+        walk(&txn, &fragment, |_, ancestors, path| {
+            assert_eq!(ancestors.len() + 1, path.len());
+            paths.push(path.to_vec());
+        });
+        assert_eq!(
+            paths,
+            vec![
+                vec![0],
+                vec![0, 0],
+                vec![0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 1],
+                vec![0, 1, 0],
+                vec![0, 1, 0, 0],
+                vec![1],
+            ]
+        );
+
+        let selector = Selector::parse("ul > li p").unwrap();
+        let texts: Vec<String> = select(&txn, &fragment, &selector)
+            .into_iter()
+            .map(|node| match node {
+                XmlNode::Element(element) => element.get_string(&txn),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(texts, vec!["<p>a</p>", "<p>b</p>"]);
+        assert!(select(&txn, &fragment, &Selector::parse("ul > p").unwrap()).is_empty());
+    }
+}
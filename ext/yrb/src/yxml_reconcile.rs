@@ -0,0 +1,366 @@
+use crate::ytext_diff::{self, Token};
+use crate::yxml_prosemirror::{self, Node};
+use crate::yxml_query::children;
+use magnus::Error;
+use std::collections::HashMap;
+use std::sync::Arc;
+use yrs::types::text::YChange;
+use yrs::types::{Attrs, Value as YrsValue};
+use yrs::{
+    Any, OffsetKind, ReadTxn, Text, TransactionMut, Xml, XmlElementRef, XmlFragment, XmlNode,
+    XmlTextRef,
+};
+
+/// Text attribute used by y-prosemirror to track changes, it is kept as is.
+const YCHANGE_ATTRIBUTE: &str = "ychange";
+
+/// Updates the children of the parent to match the target nodes with as few
+/// changes as possible, following y-prosemirror's `updateYFragment`:
+///
+/// 1. children that are equal to their target are skipped from the left and
+///    from the right
+/// 2. the remaining children are updated in place if their tag matches the
+///    target, preferring the side with more equal descendants, otherwise
+///    they are replaced
+/// 3. left over children are removed and left over targets are inserted
+///
+/// Texts are updated with a character diff and reformatted, so that
+/// concurrent edits of unchanged parts are kept.
+pub(crate) fn reconcile<F: XmlFragment>(
+    txn: &mut TransactionMut,
+    parent: &F,
+    target: &[Node],
+) -> Result<(), Error> {
+    let targets = group(target);
+    let current = children(txn, parent);
+    let (current_len, target_len) = (current.len(), targets.len());
+    let min = current_len.min(target_len);
+
+    let mut left = 0;
+    while left < min && is_equal(txn, &current[left], targets[left]) {
+        left += 1;
+    }
+    let mut right = 0;
+    while right + left + 1 < min
+        && is_equal(
+            txn,
+            &current[current_len - right - 1],
+            targets[target_len - right - 1],
+        )
+    {
+        right += 1;
+    }
+
+    while current_len - left - right > 0 && target_len - left - right > 0 {
+        let (left_node, left_target) = (&current[left], targets[left]);
+        if let (XmlNode::Text(text), [Node::Text { .. }, ..]) = (left_node, left_target) {
+            if !is_equal_text(txn, text, left_target) {
+                update_text(txn, text, left_target);
+            }
+            left += 1;
+            continue;
+        }
+
+        let right_node = &current[current_len - right - 1];
+        let right_target = targets[target_len - right - 1];
+        let mut update_left = matches_tag(left_node, left_target);
+        let mut update_right = matches_tag(right_node, right_target);
+        if update_left && update_right {
+            if equality_factor(txn, left_node, left_target)
+                < equality_factor(txn, right_node, right_target)
+            {
+                update_left = false;
+            } else {
+                update_right = false;
+            }
+        }
+
+        match (left_node, right_node) {
+            (XmlNode::Element(element), _) if update_left => {
+                update_element(txn, element, &left_target[0])?;
+                left += 1;
+            }
+            (_, XmlNode::Element(element)) if update_right => {
+                update_element(txn, element, &right_target[0])?;
+                right += 1;
+            }
+            _ => {
+                parent.remove_range(txn, left as u32, 1);
+                yxml_prosemirror::insert(txn, parent, left as u32, left_target)?;
+                left += 1;
+            }
+        }
+    }
+
+    let remove = current_len - left - right;
+    if current_len == 1 && target_len == 0 {
+        // keep a single text and only remove its content, so that
+        // concurrent edits of the text are not lost
+        if let XmlNode::Text(text) = &current[0] {
+            let len = text.len(txn);
+            text.remove_range(txn, 0, len);
+            return Ok(());
+        }
+    }
+    if remove > 0 {
+        parent.remove_range(txn, left as u32, remove as u32);
+    }
+    for (offset, target) in targets[left..target_len - right].iter().enumerate() {
+        yxml_prosemirror::insert(txn, parent, (left + offset) as u32, target)?;
+    }
+    Ok(())
+}
+
+/// Splits nodes into the children of an XML fragment: elements on their own
+/// and runs of adjacent text nodes, which are stored in one XML text.
+fn group(nodes: &[Node]) -> Vec<&[Node]> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    while start < nodes.len() {
+        let end = match nodes[start] {
+            Node::Element { .. } => start + 1,
+            Node::Text { .. } => nodes[start..]
+                .iter()
+                .position(|node| matches!(node, Node::Element { .. }))
+                .map_or(nodes.len(), |len| start + len),
+        };
+        groups.push(&nodes[start..end]);
+        start = end;
+    }
+    groups
+}
+
+fn matches_tag(node: &XmlNode, target: &[Node]) -> bool {
+    match (node, target) {
+        (XmlNode::Element(element), [Node::Element { tag, .. }]) => {
+            element.tag().to_string() == *tag
+        }
+        _ => false,
+    }
+}
+
+fn update_element(
+    txn: &mut TransactionMut,
+    element: &XmlElementRef,
+    target: &Node,
+) -> Result<(), Error> {
+    let Node::Element {
+        attributes,
+        content,
+        ..
+    } = target
+    else {
+        return Ok(());
+    };
+
+    let current: HashMap<String, String> = element
+        .attributes(txn)
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    for (name, value) in attributes {
+        if current.get(name) != Some(value) {
+            element.insert_attribute(txn, name.as_str(), value.as_str());
+        }
+    }
+    for name in current.keys() {
+        if !attributes.iter().any(|(target, _)| target == name) {
+            element.remove_attribute(txn, name);
+        }
+    }
+
+    reconcile(txn, element, content)
+}
+
+/// Replaces the content of the text with the target texts: the characters
+/// are diffed, then every run is formatted with its marks, removing all
+/// other formatting attributes.
+fn update_text(txn: &mut TransactionMut, text: &XmlTextRef, target: &[Node]) {
+    let mut old = Vec::new();
+    let mut names: Vec<Arc<str>> = Vec::new();
+    for chunk in text.diff(txn, YChange::identity) {
+        match chunk.insert {
+            YrsValue::Any(Any::String(s)) => old.extend(s.chars().map(Token::Char)),
+            _ => old.push(Token::Embed),
+        }
+        for name in chunk.attributes.iter().flat_map(|attrs| attrs.keys()) {
+            if &**name != YCHANGE_ATTRIBUTE && !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+    }
+
+    let runs = runs(target);
+    let new: Vec<char> = runs.iter().flat_map(|(s, _)| s.chars()).collect();
+    // documents always count offsets in UTF-16 code units (see `doc_options`)
+    let offset_kind = OffsetKind::Utf16;
+
+    // apply from the end, so that indices of earlier edits stay valid
+    for edit in ytext_diff::diff(&old, &new, offset_kind).into_iter().rev() {
+        if edit.remove > 0 {
+            text.remove_range(txn, edit.index, edit.remove);
+        }
+        if !edit.insert.is_empty() {
+            text.insert(txn, edit.index, edit.insert.as_str());
+        }
+    }
+
+    let mut index = 0;
+    for (s, attributes) in runs {
+        let len: u32 = s.chars().map(|c| Token::Char(c).len(offset_kind)).sum();
+        let mut attrs: Attrs = names.iter().map(|name| (name.clone(), Any::Null)).collect();
+        attrs.extend(attributes.clone());
+        if !attrs.is_empty() {
+            text.format(txn, index, len, attrs);
+        }
+        index += len;
+    }
+}
+
+/// Text and formatting of target text nodes.
+fn runs(target: &[Node]) -> Vec<(&str, &Attrs)> {
+    target
+        .iter()
+        .filter_map(|node| match node {
+            Node::Text { text, attributes } if !text.is_empty() => {
+                Some((text.as_str(), attributes))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_equal<T: ReadTxn>(txn: &T, node: &XmlNode, target: &[Node]) -> bool {
+    match (node, target) {
+        (XmlNode::Text(text), [Node::Text { .. }, ..]) => is_equal_text(txn, text, target),
+        (
+            XmlNode::Element(element),
+            [Node::Element {
+                tag,
+                attributes,
+                content,
+            }],
+        ) => {
+            let current: HashMap<String, String> = element
+                .attributes(txn)
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            let children = children(txn, element);
+            let targets = group(content);
+
+            element.tag().to_string() == *tag
+                && current.len() == attributes.len()
+                && attributes
+                    .iter()
+                    .all(|(name, value)| current.get(name) == Some(value))
+                && children.len() == targets.len()
+                && children
+                    .iter()
+                    .zip(targets)
+                    .all(|(child, target)| is_equal(txn, child, target))
+        }
+        _ => false,
+    }
+}
+
+/// Compares texts by formatting runs, adjacent runs with the same
+/// formatting are merged on both sides.
+fn is_equal_text<T: ReadTxn>(txn: &T, text: &XmlTextRef, target: &[Node]) -> bool {
+    let mut current: Vec<(String, Attrs)> = Vec::new();
+    for chunk in text.diff(txn, YChange::identity) {
+        let YrsValue::Any(Any::String(s)) = chunk.insert else {
+            return false;
+        };
+        let mut attrs = chunk.attributes.map(|attrs| *attrs).unwrap_or_default();
+        attrs.remove(YCHANGE_ATTRIBUTE);
+        push_run(&mut current, &s, attrs);
+    }
+
+    let mut expected: Vec<(String, Attrs)> = Vec::new();
+    for (s, attrs) in runs(target) {
+        push_run(&mut expected, s, attrs.clone());
+    }
+    current == expected
+}
+
+fn push_run(runs: &mut Vec<(String, Attrs)>, s: &str, attrs: Attrs) {
+    match runs.last_mut() {
+        Some((text, last)) if *last == attrs => text.push_str(s),
+        _ => runs.push((s.to_string(), attrs)),
+    }
+}
+
+/// Number of children that are equal to their target, counted from the
+/// left and from the right. Used to pick which of two candidates to update.
+fn equality_factor<T: ReadTxn>(txn: &T, node: &XmlNode, target: &[Node]) -> usize {
+    let (XmlNode::Element(element), [Node::Element { content, .. }]) = (node, target) else {
+        return 0;
+    };
+    let children = children(txn, element);
+    let targets = group(content);
+    let min = children.len().min(targets.len());
+
+    let mut left = 0;
+    while left < min && is_equal(txn, &children[left], targets[left]) {
+        left += 1;
+    }
+    let mut right = 0;
+    while left + right < min
+        && is_equal(
+            txn,
+            &children[children.len() - right - 1],
+            targets[targets.len() - right - 1],
+        )
+    {
+        right += 1;
+    }
+    left + right
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yxml_prosemirror::{insert, parse_doc, to_json};
+    use crate::yxml_reconcile::reconcile;
+    use serde_json::{json, Value as JsonValue};
+    use yrs::{Doc, GetString, Transact, XmlFragment};
+
+    fn paragraphs(texts: &[&str]) -> JsonValue {
+        let content: Vec<JsonValue> = texts
+            .iter()
+            .map(|text| json!({"type": "paragraph", "content": [{"type": "text", "text": text}]}))
+            .collect();
+        json!({"type": "doc", "content": content})
+    }
+
+    #[test]
+    fn reconcile_keeps_unchanged_nodes() {
+        let doc = Doc::new();
+        let fragment = doc.get_or_insert_xml_fragment("default");
+        let nodes = parse_doc(&paragraphs(&["a", "b", "c"])).unwrap();
+        insert(&mut doc.transact_mut(), &fragment, 0, &nodes).unwrap();
+        let first = fragment.get(&doc.transact(), 0).unwrap();
+
+        let target = paragraphs(&["a", "b!", "c", "d"]);
+        let nodes = parse_doc(&target).unwrap();
+        reconcile(&mut doc.transact_mut(), &fragment, &nodes).unwrap();
+
+        let txn = doc.transact();
+        assert_eq!(to_json(&txn, &fragment), target);
+        assert_eq!(fragment.get(&txn, 0).unwrap(), first);
+    }
+
+    #[test]
+    fn reconcile_text_edits_in_place() {
+        let doc = Doc::new();
+        let fragment = doc.get_or_insert_xml_fragment("default");
+        let nodes = parse_doc(&paragraphs(&["Hello World"])).unwrap();
+        insert(&mut doc.transact_mut(), &fragment, 0, &nodes).unwrap();
+
+        let nodes = parse_doc(&paragraphs(&["Hello Ruby World"])).unwrap();
+        reconcile(&mut doc.transact_mut(), &fragment, &nodes).unwrap();
+
+        assert_eq!(
+            fragment.get_string(&doc.transact()),
+            "<paragraph>Hello Ruby World</paragraph>"
+        );
+    }
+}
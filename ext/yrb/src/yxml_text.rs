@@ -1,15 +1,14 @@
-use crate::utils::map_rhash_to_attrs;
+use crate::utils::{defer_error, map_rhash_to_attrs};
 use crate::ydiff::YDiff;
+use crate::yevent::YEvent;
 use crate::yvalue::YValue;
 use crate::yxml_fragment::YXmlFragment;
 use crate::{YTransaction, YXmlElement};
 use magnus::block::Proc;
-use magnus::value::Qnil;
 use magnus::{Error, IntoValue, RArray, RHash, Ruby, Value};
 use std::cell::RefCell;
 use yrs::types::text::YChange;
-use yrs::types::Delta;
-use yrs::{Any, GetString, Observable, Text, Xml, XmlNode, XmlTextRef};
+use yrs::{GetString, Observable, Text, Xml, XmlNode, XmlTextRef};
 
 #[magnus::wrap(class = "Y::XMLText")]
 pub(crate) struct YXmlText(pub(crate) RefCell<XmlTextRef>);
@@ -36,10 +35,13 @@ impl YXmlText {
         length: u32,
         attrs: RHash,
     ) -> Result<(), Error> {
+        let a = map_rhash_to_attrs(attrs, transaction.conversion_options())?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        map_rhash_to_attrs(attrs).map(|a| self.0.borrow_mut().format(tx, index, length, a))
+        self.0.borrow_mut().format(tx, index, length, a);
+        Ok(())
     }
     pub(crate) fn yxml_text_get_attribute(
         &self,
@@ -75,32 +77,32 @@ impl YXmlText {
         content: Value,
         attrs: RHash,
     ) -> Result<(), Error> {
+        let options = transaction.conversion_options();
+        let yvalue = YValue::from(content);
+        let avalue = yvalue.try_into_any(options)?;
+        let a = map_rhash_to_attrs(attrs, options)?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        let yvalue = YValue::from(content);
-        let avalue = Any::from(yvalue);
-
-        map_rhash_to_attrs(attrs)
-            .map(|a| {
-                self.0
-                    .borrow_mut()
-                    .insert_embed_with_attributes(tx, index, avalue, a)
-            })
-            .map(|_| ())
+        self.0
+            .borrow_mut()
+            .insert_embed_with_attributes(tx, index, avalue, a);
+        Ok(())
     }
     pub(crate) fn yxml_text_insert_embed(
         &self,
         transaction: &YTransaction,
         index: u32,
         embed: Value,
-    ) {
+    ) -> Result<(), Error> {
+        let avalue = YValue::from(embed).try_into_any(transaction.conversion_options())?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        self.0
-            .borrow_mut()
-            .insert_embed(tx, index, Any::from(YValue::from(embed)));
+        self.0.borrow_mut().insert_embed(tx, index, avalue);
+        Ok(())
     }
     pub(crate) fn yxml_text_insert_with_attributes(
         &self,
@@ -109,14 +111,15 @@ impl YXmlText {
         content: String,
         attrs: RHash,
     ) -> Result<(), Error> {
+        let a = map_rhash_to_attrs(attrs, transaction.conversion_options())?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        map_rhash_to_attrs(attrs).map(|a| {
-            self.0
-                .borrow_mut()
-                .insert_with_attributes(tx, index, content.as_str(), a);
-        })
+        self.0
+            .borrow_mut()
+            .insert_with_attributes(tx, index, content.as_str(), a);
+        Ok(())
     }
     pub(crate) fn yxml_text_length(&self, transaction: &YTransaction) -> u32 {
         let tx = transaction.transaction();
@@ -188,98 +191,45 @@ impl YXmlText {
         self.0.borrow().get_string(tx)
     }
 
-    pub(crate) fn yxml_text_diff(&self, transaction: &YTransaction) -> RArray {
+    pub(crate) fn yxml_text_diff(&self, transaction: &YTransaction) -> Result<RArray, Error> {
         let ruby = unsafe { Ruby::get_unchecked() };
         let tx = transaction.transaction();
         let tx = tx.as_ref().unwrap();
 
         let array = ruby.ary_new();
         for diff in self.0.borrow().diff(tx, YChange::identity).iter() {
-            let yvalue = YValue::from(diff.insert.clone());
+            let yvalue = YValue::try_from(diff.insert.clone())?;
             let insert = yvalue.0.into_inner();
-            let attributes = diff.attributes.as_ref().map_or_else(
-                || None,
-                |boxed_attrs| {
+            let attributes = match diff.attributes.as_ref() {
+                Some(boxed_attrs) => {
                     let attributes = ruby.hash_new();
                     for (key, value) in boxed_attrs.iter() {
                         let key = key.to_string();
-                        let value = YValue::from(value.clone()).0.into_inner();
-                        attributes.aset(key, value).expect("cannot add value");
+                        let value = YValue::try_from(value.clone())?.0.into_inner();
+                        attributes.aset(key, value)?;
                     }
                     Some(attributes)
-                },
-            );
+                }
+                None => None,
+            };
             let ydiff = YDiff {
                 ydiff_insert: insert,
                 ydiff_attrs: attributes,
             };
-            array
-                .push(ydiff.into_value_with(&ruby))
-                .expect("cannot push diff to array");
+            array.push(ydiff.into_value_with(&ruby))?;
         }
-        array
+        Ok(array)
     }
 
     pub(crate) fn yxml_text_observe(&self, block: Proc) -> Result<u32, Error> {
-        let ruby = unsafe { Ruby::get_unchecked() };
-        let delta_insert = ruby.to_symbol("insert").to_static();
-        let delta_retain = ruby.to_symbol("retain").to_static();
-        let delta_delete = ruby.to_symbol("delete").to_static();
-        let attributes = ruby.to_symbol("attributes").to_static();
-
         let subscription_id = self
             .0
             .borrow_mut()
-            .observe(move |transaction, text_event| {
-                let ruby = unsafe { Ruby::get_unchecked() };
-                let delta = text_event.delta(transaction);
-                for change in delta.iter() {
-                    let payload = ruby.hash_new();
-                    match change {
-                        Delta::Inserted(value, attrs) => {
-                            let yvalue = YValue::from(value.clone());
-                            payload
-                                .aset(delta_insert, yvalue.0.into_inner())
-                                .expect("cannot set insert");
-                            if let Some(a) = attrs {
-                                let attrs_hash = ruby.hash_new();
-                                for (key, val) in a.iter() {
-                                    let yvalue = YValue::from(val.clone());
-                                    attrs_hash
-                                        .aset(key.to_string(), yvalue.0.into_inner())
-                                        .expect("cannot add attr");
-                                }
-                                payload
-                                    .aset(attributes, attrs_hash)
-                                    .expect("cannot set attrs");
-                            }
-                        }
-                        Delta::Retain(index, attrs) => {
-                            let yvalue = YValue::from(*index);
-                            payload
-                                .aset(delta_retain, yvalue.0.into_inner())
-                                .expect("cannot set retain");
-                            if let Some(a) = attrs {
-                                let attrs_hash = ruby.hash_new();
-                                for (key, val) in a.iter() {
-                                    let yvalue = YValue::from(val.clone());
-                                    attrs_hash
-                                        .aset(key.to_string(), yvalue.0.into_inner())
-                                        .expect("cannot add attr");
-                                }
-                                payload
-                                    .aset(attributes, attrs_hash)
-                                    .expect("cannot set attrs");
-                            }
-                        }
-                        Delta::Deleted(index) => {
-                            let yvalue = YValue::from(*index);
-                            payload
-                                .aset(delta_delete, yvalue.0.into_inner())
-                                .expect("cannot set delete");
-                        }
-                    }
-                    let _ = block.call::<(RHash,), Qnil>((payload,));
+            .observe(move |transaction, event| {
+                let result = YEvent::xml_text(transaction, event)
+                    .and_then(|event| block.call::<(Value,), Value>((event,)));
+                if let Err(error) = result {
+                    defer_error(error);
                 }
             })
             .into();
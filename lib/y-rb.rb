@@ -11,14 +11,21 @@ end
 
 require_relative "y/array"
 require_relative "y/awareness"
+require_relative "y/codecs"
 require_relative "y/diff"
 require_relative "y/doc"
+require_relative "y/errors"
+require_relative "y/event"
 require_relative "y/snapshot"
 require_relative "y/map"
+require_relative "y/room"
+require_relative "y/sync"
 require_relative "y/text"
 require_relative "y/xml"
+require_relative "y/xml_schema"
 require_relative "y/transaction"
 require_relative "y/undo_manager"
+require_relative "y/update"
 require_relative "y/version"
 
 module Y
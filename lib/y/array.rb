@@ -65,22 +65,26 @@ module Y
 
     # Attach listener to array changes
     #
+    # The listener is called once per transaction that changes the array,
+    # with a {Y::ArrayEvent}.
+    #
     # @example Listen to changes in array type
     #   local = Y::Doc.new
     #
     #   arr = local.get_array("my array")
-    #   arr.attach { |delta| pp delta }
+    #   arr.attach { |event| pp event.delta }
     #
     #   local.transact do
     #     arr << 1
     #   end
+    #   # => [{ insert: [1] }]
     #
     # @param block [Block]
     # @return [Integer]
     def attach(&block)
       raise "provide block" unless block
 
-      yarray_observe(block.to_proc)
+      yarray_observe(Event.listener(document, block))
     end
 
     # Adds to array all elements from each Array in `other_arrays`.
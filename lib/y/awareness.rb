@@ -43,7 +43,7 @@ module Y
   #   client_b.local_state = local_state_b
   #
   #   client_a.sync(client_b.diff)
-  #   client_a.clients # {1242157267=>"{\"name\":\"User A\"}", 2401067547=>…
+  #   client_a.clients # {1242157267=>{"name"=>"User A"}, 2401067547=>…
   class Awareness
     # Applies an incoming update. This gets the local awareness instance in
     # sync with changes from another client. i.e., updates the state of another
@@ -56,9 +56,11 @@ module Y
     #   awareness.sync(update)
     #
     # @param diff [Array<Integer>] A binary encoded update
+    # @param origin [String, nil] An optional origin that is passed on to the
+    #   events emitted while applying the update
     # @return [void]
-    def sync(diff)
-      yawareness_apply_update(diff)
+    def sync(diff, origin = nil)
+      yawareness_apply_update(diff, origin)
     end
 
     # Clears out a state of a current client, effectively marking it as
@@ -78,8 +80,8 @@ module Y
 
     # Returns a state map of all of the clients tracked by current Awareness
     # instance. Those states are identified by their corresponding ClientIDs.
-    # The associated state is represented and replicated to other clients as a
-    # JSON string.
+    # The associated state is replicated to other clients as JSON, but it is
+    # converted to Ruby values (Hash, Array, String, …) before it is returned.
     #
     # @example Instantiate awareness instance and encode update for broadcast
     #   local_state = {
@@ -89,14 +91,20 @@ module Y
     #
     #   awareness = Y::Awareness.new
     #   awareness.local_state = local_state
-    #   awareness.clients # {312134501=>"{\"editing\":{\"field\":\"descriptio …
+    #   awareness.clients # {312134501=>{"editing"=>{"field"=>"descriptio …
     #
-    # @return [Hash] All clients and their current state
+    # @return [Hash<Integer, Object>] All clients and their current state
     def clients
-      transform = yawareness_clients.map do |client_id, state|
-        [client_id, JSON.parse!(state)]
-      end
-      transform.to_h
+      yawareness_clients_value
+    end
+
+    # Returns a state map of all of the clients tracked by current Awareness
+    # instance, with each state being the raw JSON string as it is replicated
+    # to other clients.
+    #
+    # @return [Hash<Integer, String>] All clients and their JSON encoded state
+    def clients_json
+      yawareness_clients
     end
 
     # Returns the state of the local Awareness instance.
@@ -109,17 +117,26 @@ module Y
     #
     #   awareness = Y::Awareness.new
     #   awareness.local_state = local_state
-    #   awareness.local_state # {  editing: { field: "description", ...
+    #   awareness.local_state # { "editing" => { "field" => "description", ...
     #
-    # @return [String] The current state of the local client
+    # @return [Object, nil] The current state of the local client
     def local_state
-      json = yawareness_local_state
-      JSON.parse!(json) if json
+      yawareness_local_state_value
+    end
+
+    # Returns the state of the local Awareness instance as JSON string.
+    #
+    # @return [String, nil] The current JSON encoded state of the local client
+    def local_state_json
+      yawareness_local_state
     end
 
-    # Sets a current Awareness instance state to a corresponding JSON string.
-    # This state will be replicated to other clients as part of the
-    # AwarenessUpdate.
+    # Sets a current Awareness instance state. This state will be replicated to
+    # other clients as part of the AwarenessUpdate.
+    #
+    # Hashes, Arrays, Strings, Symbols, Integers, Floats, booleans and nil are
+    # converted to JSON natively. Any other object is encoded by calling
+    # `#to_json` on it.
     #
     # @example Set local state
     #   local_state = {
@@ -130,18 +147,75 @@ module Y
     #   awareness = Y::Awareness.new
     #   awareness.local_state = local_state
     #
-    # @param [#to_json] state
+    # @param [Hash, Array, String, #to_json] state
     # @return [void]
     def local_state=(state)
-      raise "state cannot be encoded to JSON" unless state.respond_to? :to_json
+      case state
+      when Hash, ::Array, String, Symbol, Integer, Float, true, false, nil
+        yawareness_set_local_state_value(state)
+      else
+        unless state.respond_to?(:to_json)
+          raise "state cannot be encoded to JSON"
+        end
 
-      yawareness_set_local_state(state.to_json)
+        yawareness_set_local_state(state.to_json)
+      end
+    end
+
+    # Sets a current Awareness instance state to a JSON string. The string is
+    # replicated as-is and must be valid JSON.
+    #
+    # @param [String] json
+    # @return [void]
+    def local_state_json=(json)
+      yawareness_set_local_state(json)
     end
 
-    # Subscribes to changes
+    # Subscribes to updates. This is an alias for {#on_update}.
     #
-    # @return [Integer] The subscription ID
+    # @return [Y::Awareness::Subscription] The subscription
     def attach(callback = nil, &block)
+      on_update(callback, &block)
+    end
+
+    # Removes a subscription created by {#attach}, {#on_change} or
+    # {#on_update}.
+    #
+    # @param subscription [Y::Awareness::Subscription]
+    # @return [void]
+    def detach(subscription)
+      subscription.unsubscribe
+    end
+
+    # Subscribes to changes of the state of any client. Unlike {#on_update},
+    # the listener is only called when a state was added or removed, or when
+    # its content changed. Clock bumps that keep a client alive are ignored.
+    #
+    # @example Listen to changes
+    #   awareness = Y::Awareness.new
+    #   awareness.on_change do |event|
+    #     pp event.added, event.updated, event.removed, event.origin
+    #   end
+    #
+    #   awareness.local_state = { name: "User A" } # emits with origin "local"
+    #
+    # @yield [event] Called when the state of at least one client changed
+    # @yieldparam [Y::AwarenessEvent] event
+    # @return [Y::Awareness::Subscription] The subscription
+    def on_change(callback = nil, &block)
+      return yawareness_on_change(callback) unless callback.nil?
+
+      yawareness_on_change(block.to_proc) unless block.nil?
+    end
+
+    # Subscribes to updates of any client. The listener is called for every
+    # applied update, including updates that only renew the clock of a client
+    # without changing its state.
+    #
+    # @yield [event] Called for every update
+    # @yieldparam [Y::AwarenessEvent] event
+    # @return [Y::Awareness::Subscription] The subscription
+    def on_update(callback = nil, &block)
       return yawareness_on_update(callback) unless callback.nil?
 
       yawareness_on_update(block.to_proc) unless block.nil?
@@ -179,18 +253,11 @@ module Y
     # rubocop:disable Lint/UselessAccessModifier
     private
 
-    # @!method yawareness_apply_update(update)
+    # @!method yawareness_apply_update(update, origin)
     #   Applies an update
     #
-    # @param A [Y::AwarenessUpdate] Structure that represents an encodable state
-    #   of an Awareness struct.
-    # @!visibility private
-
-    # @!method yawareness_apply_update(update)
-    #   Applies an update
-    #
-    # @param A [Y::AwarenessUpdate] Structure that represents an encodable state
-    #   of an Awareness struct.
+    # @param update [::Array<Integer>] A binary encoded update
+    # @param origin [String, nil] Origin passed on to emitted events
     # @!visibility private
 
     # @!method yawareness_clean_local_state
@@ -212,21 +279,35 @@ module Y
     # @return [Hash<Integer, String>] Map of clients
     # @!visibility private
 
+    # @!method yawareness_clients_value
+    #   Returns a state map of all of the clients tracked by current Awareness
+    #   instance, with each JSON state converted to Ruby values.
+    #
+    # @return [Hash<Integer, Object>] Map of clients
+    # @!visibility private
+
     # @!method yawareness_local_state
     #
     # @return [String, nil] Returns a JSON string state representation of a
     #   current Awareness instance.
     # @!visibility private
 
-    # @!method yawareness_on_update(callback, &block)
+    # @!method yawareness_local_state_value
     #
-    # @param callback [callback]
-    # @return [Integer] The subscription ID
+    # @return [Object, nil] Returns the state of the current Awareness instance
+    #   converted to Ruby values.
     # @!visibility private
 
-    # @!method yawareness_remove_on_update(subscription_id)
+    # @!method yawareness_on_change(callback)
     #
-    # @param subscription_id [Integer] The subscription id to remove
+    # @param callback [Proc]
+    # @return [Y::Awareness::Subscription] The subscription
+    # @!visibility private
+
+    # @!method yawareness_on_update(callback)
+    #
+    # @param callback [Proc]
+    # @return [Y::Awareness::Subscription] The subscription
     # @!visibility private
 
     # @!method yawareness_remove_state(client_id)
@@ -250,6 +331,16 @@ module Y
     #   replicated to other clients as a JSON string.
     # @!visibility private
 
+    # @!method yawareness_set_local_state_value(state)
+    #   Converts the given Ruby value to JSON and sets it as the state of the
+    #   current Awareness instance.
+    #
+    # @param state [Hash, Array, String, Symbol, Integer, Float, true, false,
+    #   nil]
+    # @raise [TypeError] if the state contains a value that cannot be
+    #   represented as JSON
+    # @!visibility private
+
     # @!method yawareness_update
     #   Returns a serializable update object which is representation of a
     #   current Awareness state.
@@ -271,20 +362,48 @@ module Y
     # rubocop:enable Lint/UselessAccessModifier
   end
 
-  # @!visibility private
+  # An event emitted by {Y::Awareness#on_change} and
+  # {Y::Awareness#on_update} listeners.
   class AwarenessEvent
-    private # rubocop:disable Lint/UselessAccessModifier
-
     # @!method added
     # @return [::Array<Integer>] Added clients
-    # @!visibility private
 
     # @!method updated
     # @return [::Array<Integer>] Updated clients
-    # @!visibility private
 
     # @!method removed
     # @return [::Array<Integer>] Removed clients
-    # @!visibility private
+
+    # @!method origin
+    # @return [String, nil] The origin passed to {Y::Awareness#sync}, or
+    #   "local" if the event was caused by a change of the local state
+  end
+
+  class Awareness
+    # A subscription to awareness events. A subscription stays active until
+    # {#unsubscribe} is called or the awareness instance is garbage collected.
+    class Subscription
+      # Removes the listener. Calling this method more than once is a no-op.
+      #
+      # @return [void]
+      def unsubscribe
+        yawareness_subscription_unsubscribe
+      end
+
+      # @return [true, false] True if the listener is still attached
+      def subscribed?
+        yawareness_subscription_subscribed
+      end
+
+      # @!method yawareness_subscription_unsubscribe
+      #
+      # @return [void]
+      # @!visibility private
+
+      # @!method yawareness_subscription_subscribed
+      #
+      # @return [Boolean]
+      # @!visibility private
+    end
   end
 end
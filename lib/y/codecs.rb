@@ -0,0 +1,142 @@
+# frozen_string_literal: true
+
+module Y
+  # Registry of codecs that convert custom Ruby objects into values that can
+  # be stored in a document and back
+  #
+  # Objects that are neither nil, booleans, numbers, strings, symbols, arrays
+  # nor hashes are encoded with the codec registered for their class (or one
+  # of its ancestors). The encoded value is stored as a map with the codec
+  # name and the value, `{"$codec" => "Time", "value" => ...}`, and decoded
+  # when it is read.
+  #
+  # Without a codec, conversion raises a {Y::UnsupportedTypeError}. When the
+  # fallback is enabled, objects are converted with `as_json` or `to_h`
+  # instead. These objects are stored as plain values and are not decoded.
+  #
+  # @example Store a Time in a map
+  #   Y.register_codec(
+  #     Time,
+  #     encode: ->(time) { time.iso8601(6) },
+  #     decode: ->(value) { Time.iso8601(value) }
+  #   )
+  #
+  #   doc = Y::Doc.new
+  #   map = doc.get_map("my map")
+  #   map[:created_at] = Time.now
+  #   map[:created_at] # => 2026-10-18 12:00:00.123456 +0200
+  #
+  # @example Convert unknown objects with as_json or to_h
+  #   Y::Codecs.fallback = true
+  module Codecs
+    Codec = Struct.new(:name, :encode, :decode)
+    private_constant :Codec
+
+    # Key of the stored map that holds the codec name
+    NAME_KEY = "$codec"
+
+    # Key of the stored map that holds the encoded value
+    VALUE_KEY = "value"
+
+    @codecs = {}
+    @fallback = false
+    @mutex = Mutex.new
+
+    class << self
+      # @!attribute [rw] fallback
+      #
+      # @return [true|false] Convert objects without a codec with `as_json`
+      #   or `to_h` instead of raising. Defaults to false.
+      attr_accessor :fallback
+
+      # Register a codec for a class and its subclasses
+      #
+      # @param klass [Class]
+      # @param encode [#call] Converts an object into a storable value
+      # @param decode [#call] Converts a stored value back into an object
+      # @param name [String] Identifies the codec in stored values, defaults
+      #   to the class name
+      # @return [void]
+      def register(klass, encode:, decode:, name: klass.name)
+        raise ArgumentError, "codec name must not be empty" if name.to_s.empty?
+
+        @mutex.synchronize do
+          @codecs = @codecs.merge(klass => Codec.new(name.to_s, encode, decode))
+        end
+        nil
+      end
+
+      # Remove the codec registered for a class
+      #
+      # @param klass [Class]
+      # @return [void]
+      def unregister(klass)
+        @mutex.synchronize { @codecs = @codecs.except(klass) }
+        nil
+      end
+
+      # @param klass [Class]
+      # @return [true|false]
+      def registered?(klass)
+        @codecs.key?(klass)
+      end
+
+      # Encodes an object with its codec or the fallback
+      #
+      # Returns the codec name (nil for the fallback) and the encoded value.
+      #
+      # @param object [Object]
+      # @return [::Array<String|nil, Object>]
+      # @raise [Y::UnsupportedTypeError] if the object cannot be converted
+      def encode(object)
+        codec = find(object.class)
+        return [codec.name, codec.encode.call(object)] if codec
+
+        if fallback
+          return [nil, object.as_json] if object.respond_to?(:as_json)
+          return [nil, object.to_h] if object.respond_to?(:to_h)
+        end
+
+        raise UnsupportedTypeError,
+              "cannot convert #{object.class} into a value that can be " \
+              "stored in a document, register a codec with Y.register_codec"
+      end
+
+      # Decodes a stored value with the codec registered under the name
+      #
+      # Values of unknown codecs are returned as stored.
+      #
+      # @param name [String]
+      # @param value [Object]
+      # @return [Object]
+      def decode(name, value)
+        codec = @codecs.each_value.find { |c| c.name == name }
+        return { NAME_KEY => name, VALUE_KEY => value } unless codec
+
+        codec.decode.call(value)
+      end
+
+      private
+
+      def find(klass)
+        codecs = @codecs
+        klass.ancestors.each do |ancestor|
+          codec = codecs[ancestor]
+          return codec if codec
+        end
+        nil
+      end
+    end
+  end
+
+  # Register a codec for a class, see {Y::Codecs.register}
+  #
+  # @param klass [Class]
+  # @param encode [#call]
+  # @param decode [#call]
+  # @param name [String]
+  # @return [void]
+  def self.register_codec(klass, encode:, decode:, name: klass.name)
+    Codecs.register(klass, encode: encode, decode: decode, name: name)
+  end
+end
@@ -15,6 +15,18 @@ module Y
   #
   #   remote_map = remote.get_map("my_map")
   #   pp remote_map.to_h #=> {hello: "world"}
+  #
+  # A document is created with `Y::Doc.new(client_id = nil, options = {})`.
+  # Supported options are:
+  #
+  # - `gc:` set to `false` to keep deleted content (required for snapshots)
+  # - `integers:` `:number` (default) stores integers within the safe range
+  #   of JavaScript numbers as numbers, and larger integers as BigInt.
+  #   `:bigint` stores all integers as BigInt. Integers that do not fit into
+  #   64 bits raise {Y::IntegerOutOfRangeError}.
+  #
+  # @example Store integers as BigInt
+  #   doc = Y::Doc.new({integers: :bigint})
   class Doc
     ZERO_STATE = [0].freeze
     private_constant :ZERO_STATE
@@ -22,6 +34,9 @@ module Y
     ZERO_STATE_V2 = [0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0].freeze
     private_constant :ZERO_STATE_V2
 
+    SchemaGuard = Struct.new(:fragment, :schema, :manager)
+    private_constant :SchemaGuard
+
     # Attach a listener to document changes. If one of the data structures is
     # changes, the block is called with the update as its only argument.
     #
@@ -77,6 +92,32 @@ module Y
       diff
     end
 
+    # Creates a copy of this document that can be changed independently
+    #
+    # The fork has the same content and options, but its own client ID, so
+    # that changes made to the fork can be merged back with {#merge_from}.
+    #
+    # @example Preview changes before applying them to the live document
+    #   preview = doc.fork
+    #   preview.get_text("content") << "Suggested text"
+    #   doc.merge_from(preview) if accepted?
+    #
+    # @param client_id [Integer, nil] The client ID of the fork, random if nil
+    # @return [Y::Doc]
+    # @raise [ArgumentError] if the client ID is the one of this document
+    def fork(client_id: nil)
+      current_transaction { |tx| ydoc_fork(tx, client_id) }
+    end
+
+    # Applies the changes of another document (usually a fork) that this
+    # document does not have yet
+    #
+    # @param fork [Y::Doc]
+    # @return [Hash] See {Y::Transaction#apply}
+    def merge_from(fork)
+      sync(fork.diff(state))
+    end
+
     # Gets or creates a new array by name
     #
     # If the optional values array is present, fills the array up with elements
@@ -157,6 +198,81 @@ module Y
       xml_text
     end
 
+    # Returns whether the document holds changes that cannot be integrated
+    # until missing changes from other clients arrive
+    #
+    # @return [true, false]
+    def pending?
+      current_transaction(&:pending?)
+    end
+
+    # Returns the changes that wait for missing changes, encoded as update
+    #
+    # @return [::Array<Integer>, nil] Nil if nothing is pending
+    def pending_update
+      current_transaction(&:pending_update)
+    end
+
+    # Returns the state vector of the changes that pending changes depend on
+    #
+    # @example Request missing changes from the peer that sent the update
+    #   report = doc.sync(update)
+    #   if report[:pending]
+    #     peer.request(Y::Sync.sync_step1(doc.state))
+    #   end
+    #
+    # @return [::Array<Integer>, nil] Nil if nothing is pending
+    def missing_state_vector
+      current_transaction(&:missing_state_vector)
+    end
+
+    # Creates a compacted copy of this document
+    #
+    # The copy has the same content, client ID and options, but deleted
+    # content is garbage collected and adjacent blocks are squashed. This is
+    # mostly useful for documents created with `gc: false`, which keep all
+    # deleted content to be able to restore snapshots.
+    #
+    # With `since`, content that was deleted after the given snapshot is
+    # kept, so that this snapshot and all newer snapshots can still be
+    # restored from the compacted document.
+    #
+    # @example Keep snapshots of the last day
+    #   compacted = doc.compact(since: snapshot_from_yesterday)
+    #   store(compacted.full_diff)
+    #
+    # @param since [Y::Snapshot, nil] The oldest snapshot to preserve
+    # @return [Y::Doc]
+    def compact(since: nil)
+      current_transaction { |tx| ydoc_compact(tx, since) }
+    end
+
+    # Collects statistics about the blocks stored in this document. They help
+    # to find out why a document is large, and if it is worth compacting it.
+    #
+    # For every client, the statistics contain the range of clocks, the
+    # number of blocks and items, the number of tombstones (deleted items that
+    # are kept because garbage collection is disabled or not yet possible),
+    # and the ranges that are garbage collected already.
+    #
+    # @example
+    #   doc.stats
+    #   # => {
+    #   #   clients: [
+    #   #     {client: 1, clock: 0...12, blocks: 3, items: 2, tombstones: 1,
+    #   #      gc: [7...12]}
+    #   #   ],
+    #   #   root_types: 1,
+    #   #   nested_types: 0,
+    #   #   encoded_size: {v1: 32, v2: 48},
+    #   #   heap_size: 512
+    #   # }
+    #
+    # @return [Hash] The heap size is an approximation in bytes
+    def stats
+      current_transaction { |tx| ydoc_stats(tx) }
+    end
+
     # Creates a snapshot of the current document state
     #
     # @return [Y::Snapshot]
@@ -192,17 +308,23 @@ module Y
     # Synchronizes this document with the diff from another document
     #
     # @param diff [::Array<Integer>] Binary encoded update
-    # @return [void]
-    def sync(diff)
-      current_transaction { |tx| tx.apply(diff) }
+    # @param allow [::Array<String, Symbol>, nil] Names of the root types the
+    #   diff may change, see {Y::Transaction#apply_update}
+    # @return [Hash] See {Y::Transaction#apply}
+    # @raise [Y::UpdateRejectedError] if the diff changes other root types
+    def sync(diff, allow: nil)
+      current_transaction { |tx| tx.apply_update(diff, allow: allow) }
     end
 
     # Synchronizes this document with the v2 diff from another document
     #
     # @param diff [::Array<Integer>] Binary encoded update
-    # @return [void]
-    def sync_v2(diff)
-      current_transaction { |tx| tx.apply_v2(diff) }
+    # @param allow [::Array<String, Symbol>, nil] Names of the root types the
+    #   diff may change, see {Y::Transaction#apply_update}
+    # @return [Hash] See {Y::Transaction#apply}
+    # @raise [Y::UpdateRejectedError] if the diff changes other root types
+    def sync_v2(diff, allow: nil)
+      current_transaction { |tx| tx.apply_update_v2(diff, allow: allow) }
     end
 
     # Restores a specific document from an update that contains full state
@@ -216,6 +338,36 @@ module Y
       current_transaction { |tx| tx.apply(full_diff) }
     end
 
+    # Rejects every transaction that leaves the fragment invalid
+    #
+    # After a transaction that changed the fragment is committed, the
+    # fragment is validated. If it does not match the schema, the changes of
+    # the transaction to the fragment are undone and the transaction raises.
+    # This applies to all transactions of this document, including the
+    # implicit ones of single operations and updates applied with {#sync}.
+    #
+    # yrs cannot roll back a transaction, so the invalid changes are
+    # committed before they are undone: observers and connected clients see
+    # both. Use {Y::XMLSchema#transact} to keep invalid changes from reaching
+    # the document at all.
+    #
+    # @example Reject invalid edits of the default fragment
+    #   doc.enforce_schema("default", schema)
+    #
+    #   fragment = doc.get_xml_fragment("default")
+    #   fragment.insert_xml(0, "<listItem/>") # raises Y::SchemaViolationError
+    #   fragment.size # => 0
+    #
+    # @param name [String] The name of the fragment
+    # @param schema [Y::XMLSchema]
+    # @return [void]
+    def enforce_schema(name, schema)
+      fragment = get_xml_fragment(name)
+      manager = Y::UndoManager.new(self, fragment)
+      (@schema_guards ||= []) << SchemaGuard.new(fragment, schema, manager)
+      nil
+    end
+
     # Creates a new transaction
     def transact
       # 1. release potentially existing transaction
@@ -229,7 +381,7 @@ module Y
       @current_transaction.document = self
 
       # 3. call block with reference to current_transaction
-      yield @current_transaction
+      run_transaction { yield @current_transaction }
     ensure
       @current_transaction&.free
       @current_transaction = nil
@@ -246,10 +398,13 @@ module Y
       end
 
       origin_bytes = origin.is_a?(String) ? origin.bytes : origin
+      @schema_guards&.each do |guard|
+        guard.manager.include_origin(origin_bytes)
+      end
       @current_transaction = ydoc_transact_with(origin_bytes)
       @current_transaction.document = self
 
-      yield @current_transaction
+      run_transaction { yield @current_transaction }
     ensure
       @current_transaction&.free
       @current_transaction = nil
@@ -266,6 +421,47 @@ module Y
       transact(&block) unless @current_transaction
     end
 
+    private
+
+    # Calls the block of a transaction, then commits the transaction and
+    # undoes its changes to enforced fragments that became invalid
+    def run_transaction
+      completed = false
+      result = yield
+      completed = true
+      result
+    ensure
+      @current_transaction&.free
+      @current_transaction = nil
+      violations = revert_schema_violations
+      raise SchemaViolationError, violations if completed && violations.any?
+    end
+
+    # Validates the enforced fragments changed by the last transaction, and
+    # undoes the changes of the ones that do not match their schema
+    def revert_schema_violations
+      return [] if @schema_guards.nil? || @checking_schemas
+
+      begin
+        # validating reads the fragment in a transaction of its own
+        @checking_schemas = true
+        @schema_guards.flat_map { |guard| revert_invalid_changes(guard) }
+      ensure
+        @checking_schemas = false
+      end
+    end
+
+    # The undo manager of a guard only captures transactions that changed
+    # its fragment
+    def revert_invalid_changes(guard)
+      return [] unless guard.manager.can_undo?
+
+      violations = guard.schema.validate(guard.fragment)
+      guard.manager.undo unless violations.empty?
+      guard.manager.clear
+      violations
+    end
+
     # @!method ydoc_encode_diff_v1(tx, state_vector)
     #   Encodes the diff of current document state vs provided state
     #
@@ -287,6 +483,26 @@ module Y
     # @return [Array<Integer>] Binary encoded update
     # @!visibility private
 
+    # @!method ydoc_compact(tx, since)
+    #
+    # @param tx [Y::Transaction]
+    # @param since [Y::Snapshot, nil]
+    # @return [Y::Doc]
+    # @!visibility private
+
+    # @!method ydoc_fork(tx, client_id)
+    #
+    # @param tx [Y::Transaction]
+    # @param client_id [Integer, nil]
+    # @return [Y::Doc]
+    # @!visibility private
+
+    # @!method ydoc_stats(tx)
+    #
+    # @param tx [Y::Transaction]
+    # @return [Hash]
+    # @!visibility private
+
     # @!method ydoc_transact
     #   Creates a new transaction for the document
     #
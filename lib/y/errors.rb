@@ -0,0 +1,72 @@
+# frozen_string_literal: true
+
+module Y
+  # Base class for errors raised by this library
+  class Error < StandardError; end
+
+  # Raised when a Ruby value cannot be converted into a value that can be
+  # stored in a document
+  class ConversionError < Error; end
+
+  # Raised when an Integer does not fit into 64 bits, the largest integer
+  # type supported by Yjs (BigInt64)
+  class IntegerOutOfRangeError < ConversionError; end
+
+  # Raised when a text String contains bytes that are invalid in its
+  # encoding. Binary data must use the `ASCII-8BIT` encoding (see
+  # `String#b`), it is stored as a buffer.
+  class InvalidEncodingError < ConversionError; end
+
+  # Raised when an object has no codec registered for its class and the
+  # fallback is disabled, see {Y::Codecs}
+  class UnsupportedTypeError < ConversionError; end
+
+  # Raised when XML markup is not well-formed, the message contains the byte
+  # offset at which parsing failed
+  class MarkupError < Error; end
+
+  # Raised when a change is rejected because the changed XML fragment does
+  # not match its schema, see {Y::XMLSchema#transact} and
+  # {Y::Doc#enforce_schema}
+  class SchemaViolationError < Error
+    # @!attribute [r] violations
+    #
+    # @return [::Array<Hash>] The violations, see {Y::XMLSchema#validate}
+    attr_reader :violations
+
+    # @param violations [::Array<Hash>]
+    def initialize(violations)
+      @violations = violations
+
+      messages = violations.map { |violation| violation[:message] }
+
+      super("schema violations: #{messages.join(", ")}")
+    end
+  end
+
+  # Raised when an update is rejected because it changes shared types it is
+  # not allowed to change
+  #
+  # @example Inspect the violations of a rejected update
+  #   begin
+  #     doc.sync(update, allow: ["content"])
+  #   rescue Y::UpdateRejectedError => e
+  #     e.violations # => [{root: "meta", client: 1, clock: 0, action: :insert}]
+  #   end
+  class UpdateRejectedError < Error
+    # @!attribute [r] violations
+    #
+    # @return [::Array<Hash>] The rejected changes. Each change has a `root`
+    #   (nil if the root type cannot be determined), the `client` and `clock`
+    #   of the affected block, and an `action` (`:insert` or `:delete`).
+    attr_reader :violations
+
+    # @param violations [::Array<Hash>]
+    def initialize(violations)
+      @violations = violations
+      roots = violations.map { |violation| violation[:root] || "unknown" }.uniq
+
+      super("update changes disallowed root types: #{roots.join(", ")}")
+    end
+  end
+end
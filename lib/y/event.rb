@@ -0,0 +1,169 @@
+# frozen_string_literal: true
+
+module Y
+  # Describes a change of a shared type, passed to observers
+  #
+  # Observers receive one event per transaction and shared type. An event is
+  # a snapshot of the change, it can be kept after the observer returns.
+  #
+  # All events have the same shape, so observer code can be shared across
+  # types:
+  # - {#delta} lists changes of sequences (text, array items and XML
+  #   children), it is empty for maps
+  # - {#keys} lists changes of keys (map entries and XML attributes), it is
+  #   empty for texts and arrays
+  #
+  # @example Log changes of any type
+  #   logger = lambda do |event|
+  #     puts "#{event.class} at #{event.path} by #{event.origin}"
+  #     pp event.delta unless event.delta.empty?
+  #     pp event.keys unless event.keys.empty?
+  #   end
+  #
+  #   doc.get_text("text").attach(logger)
+  #   doc.get_map("map").attach(logger)
+  class Event
+    # @!attribute [r] document
+    #
+    # @return [Y::Doc] The document the changed type belongs to
+    attr_accessor :document
+
+    # The changes of a sequence as a list of operations
+    #
+    # Operations are `{insert:}`, `{retain:}` and `{delete:}` Hashes. Texts
+    # insert strings or embeds and can have `attributes`, arrays and XML
+    # nodes insert lists of values.
+    #
+    # @return [::Array<Hash>]
+    def delta
+      yevent_delta
+    end
+
+    # The changed keys
+    #
+    # Every change has an `action` (`:inserted`, `:updated` or `:removed`),
+    # the `old_value` and the `new_value`.
+    #
+    # @example
+    #   event.keys # => {"title" => {action: :updated, old_value: "a", new_value: "b"}}
+    #
+    # @return [Hash{String => Hash}]
+    def keys
+      yevent_keys
+    end
+
+    # The origin of the transaction that caused the change, see
+    # {Y::Doc#transact_with}
+    #
+    # @return [String, nil]
+    def origin
+      yevent_origin
+    end
+
+    # The path from the observed type to the changed type, a list of map
+    # keys and array indices. It is empty for changes of the observed type.
+    #
+    # @return [::Array<String, Integer>]
+    def path
+      yevent_path
+    end
+
+    # The changed shared type
+    #
+    # @return [Y::Array, Y::Map, Y::Text, Y::XMLElement, Y::XMLFragment,
+    #   Y::XMLText]
+    def target
+      node = yevent_target
+      node.document = document
+      node
+    end
+
+    # Details of the transaction that caused the change: its `origin` and
+    # the encoded state vectors of the document before and after the
+    # transaction (`before_state`, `after_state`)
+    #
+    # @return [Hash]
+    def transaction
+      yevent_transaction
+    end
+
+    # Wraps a listener, so that the events passed to it belong to the document
+    #
+    # @param document [Y::Doc]
+    # @param listener [Proc]
+    # @return [Proc]
+    # @!visibility private
+    def self.listener(document, listener)
+      proc do |event|
+        event.document = document
+        listener.call(event)
+      end
+    end
+
+    # @!method yevent_delta()
+    #
+    # @return [::Array<Hash>]
+    # @!visibility private
+
+    # @!method yevent_keys()
+    #
+    # @return [Hash]
+    # @!visibility private
+
+    # @!method yevent_origin()
+    #
+    # @return [String, nil]
+    # @!visibility private
+
+    # @!method yevent_path()
+    #
+    # @return [::Array<String, Integer>]
+    # @!visibility private
+
+    # @!method yevent_target()
+    #
+    # @return [Object]
+    # @!visibility private
+
+    # @!method yevent_transaction()
+    #
+    # @return [Hash]
+    # @!visibility private
+  end
+
+  # A change of a {Y::Array}
+  #
+  # @example
+  #   event.delta # => [{retain: 1}, {insert: [2, 3]}, {delete: 1}]
+  class ArrayEvent < Event; end
+
+  # A change of a {Y::Map}
+  #
+  # @example
+  #   event.keys # => {"count" => {action: :inserted, old_value: nil, new_value: 1}}
+  class MapEvent < Event; end
+
+  # A change of a {Y::Text}
+  #
+  # The delta is a list of `{insert:, attributes:}`, `{retain:, attributes:}`
+  # and `{delete:}` operations, like a Quill delta.
+  #
+  # @example Print inserted text
+  #   text.attach do |event|
+  #     event.delta.each { |op| puts op[:insert] if op.key?(:insert) }
+  #   end
+  class TextEvent < Event; end
+
+  # A change of a {Y::XMLElement}, {Y::XMLFragment} or {Y::XMLText}
+  #
+  # For elements and fragments, the delta describes the changed children.
+  # For XML texts, it describes the changed text like a {Y::TextEvent}. The
+  # keys describe changed attributes.
+  #
+  # @example React to a changed heading level
+  #   heading.attach do |event|
+  #     level = event.keys["level"]
+  #     puts "level #{level[:old_value]} -> #{level[:new_value]}" if level
+  #   end
+  class XMLEvent < Event; end
+end
@@ -36,13 +36,22 @@ module Y
 
     # Attach a listener to get notified about any changes to the map
     #
+    # The listener is called once per transaction that changes the map, with
+    # a {Y::MapEvent}.
+    #
+    # @example Listen to changed keys
+    #   map.attach do |event|
+    #     event.keys.each { |key, change| pp key, change[:action] }
+    #   end
+    #
     # @param callback [Proc]
     # @param block [Block]
     # @return [Integer]
-    def attach(callback, &block)
-      return ymap_observe(callback) unless callback.nil?
+    def attach(callback = nil, &block)
+      listener = callback || block
+      return if listener.nil?
 
-      ymap_observe(block.to_proc) unless block.nil?
+      ymap_observe(Event.listener(document, listener))
     end
 
     # Removes all map entries
@@ -0,0 +1,180 @@
+# frozen_string_literal: true
+
+module Y
+  # A room keeps a shared document and awareness instance in sync across many
+  # connected clients. It implements the server side of the y-sync protocol
+  # (as used by y-websocket and others) and is transport agnostic: a
+  # connection is registered with a callback that is invoked with every
+  # message that must be sent to the client, and incoming messages are passed
+  # to {#receive}.
+  #
+  # When a client connects, the room starts the handshake by sending its
+  # state vector. Document updates and awareness changes received from one
+  # connection are broadcast to all other connections. When a connection is
+  # closed, the awareness states of the clients it controlled are removed and
+  # the removal is broadcast to the remaining connections.
+  #
+  # @example Connect a WebSocket to a room
+  #   room = Y::Room.new
+  #
+  #   connection_id = room.connect { |message| ws.send(message.pack("C*")) }
+  #   ws.on(:message) { |data| room.receive(connection_id, data.bytes) }
+  #   ws.on(:close) { room.disconnect(connection_id) }
+  class Room
+    # Creates a new room with an empty document. Accepts the same arguments
+    # as {Y::Doc.new}.
+    #
+    # @!method self.new(client_id = nil, options = {})
+    # @param client_id [Integer, nil]
+    # @param options [Hash]
+    # @return [Y::Room]
+
+    # Registers a new connection and sends the initial handshake messages to
+    # it.
+    #
+    # A read-only connection receives all document and awareness updates, and
+    # it can broadcast its own awareness state, but updates to the document
+    # sent by the client are not applied. The client receives an `Auth`
+    # message with a permission denied reason instead.
+    #
+    # @example Connect a viewer
+    #   room.connect(read_only: true) { |message| ws.send(message) }
+    #
+    # @param read_only [true, false]
+    # @yield [message] Called for every message that must be sent to the client
+    # @yieldparam [::Array<Integer>] message A binary encoded y-sync message
+    # @return [Integer] The connection ID
+    def connect(callback = nil, read_only: false, &block)
+      return yroom_connect(callback, read_only) unless callback.nil?
+
+      yroom_connect(block.to_proc, read_only) unless block.nil?
+    end
+
+    # Returns the IDs of all open connections
+    #
+    # @return [::Array<Integer>]
+    def connections
+      yroom_connections
+    end
+
+    # Closes a connection and removes the awareness states of all clients
+    # that were announced through this connection.
+    #
+    # @param connection_id [Integer]
+    # @return [true, false] False if the connection was unknown
+    def disconnect(connection_id)
+      yroom_disconnect(connection_id)
+    end
+
+    # Returns the document shared by all connections. Changes made to the
+    # document on the server are broadcast on the next call to {#flush}, which
+    # {#transact} does automatically.
+    #
+    # @return [Y::Doc]
+    def doc
+      @doc ||= yroom_doc
+    end
+
+    # Returns whether a connection is read-only
+    #
+    # @param connection_id [Integer]
+    # @return [true, false, nil] Nil if the connection is unknown
+    def read_only?(connection_id)
+      yroom_is_read_only(connection_id)
+    end
+
+    # Changes the permissions of open connections
+    #
+    # @example Grant write access to a viewer
+    #   room.read_only = { connection_id => false }
+    #
+    # @param permissions [Hash{Integer => true, false}] Whether a connection
+    #   is read-only, by connection ID
+    # @raise [ArgumentError] if a connection is unknown
+    def read_only=(permissions)
+      permissions.each do |connection_id, read_only|
+        yroom_set_read_only(connection_id, read_only)
+      end
+    end
+
+    # Sends all pending messages to their connections
+    #
+    # @return [void]
+    def flush
+      yroom_flush
+    end
+
+    # Handles a message received from a connection. Replies and broadcasts are
+    # sent before this method returns.
+    #
+    # @param connection_id [Integer]
+    # @param message [::Array<Integer>] A binary encoded y-sync message
+    # @return [void]
+    # @raise [RuntimeError] if a transaction on the document is open
+    def receive(connection_id, message)
+      yroom_receive(connection_id, message)
+    end
+
+    # Changes the document within a transaction and broadcasts the resulting
+    # update to all connections.
+    #
+    # @example Change the document on the server
+    #   text = room.doc.get_text("content")
+    #   room.transact { text << "Hello from the server" }
+    #
+    # @yield [Y::Transaction]
+    # @return [void]
+    def transact(&block)
+      doc.transact(&block)
+      flush
+    end
+
+    # @!method yroom_connect(callback, read_only)
+    #
+    # @param callback [Proc]
+    # @param read_only [Boolean]
+    # @return [Integer]
+    # @!visibility private
+
+    # @!method yroom_connections
+    #
+    # @return [::Array<Integer>]
+    # @!visibility private
+
+    # @!method yroom_disconnect(connection_id)
+    #
+    # @param connection_id [Integer]
+    # @return [Boolean]
+    # @!visibility private
+
+    # @!method yroom_doc
+    #
+    # @return [Y::Doc]
+    # @!visibility private
+
+    # @!method yroom_is_read_only(connection_id)
+    #
+    # @param connection_id [Integer]
+    # @return [Boolean, nil]
+    # @!visibility private
+
+    # @!method yroom_flush
+    #
+    # @return [void]
+    # @!visibility private
+
+    # @!method yroom_receive(connection_id, message)
+    #
+    # @param connection_id [Integer]
+    # @param message [::Array<Integer>]
+    # @return [void]
+    # @!visibility private
+
+    # @!method yroom_set_read_only(connection_id, read_only)
+    #
+    # @param connection_id [Integer]
+    # @param read_only [Boolean]
+    # @return [void]
+    # @!visibility private
+  end
+end
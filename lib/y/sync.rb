@@ -0,0 +1,93 @@
+# frozen_string_literal: true
+
+module Y
+  # Encoders and decoders for messages of the y-sync protocol. They can be
+  # used to implement a client for {Y::Room}, or to talk to any other server
+  # that speaks the protocol (e.g. y-websocket).
+  #
+  # @example Sync a document with a room
+  #   doc = Y::Doc.new
+  #   connection_id = room.connect do |message|
+  #     Y::Sync.decode(message).each do |type, payload|
+  #       case type
+  #       when :sync_step1
+  #         room.receive(connection_id, Y::Sync.sync_step2(doc.diff(payload)))
+  #       when :sync_step2, :update
+  #         doc.sync(payload)
+  #       end
+  #     end
+  #   end
+  module Sync
+    # Decodes one or more messages
+    #
+    # Each message is returned as pair of its type and payload. Types are
+    # `:sync_step1` (state vector), `:sync_step2` and `:update` (update),
+    # `:awareness` (awareness update), `:awareness_query` (nil), `:auth`
+    # (reason or nil) and `:custom` (tag and data).
+    #
+    # @param message [::Array<Integer>]
+    # @return [::Array<::Array(Symbol, Object)>]
+    def self.decode(message)
+      ysync_decode(message)
+    end
+
+    # Encodes an authorization message. A message with a reason denies the
+    # permission, a message without a reason grants it.
+    #
+    # @param reason [String, nil] The reason the permission was denied
+    # @return [::Array<Integer>] Encoded auth message
+    def self.auth(reason = nil)
+      ysync_encode_auth(reason)
+    end
+
+    # @param update [::Array<Integer>] A binary encoded awareness update
+    # @return [::Array<Integer>] Encoded awareness message
+    def self.awareness(update)
+      ysync_encode_awareness(update)
+    end
+
+    # @return [::Array<Integer>] Encoded awareness query message
+    def self.awareness_query
+      ysync_encode_awareness_query
+    end
+
+    # @param state [::Array<Integer>] A binary encoded state vector
+    # @return [::Array<Integer>] Encoded sync step 1 message
+    def self.sync_step1(state)
+      ysync_encode_sync_step1(state)
+    end
+
+    # @param diff [::Array<Integer>] A binary encoded update
+    # @return [::Array<Integer>] Encoded sync step 2 message
+    def self.sync_step2(diff)
+      ysync_encode_sync_step2(diff)
+    end
+
+    # @param diff [::Array<Integer>] A binary encoded update
+    # @return [::Array<Integer>] Encoded update message
+    def self.update(diff)
+      ysync_encode_update(diff)
+    end
+
+    # @!method self.ysync_decode(message)
+    # @!visibility private
+
+    # @!method self.ysync_encode_auth(reason)
+    # @!visibility private
+
+    # @!method self.ysync_encode_awareness(update)
+    # @!visibility private
+
+    # @!method self.ysync_encode_awareness_query
+    # @!visibility private
+
+    # @!method self.ysync_encode_sync_step1(state)
+    # @!visibility private
+
+    # @!method self.ysync_encode_sync_step2(diff)
+    # @!visibility private
+
+    # @!method self.ysync_encode_update(diff)
+    # @!visibility private
+  end
+end
@@ -40,36 +40,72 @@ module Y
       document.current_transaction { |tx| ytext_push(tx, str) }
     end
 
+    # Applies a Quill delta to the text
+    #
+    # A delta is a list of operations that are applied in order, starting at
+    # the beginning of the text. Operations either retain (skip), delete or
+    # insert content. Inserts are strings or embeds (a Hash), retains and
+    # inserts can have attributes. Attributes with a `nil` value are removed.
+    #
+    # @example Make the second word bold
+    #   doc = Y::Doc.new
+    #   text = doc.get_text("my text")
+    #   text << "Hello World"
+    #
+    #   text.apply_delta(
+    #     [{ retain: 6 }, { retain: 5, attributes: { bold: true } }]
+    #   )
+    #
+    # @example Replace a word and insert an image
+    #   text.apply_delta(
+    #     [
+    #       { delete: 5 },
+    #       { insert: "Hi" },
+    #       { retain: 6 },
+    #       { insert: { image: "https://example.com/logo.png" } }
+    #     ]
+    #   )
+    #
+    # @param delta [::Array<Hash>]
+    # @return [void]
+    # @raise [ArgumentError] if an operation is neither an insert, retain nor
+    #   delete, or reaches beyond the end of the text
+    def apply_delta(delta)
+      document.current_transaction { |tx| ytext_apply_delta(tx, delta) }
+    end
+
     # Attach listener to text changes
     #
+    # The listener is called once per transaction that changes the text,
+    # with a {Y::TextEvent} holding the complete delta. Errors raised by the
+    # listener are raised when the transaction is committed.
+    #
     # @example Listen to changes in text type
     #   local = Y::Doc.new
     #
     #   text = local.get_text("my text")
-    #   text.attach(->(delta) { pp delta }) # { insert: "Hello, World!" }
+    #   text.attach(->(event) { pp event.delta })
     #
     #   local.transact do
     #     text << "Hello, World!"
     #   end
+    #   # => [{ insert: "Hello, World!" }]
     #
-    # @example Listen to changes in text type
-    #   local = Y::Doc.new
-    #
-    #   text = local.get_text("my text")
-    #   text.attach(->(delta) { pp delta }) # { insert: "Hello, World!" }
-    #
-    #   text << "Hello, World!"
+    # @example Listen to changes made with an origin
+    #   text.attach do |event|
+    #     puts "changed by #{event.origin}" unless event.origin.nil?
+    #   end
     #
-    #   # todo: required, otherwise segfault
-    #   local.commit
+    #   local.transact_with("agent") { text << "Hello" }
     #
     # @param callback [Proc]
     # @param block [Block]
     # @return [Integer]
-    def attach(callback, &block)
-      return ytext_observe(callback) unless callback.nil?
+    def attach(callback = nil, &block)
+      listener = callback || block
+      return if listener.nil?
 
-      ytext_observe(block.to_proc) unless block.nil?
+      ytext_observe(Event.listener(document, listener))
     end
 
     # Detach listener
@@ -89,6 +125,37 @@ module Y
       end
     end
 
+    # Populates an empty text from a Quill delta
+    #
+    # The delta must only contain inserts, e.g. a delta stored by Quill or
+    # returned by {#to_delta}.
+    #
+    # @example Load a stored Quill document
+    #   doc = Y::Doc.new
+    #   text = doc.get_text("my text")
+    #
+    #   text.load_delta(
+    #     [{ "insert" => "Hello", "attributes" => { "bold" => true } }]
+    #   )
+    #
+    # @param delta [::Array<Hash>]
+    # @return [void]
+    # @raise [ArgumentError] if the text is not empty or the delta contains
+    #   other operations than inserts
+    def load_delta(delta)
+      document.current_transaction do |tx|
+        unless ytext_length(tx).zero?
+          raise ArgumentError, "cannot load delta into non-empty text"
+        end
+
+        unless delta.all? { |op| insert?(op) }
+          raise ArgumentError, "delta must only contain inserts"
+        end
+
+        ytext_apply_delta(tx, delta)
+      end
+    end
+
     # Checks if text is empty
     #
     # @example Check if text is empty
@@ -172,6 +239,50 @@ module Y
       end
     end
 
+    # Replaces the content of the text with a new string
+    #
+    # Instead of replacing the whole text, only the characters that differ
+    # are removed and inserted. Unchanged characters keep their formatting
+    # and identity, so cursors and attribution on them are preserved.
+    # Inserted characters take the formatting of the preceding character.
+    # Embeds are removed, as they are not part of the new string.
+    #
+    # @example Fix a typo
+    #   doc = Y::Doc.new
+    #   text = doc.get_text("my text")
+    #   text << "Hello Wrld"
+    #
+    #   text.replace_with("Hello World") # inserts a single "o"
+    #
+    # @param new_string [String]
+    # @return [void]
+    def replace_with(new_string)
+      document.current_transaction { |tx| ytext_replace_with(tx, new_string) }
+    end
+
+    # Returns the content of the text as a Quill delta
+    #
+    # The delta is normalized: adjacent strings with equal attributes are
+    # merged into one insert, and embeds are inserted as objects. Attribute
+    # keys are Strings.
+    #
+    # @example Export formatted text
+    #   doc = Y::Doc.new
+    #   text = doc.get_text("my text")
+    #   text.insert(0, "Hello", { bold: true })
+    #   text << " World"
+    #
+    #   text.to_delta
+    #   # => [
+    #   #   { insert: "Hello", attributes: { "bold" => true } },
+    #   #   { insert: " World" }
+    #   # ]
+    #
+    # @return [::Array<Hash>]
+    def to_delta
+      document.current_transaction { |tx| ytext_to_delta(tx) }
+    end
+
     # Returns length of text
     #
     # @return [Integer] Length of text
@@ -293,6 +404,17 @@ module Y
         value.is_a?(Hash)
     end
 
+    def insert?(op)
+      op.is_a?(Hash) && (op.key?(:insert) || op.key?("insert"))
+    end
+
+    # @!method ytext_apply_delta(tx, delta)
+    #   Applies a Quill delta to the text
+    #
+    # @param tx [Y::Transaction]
+    # @param delta [::Array<Hash>]
+    # @return [nil]
+
     # @!method ytext_diff(tx)
     #   Returns text changes as list of diffs
     #
@@ -369,6 +491,19 @@ module Y
     # @param proc [Proc]
     # @return [Integer]
 
+    # @!method ytext_replace_with(tx, new_string)
+    #   Replaces the text with the minimal changes to get to the new string
+    #
+    # @param tx [Y::Transaction]
+    # @param new_string [String]
+    # @return [nil]
+
+    # @!method ytext_to_delta(tx)
+    #   Returns the text as a normalized Quill delta
+    #
+    # @param tx [Y::Transaction]
+    # @return [::Array<Hash>]
+
     # @!method ytext_to_s()
     #   Returns string representation of text
     #
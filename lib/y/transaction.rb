@@ -16,8 +16,18 @@ module Y
     # Applies the encoded update on this document. This will bring the
     # the document to the same state as the one the update is from.
     #
+    # Blocks of the update that depend on changes the document has not seen
+    # yet are not integrated. They are kept as pending until the missing
+    # changes arrive, see {#pending?} and {#missing_state_vector}.
+    #
+    # @example Check if an update could not be integrated completely
+    #   report = doc.current_transaction { |tx| tx.apply(update) }
+    #   report # => {integrated: [{client: 1, clock: 0...5}],
+    #          #     pending: true, missing: {2 => 3}}
+    #
     # @param update [::Array<Integer>]
-    # @return [void]
+    # @return [Hash] The clock ranges integrated per client, whether parts of
+    #   the update are pending, and the clock missing per client
     def apply(update)
       ytransaction_apply_update(update)
     end
@@ -26,11 +36,56 @@ module Y
     # the document to the same state as the one the update is from.
     #
     # @param update [::Array<Integer>]
-    # @return [void]
+    # @return [Hash] See {#apply}
     def apply_v2(update)
       ytransaction_apply_update_v2(update)
     end
 
+    # Applies the encoded update on this document, optionally restricted to
+    # a list of root types. With `allow`, the update is inspected before it is
+    # integrated, and it is rejected as a whole if any of its changes touches
+    # a root type that is not allowed, or a type nested beneath one. Changes
+    # that cannot be attributed to a root type (e.g. because they depend on
+    # changes the document does not have yet) are rejected as well.
+    #
+    # @example Allow clients to edit the content but not the metadata
+    #   doc.current_transaction do |tx|
+    #     tx.apply_update(update, allow: ["content"])
+    #   end
+    #
+    # @param update [::Array<Integer>]
+    # @param allow [::Array<String, Symbol>, nil] Names of the root types the
+    #   update may change, nil allows all changes
+    # @return [Hash] See {#apply}
+    # @raise [Y::UpdateRejectedError] if the update changes other root types
+    def apply_update(update, allow: nil)
+      unless allow.nil?
+        violations = ytransaction_update_violations(update, allow.map(&:to_s))
+        raise UpdateRejectedError, violations unless violations.empty?
+      end
+
+      apply(update)
+    end
+
+    # Applies the v2 encoded update on this document, optionally restricted
+    # to a list of root types, see {#apply_update}.
+    #
+    # @param update [::Array<Integer>]
+    # @param allow [::Array<String, Symbol>, nil] Names of the root types the
+    #   update may change, nil allows all changes
+    # @return [Hash] See {#apply}
+    # @raise [Y::UpdateRejectedError] if the update changes other root types
+    def apply_update_v2(update, allow: nil)
+      unless allow.nil?
+        violations = ytransaction_update_violations_v2(
+          update, allow.map(&:to_s)
+        )
+        raise UpdateRejectedError, violations unless violations.empty?
+      end
+
+      apply_v2(update)
+    end
+
     # Commits transaction
     #
     # @return [void]
@@ -98,6 +153,29 @@ module Y
       xml_text
     end
 
+    # Returns whether the document holds changes that cannot be integrated
+    # until missing changes from other clients arrive
+    #
+    # @return [true, false]
+    def pending?
+      ytransaction_is_pending
+    end
+
+    # Returns the changes that wait for missing changes, encoded as update
+    #
+    # @return [::Array<Integer>, nil] Nil if nothing is pending
+    def pending_update
+      ytransaction_pending_update
+    end
+
+    # Returns the state vector of the changes that pending changes depend on.
+    # A peer that has these changes can be asked to send them.
+    #
+    # @return [::Array<Integer>, nil] Nil if nothing is pending
+    def missing_state_vector
+      ytransaction_missing_state_vector
+    end
+
     # Return a state vector for this transaction
     #
     # @return [::Array<Integer>]
@@ -142,6 +220,24 @@ module Y
     # @return [void]
     # @!visibility private
 
+    # @!method ytransaction_update_violations(update, allow)
+    #   Changes of the encoded update that touch other than the allowed root
+    #   types
+    #
+    # @param update [::Array<Integer>]
+    # @param allow [::Array<String>]
+    # @return [::Array<Hash>]
+    # @!visibility private
+
+    # @!method ytransaction_update_violations_v2(update, allow)
+    #   Changes of the v2 encoded update that touch other than the allowed
+    #   root types
+    #
+    # @param update [::Array<Integer>]
+    # @param allow [::Array<String>]
+    # @return [::Array<Hash>]
+    # @!visibility private
+
     # @!method ytransaction_commit()
     #
     # @return [void]
@@ -192,6 +288,21 @@ module Y
     # @return [Y::XMLElement] XMLElement structure
     # @!visibility private
 
+    # @!method ytransaction_is_pending
+    #
+    # @return [Boolean]
+    # @!visibility private
+
+    # @!method ytransaction_missing_state_vector
+    #
+    # @return [Array<Integer>, nil]
+    # @!visibility private
+
+    # @!method ytransaction_pending_update
+    #
+    # @return [Array<Integer>, nil]
+    # @!visibility private
+
     # @!method ytransaction_state_vector
     #
     # @return [Array<Integer>]
@@ -0,0 +1,49 @@
+# frozen_string_literal: true
+
+module Y
+  # Inspects binary encoded updates without applying them to a document
+  #
+  # @example Inspect an update received as base64
+  #   update = Base64.decode64(blob).bytes
+  #   Y::Update.decode(update)
+  #   # => {
+  #   #   clients: [
+  #   #     {
+  #   #       client: 1,
+  #   #       clock: 0...5,
+  #   #       blocks: [
+  #   #         {
+  #   #           kind: :item, clock: 0, length: 5,
+  #   #           parent: {root: "content"}, parent_sub: nil,
+  #   #           origin: nil, right_origin: nil,
+  #   #           content: :string, preview: "Hello"
+  #   #         }
+  #   #       ]
+  #   #     }
+  #   #   ],
+  #   #   delete_set: [{client: 1, clock: [1...2]}]
+  #   # }
+  module Update
+    # Decodes an update into its clients, blocks and delete set
+    #
+    # Every client lists the range of clocks covered by the update and its
+    # blocks. A block is one of `:item`, `:gc` (garbage collected content) or
+    # `:skip` (a gap in the update). Items reference their parent either by
+    # the name of a root type (`{root: "name"}`) or by the ID of the item
+    # that holds the nested type (`{client:, clock:}`). When an item has an
+    # origin, the parent is not encoded and is `nil`. The `preview` is a
+    # short, possibly truncated representation of the content. Like in
+    # JavaScript, BigInt values end with `n`.
+    #
+    # @param update [::Array<Integer>, String] Binary encoded update
+    # @param version [Integer] The encoding version of the update, 1 or 2
+    # @return [Hash]
+    def self.decode(update, version: 1)
+      update = update.bytes if update.is_a?(String)
+      yupdate_decode(update, version)
+    end
+
+    # @!method self.yupdate_decode(update, version)
+    # @!visibility private
+  end
+end
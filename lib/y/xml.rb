@@ -112,6 +112,87 @@ module Y
       self
     end
 
+    # Iterate over all descendants in document order
+    #
+    # Unlike {#each}, nested nodes are visited too. Every node is yielded with
+    # its depth (1 for children of this element) and its path, the indices of
+    # the node and its ancestors below this element.
+    #
+    # @example Print an outline
+    #   element.each_descendant do |node, depth, path|
+    #     puts "#{"  " * (depth - 1)}#{path.inspect} #{node}"
+    #   end
+    #
+    # @yieldparam node [Y::XMLElement, Y::XMLText]
+    # @yieldparam depth [Integer]
+    # @yieldparam path [::Array<Integer>]
+    # @return [self]
+    def each_descendant(&block)
+      return enum_for(:each_descendant) unless block_given?
+
+      descendants = document.current_transaction do |tx|
+        yxml_element_descendants(tx)
+      end
+      descendants.each do |node, depth, path|
+        node.document = document
+        block.call(node, depth, path)
+      end
+      self
+    end
+
+    # Returns all descendant elements with the tag and attributes
+    #
+    # Without arguments, this is Enumerable#find_all over the children.
+    #
+    # @example Find checked list items
+    #   element.find_all("listItem", checked: "true")
+    #
+    # @param tag [String, nil] Matches any tag if nil
+    # @param attributes [Hash{Symbol => String, nil}] Attribute values to
+    #   match, nil only requires the attribute to be present
+    # @return [::Array<Y::XMLElement>]
+    def find_all(tag = nil, **attributes, &block)
+      return super(&block) if tag.nil? && attributes.empty?
+
+      attributes = attributes.map { |name, value| [name.to_s, value&.to_s] }
+      nodes = document.current_transaction do |tx|
+        yxml_element_find_all(tx, tag&.to_s, attributes)
+      end
+      nodes.each { |node| node.document = document }
+      nodes
+    end
+
+    # Returns all descendant elements that match a CSS-like selector
+    #
+    # Supported are tags, `*`, attribute conditions (`[name]`,
+    # `[name="value"]`) and the descendant (space) and child (`>`)
+    # combinators. Nodes are returned in document order.
+    #
+    # @example Find the paragraphs of bullet list items
+    #   element.query_selector_all("bulletList > listItem paragraph")
+    #
+    # @param selector [String]
+    # @return [::Array<Y::XMLElement>]
+    # @raise [ArgumentError] if the selector is invalid
+    def query_selector_all(selector)
+      nodes = document.current_transaction do |tx|
+        yxml_element_query(tx, selector)
+      end
+      nodes.each { |node| node.document = document }
+      nodes
+    end
+
+    # Returns the first descendant element that matches a CSS-like selector
+    #
+    # @see #query_selector_all
+    #
+    # @param selector [String]
+    # @return [Y::XMLElement, nil]
+    # @raise [ArgumentError] if the selector is invalid
+    def query_selector(selector)
+      query_selector_all(selector).first
+    end
+
     # Insert text into element at given index
     #
     # Optional input is pushed to the text if provided
@@ -127,6 +208,27 @@ module Y
       text
     end
 
+    # Replace all children of this element with the nodes parsed from markup
+    #
+    # The children are removed and the new nodes are inserted in a single
+    # transaction, so observers receive one event. Whitespace between
+    # elements is ignored.
+    #
+    # @example Replace the content of a list item
+    #   item.replace_children_with_xml("<paragraph>Done</paragraph>")
+    #   item.to_s # => "<listItem><paragraph>Done</paragraph></listItem>"
+    #
+    # @param markup [String]
+    # @return [::Array<Y::XMLElement, Y::XMLText>] The inserted nodes
+    # @raise [Y::MarkupError] if the markup is not well-formed
+    def replace_children_with_xml(markup)
+      nodes = document.current_transaction do |tx|
+        yxml_element_replace_children_with_xml(tx, markup)
+      end
+      nodes.each { |node| node.document = document }
+      nodes
+    end
+
     # Retrieve element or text adjacent (next) to this element
     #
     # @return [Y::XMLElement, Y::XMLText, nil]
@@ -138,25 +240,28 @@ module Y
 
     # Attach listener to get notified about changes to the element
     #
-    # This supports either a `Proc` or a `Block`.
+    # This supports either a `Proc` or a `Block`. The listener receives a
+    # {Y::XMLEvent} with the changed children as delta and the changed
+    # attributes as keys.
     #
     # @example Receive changes via Proc
     #   doc = Y::Doc.new
     #   xml_element = doc.get_xml_element("my xml element")
-    #   xml_element.attach ->(changes) { … }
+    #   xml_element.attach ->(event) { … }
     #
     # @example Receive changes via Block
     #   doc = Y::Doc.new
     #   xml_element = doc.get_xml_element("my xml element")
-    #   xml_element.attach { |changes| … }
+    #   xml_element.attach { |event| … }
     #
     # @param callback [Proc]
     # @param block [Block]
     # @return [Integer] The subscription ID
     def attach(callback = nil, &block)
-      return yxml_element_observe(callback) unless callback.nil?
+      listener = callback || block
+      return if listener.nil?
 
-      yxml_element_observe(block.to_proc) unless block.nil?
+      yxml_element_observe(Event.listener(document, listener))
     end
 
     # Retrieve parent element
@@ -387,6 +492,18 @@ module Y
     #
     # @return [Hash]
 
+    # @!method yxml_element_descendants(tx)
+    #
+    # @param tx [Y::Transaction]
+    # @return [::Array<::Array>] Node, depth and path of every descendant
+
+    # @!method yxml_element_find_all(tx, tag, attributes)
+    #
+    # @param tx [Y::Transaction]
+    # @param tag [String, nil]
+    # @param attributes [::Array<::Array<String, nil>>]
+    # @return [::Array<Y::XMLElement>]
+
     # @!method yxml_element_first_child(tx)
     #
     # @param tx [Y::Transaction]
@@ -470,6 +587,12 @@ module Y
     # @param text [string]
     # @return [Y::XMLText]
 
+    # @!method yxml_element_query(tx, selector)
+    #
+    # @param tx [Y::Transaction]
+    # @param selector [String]
+    # @return [::Array<Y::XMLElement>]
+
     # @!method yxml_element_remove_attribute(tx, name)
     #
     # @param tx [Y::Transaction]
@@ -484,6 +607,12 @@ module Y
     #
     # @return [void]
 
+    # @!method yxml_element_replace_children_with_xml(tx, markup)
+    #
+    # @param tx [Y::Transaction]
+    # @param markup [String]
+    # @return [::Array<Y::XMLElement, Y::XMLText>]
+
     # @!method yxml_element_size(tx)
     #
     # @param tx [Y::Transaction]
@@ -545,11 +674,16 @@ module Y
 
     # Attach a listener to get notified about changes
     #
+    # The listener is called with a {Y::XMLEvent}, its delta describes the
+    # changed text like a {Y::TextEvent} and its keys the changed attributes.
+    #
     # @param callback [Proc]
     # @return [Integer] subscription_id
     def attach(callback = nil, &block)
-      yxml_text_observe(callback) unless callback.nil?
-      yxml_text_observe(block.to_proc) unless block.nil?
+      listener = callback || block
+      return if listener.nil?
+
+      yxml_text_observe(Event.listener(document, listener))
     end
 
     # Return text attributes
@@ -999,6 +1133,69 @@ module Y
     end
     # rubocop:enable Lint/Void
 
+    # Attach listener to get notified about changes of the children
+    #
+    # The listener receives a {Y::XMLEvent} with the changed children as
+    # delta. Changes further down the tree are not reported, see
+    # {#observe_deep}.
+    #
+    # @example Count top-level nodes
+    #   fragment = doc.get_xml_fragment("default")
+    #   fragment.attach { |event| puts event.target.length }
+    #
+    # @param callback [Proc]
+    # @param block [Block]
+    # @return [Integer] The subscription ID
+    def attach(callback = nil, &block)
+      listener = callback || block
+      return if listener.nil?
+
+      yxml_fragment_observe(Event.listener(document, listener))
+    end
+
+    # Detach a listener
+    #
+    # @param subscription_id [Integer]
+    # @return [void]
+    def detach(subscription_id)
+      yxml_fragment_unobserve(subscription_id)
+    end
+
+    # Attach listener to get notified about changes anywhere in the tree
+    #
+    # The listener is called once per transaction with all events of the
+    # transaction. The path of an event leads from this fragment to the
+    # changed node, a list of child indices.
+    #
+    # @example Reindex changed paragraphs
+    #   fragment = doc.get_xml_fragment("default")
+    #   fragment.observe_deep do |events|
+    #     events.each { |event| reindex(event.target) if event.path.size == 1 }
+    #   end
+    #
+    # @param callback [Proc]
+    # @param block [Block]
+    # @return [Integer] The subscription ID
+    def observe_deep(callback = nil, &block)
+      listener = callback || block
+      return if listener.nil?
+
+      yxml_fragment_observe_deep(
+        proc do |events|
+          events.each { |event| event.document = document }
+          listener.call(events)
+        end
+      )
+    end
+
+    # Detach a deep listener
+    #
+    # @param subscription_id [Integer]
+    # @return [void]
+    def unobserve_deep(subscription_id)
+      yxml_fragment_unobserve_deep(subscription_id)
+    end
+
     # Retrieve first child
     #
     # @return [Y::XMLElement, Y::XMLFragment, Y::XMLText, nil]
@@ -1092,6 +1289,28 @@ module Y
       text
     end
 
+    # Insert the nodes parsed from markup at the given index
+    #
+    # Elements, their attributes and texts are created in a single
+    # transaction. Comments, processing instructions and whitespace between
+    # elements are ignored, entities are decoded.
+    #
+    # @example Insert a paragraph
+    #   fragment.insert_xml(0, '<paragraph align="left">Hello</paragraph>')
+    #   fragment.to_s # => "<paragraph align=\"left\">Hello</paragraph>"
+    #
+    # @param index [Integer]
+    # @param markup [String]
+    # @return [::Array<Y::XMLElement, Y::XMLText>] The inserted nodes
+    # @raise [Y::MarkupError] if the markup is not well-formed
+    def insert_xml(index, markup)
+      nodes = document.current_transaction do |tx|
+        yxml_fragment_insert_xml(tx, index, markup)
+      end
+      nodes.each { |node| node.document = document }
+      nodes
+    end
+
     # Iterate over direct child nodes
     #
     # @yield [Y::XMLElement, Y::XMLText, Y::XMLFragment]
@@ -1112,6 +1331,87 @@ module Y
       self
     end
 
+    # Iterate over all descendants in document order
+    #
+    # Unlike {#each}, nested nodes are visited too. Every node is yielded with
+    # its depth (1 for children of this fragment) and its path, the indices of
+    # the node and its ancestors below this fragment.
+    #
+    # @example Print an outline
+    #   fragment.each_descendant do |node, depth, path|
+    #     puts "#{"  " * (depth - 1)}#{path.inspect} #{node}"
+    #   end
+    #
+    # @yieldparam node [Y::XMLElement, Y::XMLText]
+    # @yieldparam depth [Integer]
+    # @yieldparam path [::Array<Integer>]
+    # @return [self]
+    def each_descendant(&block)
+      return enum_for(:each_descendant) unless block_given?
+
+      descendants = document.current_transaction do |tx|
+        yxml_fragment_descendants(tx)
+      end
+      descendants.each do |node, depth, path|
+        node.document = document
+        block.call(node, depth, path)
+      end
+      self
+    end
+
+    # Returns all descendant elements with the tag and attributes
+    #
+    # Without arguments, this is Enumerable#find_all over the children.
+    #
+    # @example Find checked list items
+    #   fragment.find_all("listItem", checked: "true")
+    #
+    # @param tag [String, nil] Matches any tag if nil
+    # @param attributes [Hash{Symbol => String, nil}] Attribute values to
+    #   match, nil only requires the attribute to be present
+    # @return [::Array<Y::XMLElement>]
+    def find_all(tag = nil, **attributes, &block)
+      return super(&block) if tag.nil? && attributes.empty?
+
+      attributes = attributes.map { |name, value| [name.to_s, value&.to_s] }
+      nodes = document.current_transaction do |tx|
+        yxml_fragment_find_all(tx, tag&.to_s, attributes)
+      end
+      nodes.each { |node| node.document = document }
+      nodes
+    end
+
+    # Returns all descendant elements that match a CSS-like selector
+    #
+    # Supported are tags, `*`, attribute conditions (`[name]`,
+    # `[name="value"]`) and the descendant (space) and child (`>`)
+    # combinators. Nodes are returned in document order.
+    #
+    # @example Find the paragraphs of bullet list items
+    #   fragment.query_selector_all("bulletList > listItem paragraph")
+    #
+    # @param selector [String]
+    # @return [::Array<Y::XMLElement>]
+    # @raise [ArgumentError] if the selector is invalid
+    def query_selector_all(selector)
+      nodes = document.current_transaction do |tx|
+        yxml_fragment_query(tx, selector)
+      end
+      nodes.each { |node| node.document = document }
+      nodes
+    end
+
+    # Returns the first descendant element that matches a CSS-like selector
+    #
+    # @see #query_selector_all
+    #
+    # @param selector [String]
+    # @return [Y::XMLElement, nil]
+    # @raise [ArgumentError] if the selector is invalid
+    def query_selector(selector)
+      query_selector_all(selector).first
+    end
+
     # rubocop:disable Metrics/AbcSize, Metrics/CyclomaticComplexity, Metrics/MethodLength, Metrics/PerceivedComplexity
 
     # Removes one or more children from XML Fragment
@@ -1189,6 +1489,96 @@ module Y
       document.current_transaction { |tx| yxml_fragment_successors(tx) }
     end
 
+    # Returns the content of the fragment as a ProseMirror (Tiptap) document
+    #
+    # Elements are nodes with the tag as `type` and the attributes as
+    # `attrs`, texts become one text node per formatting run, with the
+    # formatting attributes as `marks`, like y-prosemirror maps them. Hashes
+    # have String keys, like parsed JSON.
+    #
+    # The layout is not compatible with y-prosemirror for anything but
+    # String attributes: y-prosemirror stores attribute values and the marks
+    # of nodes as JSON values, but XML attributes of yrs 0.17 can only be
+    # Strings. Here, the marks of a node are a JSON String in the special
+    # `marks` attribute, and only the attributes listed in the special
+    # `json-attrs` attribute are decoded from JSON, all others stay Strings.
+    #
+    # @example Read a Tiptap document
+    #   fragment = doc.get_xml_fragment("default")
+    #   fragment.to_prosemirror_json
+    #   # => {"type" => "doc", "content" => [
+    #   #      {"type" => "paragraph", "content" => [
+    #   #        {"type" => "text", "text" => "Hi",
+    #   #         "marks" => [{"type" => "bold"}]}
+    #   #      ]}
+    #   #    ]}
+    #
+    # @return [Hash]
+    def to_prosemirror_json
+      document.current_transaction do |tx|
+        yxml_fragment_to_prosemirror_json(tx)
+      end
+    end
+
+    # Populates an empty fragment from a ProseMirror (Tiptap) document
+    #
+    # This is the reverse of {#to_prosemirror_json}. Attribute values that are
+    # not Strings are stored as JSON, with their names listed in the special
+    # `json-attrs` attribute. y-prosemirror clients read these values, and
+    # the marks of nodes, as JSON Strings (see {#to_prosemirror_json}).
+    # Adjacent text nodes are stored in one XML text. A mark that occurs more
+    # than once on the same text node gets a hashed suffix (e.g.
+    # `comment--Ab3+x9Qz`), like y-prosemirror does for overlapping marks.
+    #
+    # @example Write a ProseMirror document
+    #   fragment.load_prosemirror_json(
+    #     "type" => "doc",
+    #     "content" => [
+    #       { "type" => "heading", "attrs" => { "level" => 1 },
+    #         "content" => [{ "type" => "text", "text" => "Title" }] }
+    #     ]
+    #   )
+    #
+    # @param json [Hash] A `doc` node, with String or Symbol keys
+    # @return [void]
+    # @raise [ArgumentError] if the fragment is not empty or a node is invalid
+    def load_prosemirror_json(json)
+      document.current_transaction do |tx|
+        unless yxml_fragment_len(tx).zero?
+          raise ArgumentError, "cannot load document into non-empty fragment"
+        end
+
+        yxml_fragment_load_prosemirror_json(tx, json)
+      end
+    end
+
+    # Updates the fragment to match a ProseMirror (Tiptap) document with as
+    # few changes as possible
+    #
+    # Unlike replacing all children, unchanged nodes are kept and changed
+    # texts are edited in place, so concurrent edits of other parts of the
+    # document are preserved. The algorithm is the one of y-prosemirror's
+    # `updateYFragment`: equal children are skipped from both ends, the
+    # remaining children are updated if their tag matches and replaced
+    # otherwise.
+    #
+    # @example Apply an edited document
+    #   json = fragment.to_prosemirror_json
+    #   json["content"] << {
+    #     "type" => "paragraph",
+    #     "content" => [{ "type" => "text", "text" => "Added by an agent" }]
+    #   }
+    #   fragment.reconcile(json)
+    #
+    # @param target_tree [Hash] A `doc` node, with String or Symbol keys
+    # @return [void]
+    # @raise [ArgumentError] if a node is invalid
+    def reconcile(target_tree)
+      document.current_transaction do |tx|
+        yxml_fragment_reconcile(tx, target_tree)
+      end
+    end
+
     # Returns string representation of XMLFragment
     #
     # @return [String]
@@ -1196,6 +1586,17 @@ module Y
       document.current_transaction { |tx| yxml_fragment_to_s(tx) }
     end
 
+    # Returns where the fragment does not match the schema
+    #
+    # @example Find list items outside of lists
+    #   fragment.validate(schema).select { |v| v[:error] == :invalid_child }
+    #
+    # @param schema [Y::XMLSchema]
+    # @return [::Array<Hash>] The violations, see {Y::XMLSchema#validate}
+    def validate(schema)
+      schema.validate(self)
+    end
+
     # Creates a new node and puts it in front of the child list
     #
     # @param name [String]
@@ -1208,10 +1609,48 @@ module Y
       xml_element
     end
 
+    # @!method yxml_fragment_descendants(tx)
+    #
+    # @param tx [Y::Transaction]
+    # @return [::Array<::Array>] Node, depth and path of every descendant
+
+    # @!method yxml_fragment_find_all(tx, tag, attributes)
+    #
+    # @param tx [Y::Transaction]
+    # @param tag [String, nil]
+    # @param attributes [::Array<::Array<String, nil>>]
+    # @return [::Array<Y::XMLElement>]
+
     # @!method yxml_fragment_first_child
     #
     # @return [Y::XMLElement, Y::XMLFragment, Y::XMLText, nil]
 
+    # @!method yxml_fragment_load_prosemirror_json(tx, json)
+    #
+    # @param tx [Y::Transaction]
+    # @param json [Hash]
+    # @return [void]
+
+    # @!method yxml_fragment_observe(callback)
+    #
+    # @param callback [Proc]
+    # @return [Integer]
+
+    # @!method yxml_fragment_observe_deep(callback)
+    #
+    # @param callback [Proc]
+    # @return [Integer]
+
+    # @!method yxml_fragment_unobserve(subscription_id)
+    #
+    # @param subscription_id [Integer]
+    # @return [void]
+
+    # @!method yxml_fragment_unobserve_deep(subscription_id)
+    #
+    # @param subscription_id [Integer]
+    # @return [void]
+
     # @!method yxml_fragment_get(tx, index)
     #
     # @param [Y::Transaction] tx
@@ -1225,6 +1664,13 @@ module Y
     # @param [String] tag
     # @return [Y::XMLElement, Y::XMLFragment, Y::XMLText, nil]
 
+    # @!method yxml_fragment_insert_xml(tx, index, markup)
+    #
+    # @param tx [Y::Transaction]
+    # @param index [Integer]
+    # @param markup [String]
+    # @return [::Array<Y::XMLElement, Y::XMLText>]
+
     # @!method yxml_fragment_len(tx)
     #
     # @param tx [Y::Transaction]
@@ -1246,6 +1692,18 @@ module Y
     # @param [String] tag
     # @return [Y::XMLElement]
 
+    # @!method yxml_fragment_query(tx, selector)
+    #
+    # @param tx [Y::Transaction]
+    # @param selector [String]
+    # @return [::Array<Y::XMLElement>]
+
+    # @!method yxml_fragment_reconcile(tx, target_tree)
+    #
+    # @param tx [Y::Transaction]
+    # @param target_tree [Hash]
+    # @return [void]
+
     # @!method yxml_fragment_remove(tx, index)
     #
     # @param tx [Y::Transaction]
@@ -1262,6 +1720,11 @@ module Y
     # @param tx [Y::Transaction]
     # @return [Array<Y::XMLElement, Y::XMLFragment, Y::XMLText>]
 
+    # @!method yxml_fragment_to_prosemirror_json(tx)
+    #
+    # @param tx [Y::Transaction]
+    # @return [Hash]
+
     # @!method yxml_fragment_to_s(tx)
     #
     # @param tx [Y::Transaction]
@@ -0,0 +1,163 @@
+# frozen_string_literal: true
+
+module Y
+  # Describes the structure of an XML document, e.g. the nodes of a Tiptap
+  # editor
+  #
+  # The schema lists the tags allowed at the top level of a fragment and,
+  # per tag, the allowed children, attributes and marks. Texts are children
+  # named `"text"`. Marks are allowed per parent: the marks of a node are the
+  # formatting of the texts and the node-level marks of its children.
+  # Options default to nothing allowed.
+  #
+  # @example Describe a document with paragraphs and bullet lists
+  #   schema = Y::XMLSchema.new(
+  #     root: %w[paragraph bulletList],
+  #     nodes: {
+  #       paragraph: { children: %w[text], marks: %w[bold italic] },
+  #       bulletList: { children: %w[listItem] },
+  #       listItem: { children: %w[paragraph], attributes: %w[checked] }
+  #     }
+  #   )
+  #
+  #   fragment.insert_xml(0, "<listItem><paragraph>x</paragraph></listItem>")
+  #   fragment.validate(schema)
+  #   # => [{path: [0], error: :invalid_child,
+  #   #      message: "listItem is not allowed in the root"}]
+  class XMLSchema
+    # Name of text children
+    TEXT = "text"
+
+    NodeSpec = Struct.new(:children, :attributes, :marks)
+    private_constant :NodeSpec
+
+    # @param root [::Array<String, Symbol>] Tags allowed at the top level
+    # @param nodes [Hash{String, Symbol => Hash}] Allowed `children`,
+    #   `attributes` and `marks` by tag
+    def initialize(root:, nodes:)
+      @root = NodeSpec.new(names(root), [], [])
+      @nodes = nodes.to_h do |tag, spec|
+        spec = spec.transform_keys(&:to_sym)
+        [
+          tag.to_s,
+          NodeSpec.new(
+            names(spec[:children]),
+            names(spec[:attributes]),
+            names(spec[:marks])
+          )
+        ]
+      end
+    end
+
+    # Returns the violations of the fragment
+    #
+    # Every violation has a `path` (the indices of the node from the
+    # fragment, like {Y::Event#path}), an `error` (`:unknown_node`,
+    # `:invalid_child`, `:invalid_attribute` or `:invalid_mark`) and a
+    # `message`.
+    #
+    # @param fragment [Y::XMLFragment]
+    # @return [::Array<Hash>] Empty if the fragment is valid
+    def validate(fragment)
+      violations = []
+      content = fragment.to_prosemirror_json["content"]
+      validate_content(content, "the root", @root, [], violations)
+      violations
+    end
+
+    # Changes a fragment only if it matches the schema afterwards
+    #
+    # The block is called with the fragment of a fork of the document (see
+    # {Y::Doc#fork}). If the changed fragment is valid, the changes are
+    # merged into the document, otherwise the document is left unchanged.
+    # The fork copies the whole document and has a client ID of its own,
+    # which is added to the state vector of the document by the merge.
+    #
+    # Only changes made in this block are validated. To reject every change
+    # that makes the fragment invalid, see {Y::Doc#enforce_schema}.
+    #
+    # @example Reject an invalid edit
+    #   schema.transact(doc, "default") do |fragment|
+    #     fragment.insert_xml(0, "<listItem/>")
+    #   end # raises Y::SchemaViolationError
+    #
+    # @param doc [Y::Doc]
+    # @param name [String] The name of the fragment
+    # @yieldparam fragment [Y::XMLFragment]
+    # @return [void]
+    # @raise [Y::SchemaViolationError] if the changed fragment is invalid
+    def transact(doc, name)
+      fork = doc.fork
+      fragment = fork.get_xml_fragment(name)
+      yield fragment
+
+      violations = validate(fragment)
+      raise SchemaViolationError, violations unless violations.empty?
+
+      doc.merge_from(fork)
+      nil
+    end
+
+    private
+
+    def names(list)
+      Array(list).map(&:to_s).freeze
+    end
+
+    # Consecutive text nodes are stored in a single XML text, they share an
+    # index in the path
+    def validate_content(content, parent, spec, path, violations)
+      index = -1
+      previous_text = false
+      Array(content).each do |node|
+        text = node["type"] == TEXT
+        index += 1 unless text && previous_text
+        previous_text = text
+
+        validate_node(node, parent, spec, path + [index], violations)
+      end
+    end
+
+    def validate_node(node, parent, spec, path, violations)
+      type = node["type"]
+      unless spec.children.include?(type)
+        violations << violation(path, :invalid_child,
+                                "#{type} is not allowed in #{parent}")
+      end
+      validate_marks(node, parent, spec, path, violations)
+      return if type == TEXT
+
+      node_spec = @nodes[type]
+      if node_spec.nil?
+        violations << violation(path, :unknown_node, "#{type} is unknown")
+      else
+        validate_attributes(node, node_spec, path, violations)
+        validate_content(node["content"], type, node_spec, path, violations)
+      end
+    end
+
+    def validate_marks(node, parent, spec, path, violations)
+      Array(node["marks"]).each do |mark|
+        type = mark["type"]
+        next if spec.marks.include?(type)
+
+        violations << violation(path, :invalid_mark,
+                                "#{type} mark is not allowed in #{parent}")
+      end
+    end
+
+    def validate_attributes(node, spec, path, violations)
+      type = node["type"]
+      node.fetch("attrs", {}).each_key do |attribute|
+        next if spec.attributes.include?(attribute)
+
+        violations << violation(path, :invalid_attribute,
+                                "#{attribute} is not allowed on #{type}")
+      end
+    end
+
+    def violation(path, error, message)
+      { path: path, error: error, message: message }
+    end
+  end
+end
@@ -220,7 +220,7 @@ RSpec.describe Y::Array do
     it "invokes callback" do
       called = nil
 
-      subscription_id = arr.attach { |changes| called = changes }
+      subscription_id = arr.attach { |event| called = event.delta }
 
       arr << 1
       arr << 2
@@ -233,7 +233,7 @@ RSpec.describe Y::Array do
       expect(called).to eq(
         [
           { retain: 1 },
-          { added: [3] }
+          { insert: [3] }
         ]
       )
     end
@@ -242,7 +242,7 @@ RSpec.describe Y::Array do
     it "commits automatically" do
       changes = []
 
-      arr.attach { |delta| changes << delta }
+      arr.attach { |event| changes << event.delta }
 
       local.transact do
         arr << 1
@@ -259,15 +259,49 @@ RSpec.describe Y::Array do
       end
 
       expect(arr.to_a).to contain_exactly(1, 2, 3)
-      expect(changes).to contain_exactly([{ added: [1, 2, 3] }], [
+      expect(changes).to contain_exactly([{ insert: [1, 2, 3] }], [
                                            { retain: 1 },
-                                           { removed: 1 }
+                                           { delete: 1 }
                                          ], [
                                            { retain: 1 },
-                                           { added: [2] }
+                                           { insert: [2] }
                                          ])
     end
     # rubocop:enable RSpec/MultipleExpectations
+
+    it "passes an array event" do
+      event = nil
+      arr.attach { |e| event = e }
+
+      local.transact_with("importer") { arr << 1 }
+
+      expect(event).to be_a(Y::ArrayEvent)
+      expect(event.target.to_a).to eq([1])
+      expect([event.path, event.keys, event.origin]).to eq([[], {}, "importer"])
+    end
   end
   # rubocop:enable RSpec/ExampleLength
+
+  context "when storing strings" do
+    let(:arr) { Y::Doc.new.get_array("my array") }
+
+    it "returns binary strings as frozen binary strings" do
+      arr << "\x00\xFF".b
+
+      expect(arr.first).to have_attributes(
+        encoding: Encoding::BINARY, frozen?: true, bytes: [0, 255]
+      )
+    end
+
+    it "raises for text with invalid bytes" do
+      expect { arr << "\xFF".dup.force_encoding(Encoding::UTF_8) }
+        .to raise_error(Y::InvalidEncodingError)
+    end
+
+    it "converts text in other encodings to UTF-8" do
+      arr << "caf\xE9".dup.force_encoding(Encoding::ISO_8859_1)
+
+      expect(arr.first).to eq("café")
+    end
+  end
 end
@@ -84,6 +84,124 @@ RSpec.describe Y::Awareness do
     expect(event).to be_instance_of(Y::AwarenessEvent)
   end
 
+  it "returns local_state as Hash with string keys" do
+    local_awareness.local_state = { cursor: { pos: 0 }, name: "User A" }
+
+    expect(local_awareness.local_state).to eq(
+      { "cursor" => { "pos" => 0 }, "name" => "User A" }
+    )
+  end
+
+  it "returns remote states as Ruby values" do
+    local_awareness.local_state = [1, 2.5, nil, true]
+
+    remote_awareness = described_class.new
+    remote_awareness.sync(local_awareness.diff)
+
+    expect(remote_awareness.clients[local_id]).to eq([1, 2.5, nil, true])
+  end
+
+  it "sets and receives local_state as JSON string" do
+    local_awareness.local_state_json = '{"name":"User A"}'
+
+    expect(local_awareness.local_state_json).to eq('{"name":"User A"}')
+  end
+
+  it "lists all clients with their JSON encoded state" do
+    local_awareness.local_state = { name: "User A" }
+
+    expect(local_awareness.clients_json[local_id]).to eq('{"name":"User A"}')
+  end
+
+  it "raises for states that cannot be represented as JSON" do
+    expect { local_awareness.local_state = { value: Float::NAN } }
+      .to raise_error(RangeError)
+  end
+
+  it "emits change events only when a state changed" do
+    remote_awareness = described_class.new
+    changes = []
+    remote_awareness.on_change { |event| changes << event }
+
+    local_awareness.local_state = state
+    remote_awareness.sync(local_awareness.diff)
+    local_awareness.local_state = state
+    remote_awareness.sync(local_awareness.diff)
+
+    expect(changes.map(&:added)).to eq([[local_id]])
+  end
+
+  it "emits update events for every clock bump" do
+    remote_awareness = described_class.new
+    updates = []
+    remote_awareness.on_update { |event| updates << event }
+
+    local_awareness.local_state = state
+    remote_awareness.sync(local_awareness.diff)
+    local_awareness.local_state = state
+    remote_awareness.sync(local_awareness.diff)
+
+    expect(updates.size).to eq(2)
+  end
+
+  it "passes the origin of an update to the event" do
+    origin = nil
+    local_awareness.local_state = state
+
+    remote_awareness = described_class.new
+    remote_awareness.on_update { |event| origin = event.origin }
+    remote_awareness.sync(local_awareness.diff, "connection-1")
+
+    expect(origin).to eq("connection-1")
+  end
+
+  it "marks local state changes with the local origin" do
+    origin = nil
+    local_awareness.on_change { |event| origin = event.origin }
+
+    local_awareness.local_state = state
+
+    expect(origin).to eq("local")
+  end
+
+  it "allows reading the state from within a listener" do
+    received = nil
+    local_awareness.on_change do |_event|
+      received = local_awareness.local_state
+    end
+
+    local_awareness.local_state = state
+
+    expect(received).to eq(state)
+  end
+
+  it "calls the remaining listeners when a listener raises" do
+    updates = []
+    local_awareness.on_change { |_event| raise "listener failed" }
+    local_awareness.on_update { |event| updates << event }
+
+    expect { local_awareness.local_state = state }
+      .to raise_error(RuntimeError, "listener failed")
+    expect(updates.size).to eq(1)
+  end
+
+  it "unsubscribes a listener" do
+    called = false
+    subscription = local_awareness.on_update { |_event| called = true }
+    subscription.unsubscribe
+
+    local_awareness.local_state = state
+
+    expect(called).to be(false)
+  end
+
+  it "detaches a subscription" do
+    subscription = local_awareness.attach { |_event| nil }
+    local_awareness.detach(subscription)
+
+    expect(subscription.subscribed?).to be(false)
+  end
+
   context "when syncing multiple client states" do
     it "merges state of all clients" do
       client_a = described_class.new
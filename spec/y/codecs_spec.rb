@@ -0,0 +1,84 @@
+# frozen_string_literal: true
+
+require "time"
+
+RSpec.describe Y::Codecs do
+  let(:doc) { Y::Doc.new }
+  let(:map) { doc.get_map("my map") }
+
+  point = Struct.new(:x, :y)
+
+  after do
+    described_class.unregister(Time)
+    described_class.unregister(Struct)
+    described_class.fallback = false
+  end
+
+  it "raises for objects without a codec" do
+    expect { map[:created_at] = Time.now }
+      .to raise_error(Y::UnsupportedTypeError)
+  end
+
+  context "with a registered codec" do
+    before do
+      Y.register_codec(
+        Time,
+        encode: ->(time) { time.iso8601(6) },
+        decode: ->(value) { Time.iso8601(value) }
+      )
+    end
+
+    it "reads back the decoded object" do
+      time = Time.at(1_700_000_000, 123_456, :usec)
+      map[:created_at] = time
+
+      expect(map[:created_at]).to eq(time)
+    end
+
+    it "stores the encoded value with the codec name" do
+      map[:created_at] = Time.at(0).utc
+
+      remote = Y::Doc.new
+      remote.sync(doc.diff)
+      described_class.unregister(Time)
+
+      expect(remote.get_map("my map")[:created_at]).to eq(
+        "$codec" => "Time", "value" => "1970-01-01T00:00:00.000000Z"
+      )
+    end
+
+    it "encodes nested objects" do
+      time = Time.at(1_700_000_000)
+      doc.get_array("my array") << [{ at: time }]
+
+      expect(doc.get_array("my array").first).to eq([{ "at" => time }])
+    end
+
+    it "raises errors of the codec when decoding" do
+      map[:created_at] = Time.now
+      described_class.register(
+        Time,
+        encode: ->(time) { time.iso8601(6) },
+        decode: ->(_value) { raise ArgumentError, "invalid time" }
+      )
+
+      expect { map[:created_at] }.to raise_error(ArgumentError, "invalid time")
+    end
+  end
+
+  it "uses the codec of an ancestor class" do
+    Y.register_codec(Struct, encode: :to_h.to_proc, decode: ->(v) { v })
+
+    map[:point] = point.new(1, 2)
+
+    expect(map[:point]).to eq("x" => 1, "y" => 2)
+  end
+
+  it "falls back to to_h when configured" do
+    described_class.fallback = true
+
+    map[:point] = point.new(1, 2)
+
+    expect(map[:point]).to eq("x" => 1, "y" => 2)
+  end
+end
@@ -111,7 +111,7 @@ RSpec.describe Y::Doc do
       local_text = local_doc.get_text("my text")
 
       changes = nil
-      local_text.attach(->(c) { changes = c })
+      local_text.attach(->(event) { changes = event.delta })
 
       remote_doc = described_class.new
       remote_text = remote_doc.get_text("my text")
@@ -121,7 +121,7 @@ RSpec.describe Y::Doc do
       local_doc.sync(update)
       local_doc.commit
 
-      expect(changes).to eq({ insert: "hello" })
+      expect(changes).to eq([{ insert: "hello" }])
     end
     # rubocop:enable RSpec/ExampleLength
   end
@@ -186,4 +186,252 @@ RSpec.describe Y::Doc do
       expect(remote_text.to_s).to eq(local_text.to_s)
     end
   end
+
+  context "when updates arrive out of order" do
+    let(:local) { described_class.new(1) }
+    let(:local_text) { local.get_text("my text") }
+    let!(:first_update) do
+      local_text << "Hello"
+      local.diff
+    end
+    let!(:second_update) do
+      state = local.state
+      local_text << ", World!"
+      local.diff(state)
+    end
+    let(:remote) { described_class.new(2) }
+
+    it "reports the integrated clock ranges" do
+      expect(remote.sync(first_update))
+        .to eq({ integrated: [{ client: 1, clock: 0...5 }], pending: false,
+                 missing: {} })
+    end
+
+    it "keeps an update with missing dependencies pending" do
+      report = remote.sync(second_update)
+
+      expect(report).to include(integrated: [], pending: true)
+    end
+
+    it "reports the clock missing per client" do
+      report = remote.sync(second_update)
+
+      expect(report).to include(missing: { 1 => 0 })
+    end
+
+    it "reports that changes are pending" do
+      remote.sync(second_update)
+
+      expect(remote).to be_pending
+    end
+
+    it "returns the missing state vector" do
+      remote.sync(second_update)
+
+      expect(remote.missing_state_vector).not_to be_nil
+    end
+
+    it "returns the pending update" do
+      remote.sync(second_update)
+
+      other = described_class.new(3)
+      other.sync(first_update)
+      other.sync(remote.pending_update)
+
+      expect(other.get_text("my text").to_s).to eq("Hello, World!")
+    end
+
+    it "integrates pending changes once the dependencies arrive" do
+      remote.sync(second_update)
+      remote.sync(first_update)
+
+      expect(remote).not_to be_pending
+    end
+
+    it "applies deletions once the deleted changes arrive" do
+      state = local.state
+      local_text.slice!(0, 7)
+      remote.sync(local.diff(state))
+      remote.sync(first_update)
+      remote.sync(second_update)
+
+      expect(remote.get_text("my text").to_s).to eq("World!")
+    end
+  end
+
+  context "when collecting stats" do
+    let(:doc) { described_class.new(1, { gc: false }) }
+
+    it "counts blocks per client" do
+      doc.get_text("my text") << "Hello"
+
+      expect(doc.stats[:clients])
+        .to match([include(client: 1, clock: 0...5, items: 1)])
+    end
+
+    it "counts tombstones" do
+      text = doc.get_text("my text")
+      text << "Hello"
+      text.delete(0, 2)
+
+      expect(doc.stats[:clients].first[:tombstones]).to eq(1)
+    end
+
+    it "counts root and nested types" do
+      fragment = doc.get_xml_fragment("my fragment")
+      fragment << "p"
+      doc.get_text("my text") << "Hello"
+
+      expect(doc.stats).to include(root_types: 2, nested_types: 1)
+    end
+
+    it "counts root types without content" do
+      doc.get_map("my map")
+
+      expect(doc.stats).to include(root_types: 1, heap_size: 0)
+    end
+
+    it "reports the encoded size" do
+      doc.get_text("my text") << "Hello"
+
+      expect(doc.stats[:encoded_size]).to eq({ v1: doc.diff.size,
+                                               v2: doc.diff_v2.size })
+    end
+  end
+
+  context "when compacting" do
+    let(:doc) { described_class.new(1, { gc: false }) }
+    let(:text) { doc.get_text("my text") }
+
+    before do
+      text << "Hello, World!"
+    end
+
+    it "keeps the content" do
+      text.delete(5, 7)
+
+      expect(doc.compact.get_text("my text").to_s).to eq("Hello!")
+    end
+
+    it "removes tombstones" do
+      text.delete(5, 7)
+
+      expect(doc.compact.stats[:clients].first[:tombstones]).to eq(0)
+    end
+
+    it "produces a smaller update" do
+      text.delete(5, 7)
+
+      expect(doc.compact.diff.size).to be < doc.diff.size
+    end
+
+    it "preserves snapshots since the given snapshot" do
+      snapshot = doc.snapshot
+      text.delete(5, 7)
+
+      compacted = doc.compact(since: snapshot)
+      restored = described_class.new
+      restored.sync(compacted.diff_from_snapshot(snapshot))
+
+      expect(restored.get_text("my text").to_s).to eq("Hello, World!")
+    end
+  end
+
+  context "when forking" do
+    let(:doc) { described_class.new(1) }
+
+    before do
+      doc.get_text("my text") << "Hello"
+    end
+
+    it "copies the content" do
+      expect(doc.fork.get_text("my text").to_s).to eq("Hello")
+    end
+
+    it "does not change the original document" do
+      doc.fork.get_text("my text") << ", World!"
+
+      expect(doc.get_text("my text").to_s).to eq("Hello")
+    end
+
+    it "uses the given client ID" do
+      fork = doc.fork(client_id: 2)
+      fork.get_text("my text") << "!"
+
+      expect(fork.stats[:clients].map { |client| client[:client] })
+        .to contain_exactly(1, 2)
+    end
+
+    it "raises for the client ID of the document" do
+      expect { doc.fork(client_id: 1) }.to raise_error(ArgumentError)
+    end
+
+    it "merges the changes of a fork" do
+      fork = doc.fork
+      fork.get_text("my text") << ", World!"
+
+      doc.merge_from(fork)
+
+      expect(doc.get_text("my text").to_s).to eq("Hello, World!")
+    end
+
+    it "merges changes made concurrently" do
+      fork = doc.fork
+      fork.get_text("my text") << "!"
+      doc.get_text("my text").insert(0, ">")
+
+      doc.merge_from(fork)
+
+      expect(doc.get_text("my text").to_s).to eq(">Hello!")
+    end
+  end
+
+  context "when storing integers" do
+    it "stores integers as numbers" do
+      doc = described_class.new
+      doc.get_array("my array") << 42
+
+      block = Y::Update.decode(doc.diff)[:clients].first[:blocks].first
+      expect(block).to include(content: :any, preview: "42")
+    end
+
+    it "reads integers back as Integer" do
+      doc = described_class.new
+      array = doc.get_array("my array")
+      array << 42
+
+      expect(array.first).to be_an(Integer)
+    end
+
+    it "keeps integers outside the safe range lossless" do
+      doc = described_class.new
+      array = doc.get_array("my array")
+      array << ((2**53) + 1)
+
+      expect(array.first).to eq((2**53) + 1)
+    end
+
+    it "stores integers as BigInt when configured" do
+      doc = described_class.new({ integers: :bigint })
+      doc.get_array("my array") << 42
+
+      block = Y::Update.decode(doc.diff)[:clients].first[:blocks].first
+      expect(block).to include(content: :any, preview: "42n")
+    end
+
+    it "reads BigInt values back as Integer" do
+      doc = described_class.new({ integers: :bigint })
+      array = doc.get_array("my array")
+      array << 42
+
+      expect(array.first).to eq(42).and be_an(Integer)
+    end
+
+    it "raises for integers that do not fit into 64 bits" do
+      doc = described_class.new
+      array = doc.get_array("my array")
+
+      expect { array << (2**64) }.to raise_error(Y::IntegerOutOfRangeError)
+    end
+  end
 end
@@ -200,7 +200,7 @@ RSpec.describe Y::Map do
       map = local.get_map("my map")
 
       called = []
-      listener = proc { |changes| called = changes }
+      listener = proc { |event| called = event.keys }
 
       subscription_id = map.attach(listener)
 
@@ -211,8 +211,10 @@ RSpec.describe Y::Map do
 
       map.detach(subscription_id)
 
-      expect(called).to contain_exactly({ inserted: { hello: "world" } },
-                                        { inserted: { say: "goodbye" } })
+      expect(called).to eq(
+        "hello" => { action: :inserted, old_value: nil, new_value: "world" },
+        "say" => { action: :inserted, old_value: nil, new_value: "goodbye" }
+      )
     end
 
     # rubocop:disable RSpec/MultipleExpectations
@@ -222,7 +224,7 @@ RSpec.describe Y::Map do
       changes = []
 
       map = local.get_map("my map")
-      map.attach(->(delta) { changes << delta })
+      map.attach(->(event) { changes << event.keys })
 
       local.transact do
         map[:hello] = "world"
@@ -244,12 +246,30 @@ RSpec.describe Y::Map do
         }
       )
 
-      expect(changes[0]).to contain_exactly({ inserted: { say: "goodbye" } },
-                                            { inserted: { hello: "world" } })
-      expect(changes[1]).to contain_exactly({ removed: { say: "goodbye" } })
-      expect(changes[2]).to contain_exactly({ inserted: { say: "hello again" } })
+      expect(changes[0].keys).to contain_exactly("hello", "say")
+      expect(changes[1]).to eq(
+        "say" => { action: :removed, old_value: "goodbye", new_value: nil }
+      )
+      expect(changes[2]).to eq(
+        "say" => { action: :inserted, old_value: nil, new_value: "hello again" }
+      )
     end
     # rubocop:enable RSpec/MultipleExpectations
+
+    it "reports updated values" do
+      local = Y::Doc.new
+      map = local.get_map("my map")
+      map[:count] = 1
+
+      event = nil
+      map.attach { |e| event = e }
+      map[:count] = 2
+
+      expect(event).to be_a(Y::MapEvent)
+      expect(event.keys).to eq(
+        "count" => { action: :updated, old_value: 1, new_value: 2 }
+      )
+    end
   end
   # rubocop:enable RSpec/ExampleLength
 end
@@ -0,0 +1,217 @@
+# frozen_string_literal: true
+
+RSpec.describe Y::Room do
+  # Registers a fake client that is backed by its own document and records
+  # the types of all messages it receives
+  def connect(room, read_only: false)
+    doc = Y::Doc.new
+    inbox = []
+    id = room.connect(read_only: read_only) do |message|
+      Y::Sync.decode(message).each do |type, payload|
+        inbox << type
+        doc.sync(payload) if %i[sync_step2 update].include?(type)
+      end
+    end
+    room.receive(id, Y::Sync.sync_step1(doc.state))
+
+    { id: id, doc: doc, inbox: inbox }
+  end
+
+  def send_update(room, connection)
+    room.receive(connection[:id], Y::Sync.update(connection[:doc].diff))
+  end
+
+  def send_awareness(room, connection, state)
+    awareness = Y::Awareness.new
+    awareness.local_state = state
+    room.receive(connection[:id], Y::Sync.awareness(awareness.diff))
+  end
+
+  let(:room) { described_class.new }
+
+  it "starts the handshake when a connection is registered" do
+    alice = connect(room)
+
+    expect(alice[:inbox]).to eq(%i[sync_step1 sync_step2])
+  end
+
+  it "sends the current document to a new connection" do
+    text = room.doc.get_text("my text")
+    room.transact { text << "Hello" }
+
+    alice = connect(room)
+
+    expect(alice[:doc].get_text("my text").to_s).to eq("Hello")
+  end
+
+  it "applies updates from a connection to the room document" do
+    alice = connect(room)
+    alice[:doc].get_text("my text") << "Hello"
+
+    send_update(room, alice)
+
+    expect(room.doc.get_text("my text").to_s).to eq("Hello")
+  end
+
+  it "broadcasts updates to other connections" do
+    alice = connect(room)
+    bob = connect(room)
+    alice[:doc].get_text("my text") << "Hello"
+
+    send_update(room, alice)
+
+    expect(bob[:doc].get_text("my text").to_s).to eq("Hello")
+  end
+
+  it "does not send updates back to the sender" do
+    alice = connect(room)
+    alice[:doc].get_text("my text") << "Hello"
+    alice[:inbox].clear
+
+    send_update(room, alice)
+
+    expect(alice[:inbox]).to be_empty
+  end
+
+  it "broadcasts changes made on the server" do
+    alice = connect(room)
+    text = room.doc.get_text("my text")
+
+    room.transact { text << "Hello" }
+
+    expect(alice[:doc].get_text("my text").to_s).to eq("Hello")
+  end
+
+  it "broadcasts awareness updates to other connections" do
+    alice = connect(room)
+    bob = connect(room)
+
+    send_awareness(room, alice, { name: "Alice" })
+
+    expect(bob[:inbox]).to include(:awareness)
+  end
+
+  it "removes awareness states when a connection is closed" do
+    alice = connect(room)
+    bob = connect(room)
+    send_awareness(room, alice, { name: "Alice" })
+    bob[:inbox].clear
+
+    room.disconnect(alice[:id])
+
+    expect(bob[:inbox]).to eq([:awareness])
+  end
+
+  it "ignores awareness updates for clients of other connections" do
+    alice = connect(room)
+    bob = connect(room)
+    carol = connect(room)
+    awareness = Y::Awareness.new
+    awareness.local_state = { name: "Alice" }
+    room.receive(alice[:id], Y::Sync.awareness(awareness.diff))
+    carol[:inbox].clear
+
+    awareness.local_state = { name: "Mallory" }
+    room.receive(bob[:id], Y::Sync.awareness(awareness.diff))
+    room.disconnect(bob[:id])
+
+    expect(carol[:inbox]).to eq([])
+  end
+
+  it "keeps connection callbacks alive across garbage collections" do
+    messages = []
+    id = room.connect { |message| messages << message }
+    GC.start
+    alice = connect(room)
+    alice[:doc].get_text("my text") << "Hello"
+    send_update(room, alice)
+
+    expect(messages).not_to be_empty
+    expect(room.connections).to include(id)
+  end
+
+  it "raises when a message is received while a transaction is open" do
+    alice = connect(room)
+    alice[:doc].get_text("my text") << "Hello"
+
+    expect { room.doc.transact { send_update(room, alice) } }
+      .to raise_error(RuntimeError, /transaction is open/)
+  end
+
+  it "raises errors of observers when a received update is committed" do
+    alice = connect(room)
+    alice[:doc].get_text("my text") << "Hello"
+    text = room.doc.get_text("my text")
+    text.attach { |_event| raise ArgumentError, "listener failed" }
+
+    expect { send_update(room, alice) }
+      .to raise_error(ArgumentError, "listener failed")
+  end
+
+  it "lists open connections" do
+    alice = connect(room)
+    bob = connect(room)
+
+    room.disconnect(alice[:id])
+
+    expect(room.connections).to eq([bob[:id]])
+  end
+
+  context "with a read-only connection" do
+    it "completes the handshake without an auth message" do
+      viewer = connect(room, read_only: true)
+
+      expect(viewer[:inbox]).to eq(%i[sync_step1 sync_step2])
+    end
+
+    it "does not apply updates to the room document" do
+      viewer = connect(room, read_only: true)
+      viewer[:doc].get_text("my text") << "Hello"
+
+      send_update(room, viewer)
+
+      expect(room.doc.get_text("my text").to_s).to eq("")
+    end
+
+    it "replies with a denied auth message" do
+      viewer = connect(room, read_only: true)
+      viewer[:doc].get_text("my text") << "Hello"
+      viewer[:inbox].clear
+
+      send_update(room, viewer)
+
+      expect(viewer[:inbox]).to eq([:auth])
+    end
+
+    it "receives updates from other connections" do
+      viewer = connect(room, read_only: true)
+      alice = connect(room)
+      alice[:doc].get_text("my text") << "Hello"
+
+      send_update(room, alice)
+
+      expect(viewer[:doc].get_text("my text").to_s).to eq("Hello")
+    end
+
+    it "can be granted write access" do
+      viewer = connect(room, read_only: true)
+      room.read_only = { viewer[:id] => false }
+      viewer[:doc].get_text("my text") << "Hello"
+
+      send_update(room, viewer)
+
+      expect(room.doc.get_text("my text").to_s).to eq("Hello")
+    end
+  end
+
+  it "reports whether a connection is read-only" do
+    alice = connect(room)
+
+    expect(room.read_only?(alice[:id])).to be(false)
+  end
+
+  it "raises when changing the permission of an unknown connection" do
+    expect { room.read_only = { 42 => true } }
+      .to raise_error(ArgumentError, /unknown connection/)
+  end
+end
@@ -0,0 +1,31 @@
+# frozen_string_literal: true
+
+RSpec.describe Y::Sync do
+  it "encodes and decodes sync step 1" do
+    state = Y::Doc.new.state
+
+    expect(described_class.decode(described_class.sync_step1(state)))
+      .to eq([[:sync_step1, state]])
+  end
+
+  it "encodes and decodes an update" do
+    doc = Y::Doc.new
+    doc.get_text("my text") << "Hello"
+
+    expect(described_class.decode(described_class.update(doc.diff)))
+      .to eq([[:update, doc.diff]])
+  end
+
+  it "decodes multiple messages" do
+    message = described_class.awareness_query +
+              described_class.sync_step2([0, 0])
+
+    expect(described_class.decode(message).map(&:first))
+      .to eq(%i[awareness_query sync_step2])
+  end
+
+  it "encodes and decodes a denied auth message" do
+    expect(described_class.decode(described_class.auth("denied")))
+      .to eq([[:auth, "denied"]])
+  end
+end
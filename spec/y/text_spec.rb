@@ -147,6 +147,147 @@ RSpec.describe Y::Text do
     expect(text.to_s).to eq("Hello, World!")
   end
 
+  context "when applying a delta" do
+    let(:doc) { Y::Doc.new }
+    let(:text) { doc.get_text("my text") }
+
+    before { text << "Hello World" }
+
+    it "inserts and deletes text" do
+      text.apply_delta([{ delete: 5 }, { insert: "Hi" }])
+
+      expect(text.to_s).to eq("Hi World")
+    end
+
+    it "formats retained text" do
+      text.apply_delta(
+        [{ retain: 6 }, { retain: 5, attributes: { bold: true } }]
+      )
+
+      expect(text.diff.map(&:to_h)).to eq([
+                                            { insert: "Hello ", attrs: nil },
+                                            { insert: "World",
+                                              attrs: { "bold" => true } }
+                                          ])
+    end
+
+    it "removes attributes set to nil" do
+      text.format(0, 5, { bold: true })
+
+      text.apply_delta([{ "retain" => 5, "attributes" => { "bold" => nil } }])
+
+      expect(text.diff.map(&:attrs)).to eq([nil])
+    end
+
+    it "inserts embeds" do
+      text.apply_delta([{ retain: 11 }, { insert: { image: "logo.png" } }])
+
+      expect(text.diff.last.insert).to eq({ "image" => "logo.png" })
+    end
+
+    it "raises for invalid operations" do
+      expect { text.apply_delta([{ replace: 1 }]) }
+        .to raise_error(ArgumentError)
+    end
+
+    it "raises for operations beyond the end of the text" do
+      expect { text.apply_delta([{ retain: 11 }, { delete: 1 }]) }
+        .to raise_error(ArgumentError)
+    end
+
+    it "leaves the text untouched when the delta exceeds it" do
+      delta = [{ delete: 5 }, { insert: "Hi" }, { retain: 10 }]
+
+      expect { text.apply_delta(delta) }.to raise_error(ArgumentError)
+      expect(text.to_s).to eq("Hello World")
+    end
+  end
+
+  context "when replacing the text" do
+    let(:doc) { Y::Doc.new }
+    let(:text) { doc.get_text("my text") }
+
+    it "replaces the content" do
+      text << "Hello World"
+
+      text.replace_with("Hello, brave new World!")
+
+      expect(text.to_s).to eq("Hello, brave new World!")
+    end
+
+    it "keeps the formatting of unchanged text" do
+      text.insert(0, "Hello World", { bold: true })
+
+      text.replace_with("Hello Wörld")
+
+      expect(text.diff.map(&:to_h)).to eq([
+                                            { insert: "Hello Wörld",
+                                              attrs: { "bold" => true } }
+                                          ])
+    end
+
+    it "only changes the characters that differ" do
+      text << "Hello Wrld"
+      changes = []
+      text.attach(proc { |event| changes << event.delta })
+
+      text.replace_with("Hello World")
+      doc.commit
+
+      expect(changes).to eq([[{ retain: 7 }, { insert: "o" }]])
+    end
+  end
+
+  context "when converting to and from a delta" do
+    let(:doc) { Y::Doc.new }
+    let(:text) { doc.get_text("my text") }
+
+    it "merges adjacent inserts with equal attributes" do
+      text.insert(0, "Hello", { bold: true })
+      text.insert(5, " World", { bold: true })
+      text << "!"
+
+      expect(text.to_delta).to eq([
+                                    { insert: "Hello World",
+                                      attributes: { "bold" => true } },
+                                    { insert: "!" }
+                                  ])
+    end
+
+    it "exports embeds as objects" do
+      text << "Logo: "
+      text.insert(6, { image: "logo.png" })
+
+      expect(text.to_delta.last).to eq({ insert: { "image" => "logo.png" } })
+    end
+
+    it "loads a delta into an empty text" do
+      delta = [
+        { "insert" => "Hello", "attributes" => { "bold" => true } },
+        { "insert" => " World\n" }
+      ]
+
+      text.load_delta(delta)
+
+      expect(text.to_delta).to eq([
+                                    { insert: "Hello",
+                                      attributes: { "bold" => true } },
+                                    { insert: " World\n" }
+                                  ])
+    end
+
+    it "raises when loading into a non-empty text" do
+      text << "Hello"
+
+      expect { text.load_delta([{ insert: "World" }]) }
+        .to raise_error(ArgumentError)
+    end
+
+    it "raises when loading operations that are not hashes" do
+      expect { text.load_delta(["Hello"]) }.to raise_error(ArgumentError)
+    end
+  end
+
   context "when syncing documents" do
     it "updates remote text from local text" do
       local = Y::Doc.new
@@ -171,7 +312,7 @@ RSpec.describe Y::Text do
       text = local.get_text("my text")
 
       called = nil
-      listener = proc { |changes| called = changes }
+      listener = proc { |event| called = event.delta }
 
       subscription_id = text.attach(listener)
 
@@ -183,7 +324,7 @@ RSpec.describe Y::Text do
 
       text.detach(subscription_id)
 
-      expect(called).to eq({ insert: "Hello, World!" })
+      expect(called).to eq([{ insert: "Hello, World!" }])
     end
 
     # rubocop:disable RSpec/MultipleExpectations
@@ -193,7 +334,7 @@ RSpec.describe Y::Text do
       changes = []
 
       text = local.get_text("my text")
-      text.attach(proc { |delta| changes << delta })
+      text.attach(proc { |event| changes << event.delta })
 
       local.transact do
         text << "Hello, Wörld!"
@@ -208,10 +349,52 @@ RSpec.describe Y::Text do
       end
 
       expect(text.to_s).to eq("Hello, World!")
-      expect(changes).to contain_exactly({ insert: "Hello, Wörld!" },
-                                         { retain: 8 }, { delete: 1 }, { retain: 8 }, { insert: "o" })
+      expect(changes).to eq([
+                              [{ insert: "Hello, Wörld!" }],
+                              [{ retain: 8 }, { delete: 1 }],
+                              [{ retain: 8 }, { insert: "o" }]
+                            ])
     end
     # rubocop:enable RSpec/MultipleExpectations
+
+    it "passes the target and origin" do
+      local = Y::Doc.new
+      text = local.get_text("my text")
+
+      event = nil
+      text.attach { |e| event = e }
+
+      local.transact_with("agent") { text << "Hello" }
+
+      expect([event.target.to_s, event.origin]).to eq(["Hello", "agent"])
+    end
+
+    it "raises errors of the listener" do
+      local = Y::Doc.new
+      text = local.get_text("my text")
+      text.attach { |_event| raise ArgumentError, "listener failed" }
+
+      expect { local.transact { text << "Hello" } }
+        .to raise_error(ArgumentError, "listener failed")
+    end
+
+    it "raises errors of a listener only for the commit of its document" do
+      local = Y::Doc.new
+      other = Y::Doc.new
+      text = local.get_text("my text")
+      other_text = other.get_text("other text")
+      other_text.attach { |_event| raise ArgumentError, "listener failed" }
+
+      errors = []
+      text.attach do |_event|
+        other.transact { other_text << "!" }
+      rescue ArgumentError => e
+        errors << e.message
+      end
+
+      expect { local.transact { text << "Hello" } }.not_to raise_error
+      expect(errors).to eq(["listener failed"])
+    end
   end
   # rubocop:enable RSpec/ExampleLength
 end
@@ -56,4 +56,88 @@ RSpec.describe Y::Transaction do
 
     expect(xml_text).to be_instance_of(Y::XMLText)
   end
+
+  context "when applying an update with allowed root types" do
+    let(:remote) { Y::Doc.new(1) }
+    let(:local) { Y::Doc.new }
+
+    it "applies changes to allowed root types" do
+      remote.get_text("content") << "Hello"
+
+      local.sync(remote.diff, allow: ["content"])
+
+      expect(local.get_text("content").to_s).to eq("Hello")
+    end
+
+    it "applies changes to types nested in allowed root types" do
+      paragraph = remote.get_xml_fragment("content") << "p"
+      local.sync(remote.diff, allow: ["content"])
+      paragraph.push_text("Hello")
+
+      local.sync(remote.diff(local.state), allow: ["content"])
+
+      expect(local.get_xml_fragment("content").to_s).to eq("<p>Hello</p>")
+    end
+
+    it "rejects changes to other root types" do
+      remote.get_text("content") << "Hello"
+      remote.get_map("meta")[:owner] = "Alice"
+
+      expect { local.sync(remote.diff, allow: ["content"]) }
+        .to raise_error(Y::UpdateRejectedError, /meta/)
+    end
+
+    it "does not apply a rejected update" do
+      remote.get_text("content") << "Hello"
+      remote.get_map("meta")[:owner] = "Alice"
+
+      begin
+        local.sync(remote.diff, allow: ["content"])
+      rescue Y::UpdateRejectedError
+        # expected
+      end
+
+      expect(local.get_text("content").to_s).to eq("")
+    end
+
+    it "lists the violations" do
+      remote.get_map("meta")[:owner] = "Alice"
+
+      error = begin
+        local.sync(remote.diff, allow: ["content"])
+      rescue Y::UpdateRejectedError => e
+        e
+      end
+
+      expect(error.violations).to contain_exactly(
+        { root: "meta", client: 1, clock: 0, action: :insert }
+      )
+    end
+
+    it "rejects v2 updates that change other root types" do
+      remote.get_text("content") << "Hello"
+      remote.get_map("meta")[:owner] = "Alice"
+
+      expect { local.sync_v2(remote.diff_v2, allow: ["content"]) }
+        .to raise_error(Y::UpdateRejectedError, /meta/)
+      expect(local.get_text("content").to_s).to eq("")
+    end
+
+    it "applies v2 updates that change allowed root types" do
+      remote.get_text("content") << "Hello"
+
+      local.sync_v2(remote.diff_v2, allow: ["content"])
+
+      expect(local.get_text("content").to_s).to eq("Hello")
+    end
+
+    it "rejects deletions in other root types" do
+      remote.get_text("meta") << "Hello"
+      local.sync(remote.diff)
+      remote.get_text("meta").delete(0, 5)
+
+      expect { local.sync(remote.diff(local.state), allow: ["content"]) }
+        .to raise_error(Y::UpdateRejectedError, /meta/)
+    end
+  end
 end
@@ -0,0 +1,62 @@
+# frozen_string_literal: true
+
+require "base64"
+
+RSpec.describe Y::Update do
+  let(:doc) { Y::Doc.new(1) }
+
+  it "decodes the blocks of a client" do
+    doc.get_text("content") << "Hello"
+
+    client = described_class.decode(doc.diff)[:clients].first
+
+    expect(client).to include(client: 1, clock: 0...5)
+  end
+
+  it "decodes items with their parent and content" do
+    doc.get_text("content") << "Hello"
+
+    block = described_class.decode(doc.diff)[:clients].first[:blocks].first
+
+    expect(block).to include(
+      kind: :item, parent: { root: "content" }, content: :string,
+      preview: "Hello"
+    )
+  end
+
+  it "decodes moved items" do
+    # an array [1, 2] where the first element was moved to the end
+    update = [
+      1, 3, 1, 0, 8, 1, 4, 108, 105, 115, 116, 1, 125, 1, 136, 1, 0, 1, 125, 2,
+      139, 1, 1, 131, 1, 1, 0, 0
+    ]
+
+    blocks = described_class.decode(update)[:clients].first[:blocks]
+
+    expect(blocks.map { |block| block[:content] }).to eq(%i[any any move])
+  end
+
+  it "decodes the delete set" do
+    text = doc.get_text("content")
+    text << "Hello"
+    text.delete(1, 2)
+
+    expect(described_class.decode(doc.diff)[:delete_set])
+      .to eq([{ client: 1, clock: [1...3] }])
+  end
+
+  it "decodes v2 updates" do
+    doc.get_text("content") << "Hello"
+
+    client = described_class.decode(doc.diff_v2, version: 2)[:clients].first
+
+    expect(client).to include(client: 1, clock: 0...5)
+  end
+
+  it "decodes a real world document" do
+    file = File.join(__dir__, "/../files/issue131_ydoc.base64.txt")
+    update = Base64.decode64(File.read(file))
+
+    expect(described_class.decode(update)[:clients]).not_to be_empty
+  end
+end
@@ -217,6 +217,52 @@ RSpec.describe Y::XMLElement do
     end
   end
 
+  context "when replacing children with markup" do
+    let(:doc) { Y::Doc.new }
+    let(:xml) { doc.get_xml_element("root") }
+
+    it "replaces all children" do
+      xml << "A"
+      xml.push_text("old")
+
+      nodes = xml.replace_children_with_xml("<B><C/></B>new")
+
+      expect(nodes.map(&:class)).to eq([described_class, Y::XMLText])
+      expect(xml.to_s).to eq("<root><B><C></C></B>new</root>")
+    end
+
+    it "sets the document of the inserted nodes" do
+      nodes = xml.replace_children_with_xml("<A/>")
+
+      expect(nodes.first.document).to eq(doc)
+    end
+  end
+
+  context "when querying descendants" do
+    let(:doc) { Y::Doc.new }
+    let(:xml) { doc.get_xml_element("root") }
+
+    before do
+      xml.replace_children_with_xml(
+        "<table><row><cell>1</cell><cell>2</cell></row></table>"
+      )
+    end
+
+    it "walks descendants relative to the element" do
+      table = xml.first_child
+      paths = table.each_descendant.map { |_node, _depth, path| path }
+
+      expect(paths).to eq([[0], [0, 0], [0, 0, 0], [0, 1], [0, 1, 0]])
+    end
+
+    it "finds and queries descendant elements" do
+      expect(xml.find_all("cell").map(&:to_s))
+        .to eq(%w[<cell>1</cell> <cell>2</cell>])
+      expect(xml.query_selector("table > row > cell").to_s)
+        .to eq("<cell>1</cell>")
+    end
+  end
+
   context "when traversing elements" do
     let!(:local) { Y::Doc.new }
     let!(:local_xml) { local.get_xml_element("my xml") }
@@ -287,7 +333,7 @@ RSpec.describe Y::XMLElement do
       xml_element = local.get_xml_element("my xml element")
 
       called = nil
-      listener = proc { |changes| called = changes }
+      listener = proc { |event| called = event.delta }
 
       subscription_id = xml_element.attach(listener)
 
@@ -298,9 +344,9 @@ RSpec.describe Y::XMLElement do
 
       xml_element.detach(subscription_id)
 
-      expect(called.first[:added].size).to eq(2)
-      expect(called.first[:added].first.tag).to eq("A")
-      expect(called.first[:added].last.tag).to eq("B")
+      expect(called.first[:insert].size).to eq(2)
+      expect(called.first[:insert].first.tag).to eq("A")
+      expect(called.first[:insert].last.tag).to eq("B")
     end
 
     it "supports block as callback argument" do
@@ -309,8 +355,8 @@ RSpec.describe Y::XMLElement do
 
       called = nil
 
-      subscription_id = xml_element.attach do |changes|
-        called = changes
+      subscription_id = xml_element.attach do |event|
+        called = event.delta
       end
 
       local.transact do
@@ -320,9 +366,9 @@ RSpec.describe Y::XMLElement do
 
       xml_element.detach(subscription_id)
 
-      expect(called.first[:added].size).to eq(2)
-      expect(called.first[:added].first.tag).to eq("A")
-      expect(called.first[:added].last.tag).to eq("B")
+      expect(called.first[:insert].size).to eq(2)
+      expect(called.first[:insert].first.tag).to eq("A")
+      expect(called.first[:insert].last.tag).to eq("B")
     end
 
     it "commits automatically" do
@@ -333,7 +379,7 @@ RSpec.describe Y::XMLElement do
       changes = []
 
       xml_element = local.get_xml_element("root")
-      xml_element.attach(proc { |delta| changes << delta })
+      xml_element.attach(proc { |event| changes << event.delta })
 
       local.transact do
         xml_element << "A"
@@ -355,17 +401,38 @@ RSpec.describe Y::XMLElement do
       expect(changes.size).to eq(3)
 
       expect(changes[0].size).to eq(1)
-      expect(changes[0].first).to have_key(:added)
-      expect(changes[0].first[:added].map(&:tag)).to match_array(%w[A B C])
+      expect(changes[0].first).to have_key(:insert)
+      expect(changes[0].first[:insert].map(&:tag)).to match_array(%w[A B C])
 
       expect(changes[1].size).to eq(2)
       expect(changes[1].first).to eq({ retain: 1 })
-      expect(changes[1].last).to eq({ removed: 1 })
+      expect(changes[1].last).to eq({ delete: 1 })
 
       expect(changes[2].size).to eq(2)
       expect(changes[2].first).to eq({ retain: 1 })
-      expect(changes[2].last).to have_key(:added)
-      expect(changes[2].last[:added].first.tag).to eq("B")
+      expect(changes[2].last).to have_key(:insert)
+      expect(changes[2].last[:insert].first.tag).to eq("B")
+    end
+
+    it "reports attribute changes" do
+      local = Y::Doc.new
+      heading = local.get_xml_element("heading")
+      heading.set_attribute("level", "1")
+
+      changes = []
+      heading.attach { |event| changes << event.keys }
+
+      heading.set_attribute("level", "2")
+      local.transact { heading.set_attribute("id", "intro") }
+
+      expect(changes).to eq([
+                              { "level" => { action: :updated,
+                                             old_value: "1",
+                                             new_value: "2" } },
+                              { "id" => { action: :inserted,
+                                          old_value: nil,
+                                          new_value: "intro" } }
+                            ])
     end
   end
   # rubocop:enable RSpec/ExampleLength, RSpec/MultipleExpectations
@@ -205,4 +205,391 @@ RSpec.describe Y::XMLFragment do
       expect(tags).to eq(%w[heading paragraph paragraph])
     end
   end
+
+  context "when inserting markup" do
+    let(:doc) { Y::Doc.new }
+    let(:fragment) { doc.get_xml_fragment("default") }
+
+    it "creates elements, attributes and texts" do
+      nodes = fragment.insert_xml(0, <<~XML)
+        <heading level="1">Title</heading>
+        <paragraph>Hello <bold>World</bold></paragraph>
+      XML
+
+      expect(nodes.map(&:tag)).to eq(%w[heading paragraph])
+      expect(fragment[0].attrs).to eq({ "level" => "1" })
+      expect(fragment.to_s).to eq(
+        '<heading level="1">Title</heading>' \
+        "<paragraph>Hello <bold>World</bold></paragraph>"
+      )
+    end
+
+    it "inserts at the given index" do
+      fragment << "heading"
+      fragment << "paragraph"
+
+      fragment.insert_xml(1, "<blockquote/>")
+
+      expect(fragment.map(&:tag)).to eq(%w[heading blockquote paragraph])
+    end
+
+    it "decodes entities" do
+      fragment.insert_xml(0, "<paragraph>1 &lt; 2 &amp;&#x20;3</paragraph>")
+
+      expect(fragment[0].first_child.to_s).to eq("1 < 2 & 3")
+    end
+
+    it "keeps whitespace between inline elements" do
+      fragment.insert_xml(0, <<~XML)
+        <paragraph><bold>a</bold> <italic>b</italic></paragraph>
+        <paragraph>c</paragraph>
+      XML
+
+      expect(fragment.to_s).to eq(
+        "<paragraph><bold>a</bold> <italic>b</italic></paragraph>" \
+        "<paragraph>c</paragraph>"
+      )
+    end
+
+    it "skips end tags of void elements" do
+      markup = '<paragraph>a<br></br>b<img src="x.png"></img></paragraph>'
+
+      fragment.insert_xml(0, markup)
+
+      expect(fragment[0].size).to eq(4)
+      expect(fragment.to_s).to eq(markup)
+    end
+
+    it "reads unquoted attribute values up to whitespace or '>'" do
+      fragment.insert_xml(0, "<link href=/path/to title=x>Docs</link>")
+
+      expect(fragment[0].attrs).to eq({ "href" => "/path/to", "title" => "x" })
+    end
+
+    it "creates all nodes in a single transaction" do
+      events = []
+      fragment.observe_deep { |e| events << e }
+
+      fragment.insert_xml(0, "<paragraph>A</paragraph><paragraph>B</paragraph>")
+
+      expect(events.size).to eq(1)
+    end
+
+    it "raises for markup that is not well-formed" do
+      expect { fragment.insert_xml(0, "<paragraph>Hello</heading>") }
+        .to raise_error(Y::MarkupError, /unexpected closing tag/)
+      expect(fragment.size).to eq(0)
+    end
+  end
+
+  context "when converting to ProseMirror JSON" do
+    let(:doc) { Y::Doc.new }
+    let(:fragment) { doc.get_xml_fragment("default") }
+    let(:json) do
+      {
+        "type" => "doc",
+        "content" => [
+          {
+            "type" => "heading",
+            "attrs" => { "level" => 1, "id" => "intro" },
+            "content" => [{ "type" => "text", "text" => "Title" }]
+          },
+          {
+            "type" => "paragraph",
+            "marks" => [{ "type" => "highlight" }],
+            "content" => [
+              { "type" => "text", "text" => "Hello " },
+              {
+                "type" => "text",
+                "text" => "World",
+                "marks" => [
+                  { "type" => "bold" },
+                  { "type" => "link", "attrs" => { "href" => "https://y.rb" } }
+                ]
+              }
+            ]
+          },
+          { "type" => "horizontalRule" }
+        ]
+      }
+    end
+
+    it "converts an empty fragment" do
+      expect(fragment.to_prosemirror_json)
+        .to eq({ "type" => "doc", "content" => [] })
+    end
+
+    it "converts elements and formatted texts" do
+      paragraph = fragment << "paragraph"
+      paragraph.set_attribute("textAlign", "left")
+      text = paragraph.push_text("Hello World")
+      text.format(0, 5, { "bold" => true })
+
+      expect(fragment.to_prosemirror_json["content"]).to eq(
+        [
+          {
+            "type" => "paragraph",
+            "attrs" => { "textAlign" => "left" },
+            "content" => [
+              { "type" => "text", "text" => "Hello",
+                "marks" => [{ "type" => "bold" }] },
+              { "type" => "text", "text" => " World" }
+            ]
+          }
+        ]
+      )
+    end
+
+    it "strips the hash of overlapping marks" do
+      text = (fragment << "paragraph").push_text("Hi")
+      text.format(0, 2, { "comment--Ab3+x9Qz" => { "id" => "1" } })
+
+      node = fragment.to_prosemirror_json.dig("content", 0, "content", 0)
+
+      expect(node["marks"]).to eq([{ "type" => "comment",
+                                     "attrs" => { "id" => "1" } }])
+    end
+
+    it "round-trips a document" do
+      fragment.load_prosemirror_json(json)
+
+      expect(fragment.to_prosemirror_json).to eq(json)
+    end
+
+    it "keeps String attributes that look like JSON" do
+      paragraph = fragment << "paragraph"
+      paragraph.set_attribute("title", "42")
+
+      expect(fragment.to_prosemirror_json.dig("content", 0, "attrs"))
+        .to eq({ "title" => "42" })
+    end
+
+    it "stores non-String attributes and node marks as JSON Strings" do
+      # unlike y-prosemirror, as yrs only stores Strings as XML attributes
+      fragment.load_prosemirror_json(json)
+
+      expect(fragment[0].attrs).to eq(
+        { "level" => "1", "id" => "intro", "json-attrs" => '["level"]' }
+      )
+      expect(fragment[1].attrs).to eq(
+        { "marks" => '[{"type":"highlight"}]' }
+      )
+    end
+
+    it "stores adjacent text nodes in one XML text" do
+      fragment.load_prosemirror_json(json)
+
+      expect(fragment[1].size).to eq(1)
+      expect(fragment[1].first_child.to_s).to include("Hello ")
+    end
+
+    it "syncs loaded documents" do
+      fragment.load_prosemirror_json(json)
+
+      remote = Y::Doc.new
+      remote.sync(doc.diff)
+
+      expect(remote.get_xml_fragment("default").to_prosemirror_json)
+        .to eq(json)
+    end
+
+    it "raises for a non-empty fragment" do
+      fragment << "paragraph"
+
+      expect { fragment.load_prosemirror_json(json) }
+        .to raise_error(ArgumentError, /non-empty/)
+    end
+
+    it "raises with the path of an invalid node" do
+      invalid = { "type" => "doc", "content" => [{ "content" => [] }] }
+
+      expect { fragment.load_prosemirror_json(invalid) }
+        .to raise_error(ArgumentError, "missing node type at content/0")
+    end
+  end
+
+  context "when reconciling with a target tree" do
+    let(:doc) { Y::Doc.new }
+    let(:fragment) { doc.get_xml_fragment("default") }
+
+    def paragraphs(*texts)
+      {
+        type: "doc",
+        content: texts.map do |text|
+          { type: "paragraph", content: [{ type: "text", text: text }] }
+        end
+      }
+    end
+
+    before { fragment.load_prosemirror_json(paragraphs("a", "b", "c")) }
+
+    it "updates the fragment to match the target" do
+      fragment.reconcile(paragraphs("a", "b!", "d"))
+
+      expect(fragment.to_s).to eq(
+        "<paragraph>a</paragraph><paragraph>b!</paragraph>" \
+        "<paragraph>d</paragraph>"
+      )
+    end
+
+    it "does not change anything for an equal tree" do
+      events = []
+      fragment.observe_deep { |e| events.concat(e) }
+
+      fragment.reconcile(fragment.to_prosemirror_json)
+
+      expect(events).to be_empty
+    end
+
+    it "edits texts in place" do
+      events = []
+      fragment.observe_deep { |e| events.concat(e) }
+
+      fragment.reconcile(paragraphs("a", "b!", "c"))
+
+      expect(events.map(&:path)).to eq([[1, 0]])
+      expect(events.first.delta).to eq([{ retain: 1 }, { insert: "!" }])
+    end
+
+    it "updates attributes and marks" do
+      fragment.reconcile(
+        type: "doc",
+        content: [
+          { type: "heading", attrs: { level: 2 } },
+          { type: "paragraph",
+            content: [{ type: "text", text: "b", marks: [{ type: "bold" }] }] }
+        ]
+      )
+
+      expect(fragment.to_prosemirror_json["content"]).to eq(
+        [
+          { "type" => "heading", "attrs" => { "level" => 2 } },
+          { "type" => "paragraph",
+            "content" => [{ "type" => "text", "text" => "b",
+                            "marks" => [{ "type" => "bold" }] }] }
+        ]
+      )
+    end
+
+    it "keeps concurrent edits of unchanged nodes" do
+      remote = Y::Doc.new
+      remote.sync(doc.diff)
+      remote.get_xml_fragment("default")[2].first_child << "!"
+
+      fragment.reconcile(paragraphs("A", "b", "c"))
+      doc.sync(remote.diff(doc.state))
+
+      expect(fragment.to_s).to eq(
+        "<paragraph>A</paragraph><paragraph>b</paragraph>" \
+        "<paragraph>c!</paragraph>"
+      )
+    end
+  end
+
+  context "when querying descendants" do
+    let(:doc) { Y::Doc.new }
+    let(:fragment) { doc.get_xml_fragment("default") }
+
+    before do
+      fragment.insert_xml(0, <<~XML)
+        <bulletList>
+          <listItem checked="true"><paragraph>A</paragraph></listItem>
+          <listItem><paragraph>B</paragraph></listItem>
+        </bulletList>
+        <paragraph>C</paragraph>
+      XML
+    end
+
+    it "walks all descendants with depth and path" do
+      walked = fragment.each_descendant.map do |node, depth, path|
+        [node.is_a?(Y::XMLText) ? node.to_s : node.tag, depth, path]
+      end
+
+      expect(walked).to eq(
+        [["bulletList", 1, [0]], ["listItem", 2, [0, 0]],
+         ["paragraph", 3, [0, 0, 0]], ["A", 4, [0, 0, 0, 0]],
+         ["listItem", 2, [0, 1]], ["paragraph", 3, [0, 1, 0]],
+         ["B", 4, [0, 1, 0, 0]], ["paragraph", 1, [1]], ["C", 2, [1, 0]]]
+      )
+    end
+
+    it "finds elements by tag and attributes" do
+      expect(fragment.find_all("paragraph").map(&:to_s)).to eq(
+        %w[<paragraph>A</paragraph> <paragraph>B</paragraph>
+           <paragraph>C</paragraph>]
+      )
+      expect(fragment.find_all("listItem", checked: "true").size).to eq(1)
+      expect(fragment.find_all(checked: nil).map(&:tag)).to eq(%w[listItem])
+    end
+
+    it "keeps Enumerable#find_all for blocks" do
+      expect(fragment.find_all { |node| node.tag == "paragraph" }.size).to eq(1)
+    end
+
+    it "queries elements with a selector" do
+      nodes = fragment.query_selector_all("bulletList > listItem paragraph")
+
+      expect(nodes.map(&:to_s)).to eq(
+        %w[<paragraph>A</paragraph> <paragraph>B</paragraph>]
+      )
+      expect(fragment.query_selector("listItem[checked=true] > *").to_s)
+        .to eq("<paragraph>A</paragraph>")
+      expect(fragment.query_selector("bulletList > paragraph")).to be_nil
+    end
+
+    it "returns live nodes" do
+      paragraph = fragment.query_selector("listItem paragraph")
+      paragraph.push_text("!")
+
+      expect(paragraph.document).to eq(doc)
+      expect(fragment.find_all("paragraph").first.to_s)
+        .to eq("<paragraph>A!</paragraph>")
+    end
+
+    it "raises for invalid selectors" do
+      expect { fragment.query_selector_all("listItem >") }
+        .to raise_error(ArgumentError, /invalid selector/)
+    end
+  end
+
+  context "when observing changes" do
+    let(:doc) { Y::Doc.new }
+    let(:fragment) { doc.get_xml_fragment("default") }
+
+    it "reports changed children" do
+      fragment << "heading"
+
+      delta = nil
+      subscription_id = fragment.attach { |event| delta = event.delta }
+      fragment << "paragraph"
+      fragment.detach(subscription_id)
+
+      expect(delta.last[:insert].map(&:tag)).to eq(["paragraph"])
+    end
+
+    it "does not report changes of descendants to shallow listeners" do
+      paragraph = fragment << "paragraph"
+
+      called = false
+      fragment.attach { |_event| called = true }
+      paragraph.push_text("Hello")
+
+      expect(called).to be(false)
+    end
+
+    it "reports changes anywhere in the tree with paths" do
+      fragment << "heading"
+      paragraph = fragment << "paragraph"
+      text = paragraph.push_text("Hello")
+
+      events = nil
+      subscription_id = fragment.observe_deep { |e| events = e }
+      text << " World"
+      fragment.unobserve_deep(subscription_id)
+
+      expect(events.map(&:class)).to eq([Y::XMLEvent])
+      expect(events.first.path).to eq([1, 0])
+      expect(events.first.delta).to eq([{ retain: 5 }, { insert: " World" }])
+    end
+  end
 end
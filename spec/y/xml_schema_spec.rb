@@ -0,0 +1,111 @@
+# frozen_string_literal: true
+
+RSpec.describe Y::XMLSchema do
+  let(:doc) { Y::Doc.new }
+  let(:fragment) { doc.get_xml_fragment("default") }
+  let(:schema) do
+    described_class.new(
+      root: %w[paragraph bulletList],
+      nodes: {
+        paragraph: { children: %w[text], marks: %w[bold] },
+        bulletList: { children: %w[listItem] },
+        listItem: { children: %w[paragraph], attributes: %w[checked] }
+      }
+    )
+  end
+
+  it "accepts a valid fragment" do
+    fragment.insert_xml(0, <<~XML)
+      <paragraph>Hello</paragraph>
+      <bulletList><listItem checked="true"><paragraph/></listItem></bulletList>
+    XML
+
+    expect(fragment.validate(schema)).to eq([])
+  end
+
+  it "reports children that are not allowed with their path" do
+    fragment.insert_xml(0, "<paragraph/><listItem><paragraph/></listItem>")
+
+    expect(fragment.validate(schema)).to eq(
+      [{ path: [1], error: :invalid_child,
+         message: "listItem is not allowed in the root" }]
+    )
+  end
+
+  it "reports unknown nodes, attributes and marks" do
+    fragment.insert_xml(0, <<~XML)
+      <bulletList><listItem id="1"><image/></listItem></bulletList>
+    XML
+    text = (fragment << "paragraph").push_text("Hi")
+    text.format(0, 2, { "italic" => true })
+
+    expect(fragment.validate(schema).map { |v| [v[:path], v[:error]] }).to eq(
+      [[[0, 0], :invalid_attribute], [[0, 0, 0], :invalid_child],
+       [[0, 0, 0], :unknown_node], [[1, 0], :invalid_mark]]
+    )
+  end
+
+  context "when transacting" do
+    it "merges valid changes" do
+      schema.transact(doc, "default") do |draft|
+        draft.insert_xml(0, "<paragraph>Hello</paragraph>")
+      end
+
+      expect(fragment.to_s).to eq("<paragraph>Hello</paragraph>")
+    end
+
+    it "makes changes on a fork of the document" do
+      fragment.insert_xml(0, "<paragraph>Hello</paragraph>")
+      schema.transact(doc, "default") do |draft|
+        expect(draft.document).not_to equal(doc)
+        draft.insert_xml(1, "<paragraph>World</paragraph>")
+      end
+
+      expect(doc.stats[:clients].size).to eq(2)
+      expect(fragment.size).to eq(2)
+    end
+
+    it "rejects invalid changes" do
+      expect do
+        schema.transact(doc, "default") { |draft| draft.insert_xml(0, "<x/>") }
+      end.to raise_error(Y::SchemaViolationError) { |error|
+        expect(error.violations.map { |v| v[:error] })
+          .to eq(%i[invalid_child unknown_node])
+      }
+      expect(fragment.size).to eq(0)
+    end
+  end
+
+  context "when enforcing the schema" do
+    before { doc.enforce_schema("default", schema) }
+
+    it "keeps valid changes" do
+      fragment.insert_xml(0, "<paragraph>Hello</paragraph>")
+
+      expect(fragment.to_s).to eq("<paragraph>Hello</paragraph>")
+    end
+
+    it "undoes invalid changes of single operations" do
+      expect { fragment.insert_xml(0, "<x/>") }
+        .to raise_error(Y::SchemaViolationError)
+      expect(fragment.size).to eq(0)
+    end
+
+    it "undoes invalid changes of transactions with an origin" do
+      fragment.insert_xml(0, "<paragraph>Hello</paragraph>")
+
+      expect do
+        doc.transact_with("agent") { fragment.insert_xml(1, "<x/>") }
+      end.to raise_error(Y::SchemaViolationError)
+      expect(fragment.to_s).to eq("<paragraph>Hello</paragraph>")
+    end
+
+    it "undoes invalid changes of nested nodes" do
+      fragment.insert_xml(0, "<paragraph>Hello</paragraph>")
+
+      expect { fragment[0].replace_children_with_xml("<paragraph/>") }
+        .to raise_error(Y::SchemaViolationError)
+      expect(fragment.to_s).to eq("<paragraph>Hello</paragraph>")
+    end
+  end
+end
@@ -256,11 +256,25 @@ RSpec.describe Y::XMLText do
     xml_text = doc.get_xml_text("my xml text")
 
     changes = nil
-    xml_text.attach { |delta| changes = delta }
+    xml_text.attach { |event| changes = event.delta }
 
     xml_text << "Hello"
 
-    expect(changes).not_to be_nil
+    expect(changes).to eq([{ insert: "Hello" }])
+  end
+
+  it "reports attribute changes to observers" do
+    doc = Y::Doc.new
+    xml_text = doc.get_xml_text("my xml text")
+
+    keys = nil
+    xml_text.attach { |event| keys = event.keys }
+
+    xml_text.attr_color = "red"
+
+    expect(keys).to eq(
+      "color" => { action: :inserted, old_value: nil, new_value: "red" }
+    )
   end
 
   it "detaches observer" do
@@ -268,7 +282,7 @@ RSpec.describe Y::XMLText do
     xml_text = doc.get_xml_text("my xml text")
 
     count = 0
-    sub_id = xml_text.attach { |_delta| count += 1 }
+    sub_id = xml_text.attach { |_event| count += 1 }
 
     xml_text << "Hello"
     xml_text.detach(sub_id)